@@ -0,0 +1,19 @@
+-- Create user <-> permission group table
+-- Maintained by the permission system; each user belongs to at most one group here
+CREATE TABLE IF NOT EXISTS user_groups (
+    user_uuid UUID PRIMARY KEY,
+    group_name TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create group -> default skin table
+-- skin_hash refers to a SKIN file already present in the storage backend
+CREATE TABLE IF NOT EXISTS group_default_skins (
+    group_name TEXT PRIMARY KEY,
+    skin_hash TEXT NOT NULL,
+    metadata JSONB,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create index for faster lookups by skin hash
+CREATE INDEX IF NOT EXISTS idx_group_default_skins_hash ON group_default_skins(skin_hash);
@@ -0,0 +1,9 @@
+-- Create cape gallery table
+-- Named capes that admins can assign to users without uploading the bytes again
+-- cape_hash refers to a CAPE file already present in the storage backend
+CREATE TABLE IF NOT EXISTS cape_gallery (
+    name TEXT PRIMARY KEY,
+    cape_hash TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,6 @@
+-- Record the hash embedded in the textures.minecraft.net URL for textures pulled from Mojang
+-- It differs from file_hash (SHA256 of our bytes), and lets legacy clients download by it
+ALTER TABLE textures ADD COLUMN IF NOT EXISTS mojang_hash TEXT;
+
+-- Create index for lookups by Mojang hash
+CREATE INDEX IF NOT EXISTS idx_textures_mojang_hash ON textures(mojang_hash);
@@ -0,0 +1,4 @@
+-- Allow ELYTRA textures alongside skins and capes
+ALTER TABLE textures DROP CONSTRAINT IF EXISTS textures_texture_type_check;
+ALTER TABLE textures ADD CONSTRAINT textures_texture_type_check
+    CHECK (texture_type IN ('SKIN', 'CAPE', 'ELYTRA'));
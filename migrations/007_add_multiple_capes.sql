@@ -0,0 +1,13 @@
+-- Let users own several capes with one of them active
+-- Skins and elytras keep a single row per user, which is always active
+ALTER TABLE textures ADD COLUMN IF NOT EXISTS active BOOLEAN NOT NULL DEFAULT TRUE;
+
+DROP INDEX IF EXISTS idx_textures_unique_user_type;
+
+-- Owned capes are distinguished by their file
+CREATE UNIQUE INDEX IF NOT EXISTS idx_textures_unique_user_type_hash
+    ON textures(user_uuid, texture_type, file_hash);
+
+-- One texture per type per user for everything but capes
+CREATE UNIQUE INDEX IF NOT EXISTS idx_textures_unique_user_type
+    ON textures(user_uuid, texture_type) WHERE texture_type <> 'CAPE';
@@ -0,0 +1,7 @@
+-- Create upload blocks table
+-- Users listed here may not upload textures themselves (admin uploads still work)
+CREATE TABLE IF NOT EXISTS upload_blocks (
+    user_uuid UUID PRIMARY KEY,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
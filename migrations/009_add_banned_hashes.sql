@@ -0,0 +1,7 @@
+-- Create banned hashes table
+-- Textures with these hashes (known offensive images) can not be uploaded by anyone
+CREATE TABLE IF NOT EXISTS banned_hashes (
+    hash TEXT PRIMARY KEY,
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
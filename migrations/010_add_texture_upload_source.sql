@@ -0,0 +1,7 @@
+-- Record where a texture came from, for auditing (RECORD_UPLOAD_SOURCE)
+-- uploaded_by: the uploading user's UUID, or "admin" for the admin API
+-- source: how the texture was written (upload, admin_upload, mojang, gallery)
+-- original_filename: multipart filename of uploaded files
+ALTER TABLE textures ADD COLUMN IF NOT EXISTS uploaded_by TEXT;
+ALTER TABLE textures ADD COLUMN IF NOT EXISTS source TEXT;
+ALTER TABLE textures ADD COLUMN IF NOT EXISTS original_filename TEXT;
@@ -0,0 +1,5 @@
+-- Username as it was given, when username holds the lowercase form (CASE_INSENSITIVE_USERNAMES)
+ALTER TABLE username_mappings ADD COLUMN IF NOT EXISTS display_username TEXT;
+
+-- Index for case-insensitive lookups by username
+CREATE INDEX IF NOT EXISTS idx_username_mappings_username_lower ON username_mappings(LOWER(username));
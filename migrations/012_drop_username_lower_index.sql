@@ -0,0 +1,5 @@
+-- Lookups compare the stored form of a username exactly (lowercase with
+-- CASE_INSENSITIVE_USERNAMES), using idx_username_mappings_username instead
+-- Mappings stored before the option was enabled are lowercased by
+-- `texture-provider2 normalize-usernames`
+DROP INDEX IF EXISTS idx_username_mappings_username_lower;
@@ -1,9 +1,11 @@
 use std::sync::Arc;
 
-use crate::models::JwtClaims;
+use crate::config::{Config, JwtAlgorithm};
+use crate::jwks::JwksKeys;
 use anyhow::Result;
 use axum::http::{HeaderMap, StatusCode};
-use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
 use uuid::Uuid;
 
 /// Extract and validate JWT from Authorization header
@@ -22,27 +24,106 @@ pub fn extract_jwt(headers: &HeaderMap) -> Result<String> {
     Ok(token)
 }
 
-pub fn decode_key(public_key: &str) -> Result<DecodingKey> {
+/// Build the key of a base64 public key (a PEM body) for the configured algorithm
+/// Fails if the key is not of the type the algorithm needs, e.g. an EC key for RS256
+pub fn decode_key(public_key: &str, algorithm: JwtAlgorithm) -> Result<DecodingKey> {
     let key = format!(
         "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----",
         public_key
     );
-    DecodingKey::from_ec_pem(key.as_bytes())
-        .map_err(|e| anyhow::anyhow!("Failed to create decoding key: {}", e))
+    match algorithm {
+        JwtAlgorithm::Es256 => DecodingKey::from_ec_pem(key.as_bytes()).map_err(|e| {
+            anyhow::anyhow!("JWT_PUBLIC_KEY is not an EC public key as needed for ES256: {}", e)
+        }),
+        JwtAlgorithm::Rs256 => DecodingKey::from_rsa_pem(key.as_bytes()).map_err(|e| {
+            anyhow::anyhow!("JWT_PUBLIC_KEY is not an RSA public key as needed for RS256: {}", e)
+        }),
+    }
+}
+
+fn jsonwebtoken_algorithm(algorithm: JwtAlgorithm) -> Algorithm {
+    match algorithm {
+        JwtAlgorithm::Es256 => Algorithm::ES256,
+        JwtAlgorithm::Rs256 => Algorithm::RS256,
+    }
+}
+
+/// Where the keys verifying user JWTs come from
+enum VerificationKeys {
+    /// JWT_PUBLIC_KEY
+    Static(DecodingKey),
+    /// JWT_JWKS_URL, selected by the token's `kid` header
+    Jwks(JwksKeys),
+}
+
+/// Verifies user JWTs and reads the user UUID from them
+pub struct JwtVerifier {
+    keys: VerificationKeys,
+    algorithm: JwtAlgorithm,
+    /// Claim holding the user UUID (JWT_UUID_CLAIM)
+    uuid_claim: String,
 }
 
-/// Decode and validate JWT token, returning user UUID
-pub fn validate_jwt(token: &str, public_key: &DecodingKey) -> Result<Uuid> {
-    let mut validation = Validation::new(Algorithm::ES256);
+impl JwtVerifier {
+    /// Returns None if neither JWT_PUBLIC_KEY nor JWT_JWKS_URL is set, which disables
+    /// user JWT authentication; a key that is set but invalid is still an error
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let keys = match (&config.jwt_jwks_url, &config.jwt_public_key) {
+            (Some(url), _) => VerificationKeys::Jwks(JwksKeys::new(url.clone())),
+            (None, Some(public_key)) => {
+                VerificationKeys::Static(decode_key(public_key, config.jwt_algorithm)?)
+            }
+            (None, None) => return Ok(None),
+        };
+
+        Ok(Some(JwtVerifier {
+            keys,
+            algorithm: config.jwt_algorithm,
+            uuid_claim: config.jwt_uuid_claim.clone(),
+        }))
+    }
+
+    /// Decode and validate JWT token, returning user UUID
+    pub async fn validate(&self, token: &str) -> Result<Uuid> {
+        match &self.keys {
+            VerificationKeys::Static(key) => {
+                validate_jwt(token, key, self.algorithm, &self.uuid_claim)
+            }
+            VerificationKeys::Jwks(jwks) => {
+                let header = decode_header(token)
+                    .map_err(|e| anyhow::anyhow!("Invalid JWT token: {}", e))?;
+                let key = jwks.key(header.kid.as_deref()).await?;
+                validate_jwt(token, &key, self.algorithm, &self.uuid_claim)
+            }
+        }
+    }
+}
+
+/// Decode and validate JWT token, returning the UUID in the `uuid_claim` claim
+pub fn validate_jwt(
+    token: &str,
+    public_key: &DecodingKey,
+    algorithm: JwtAlgorithm,
+    uuid_claim: &str,
+) -> Result<Uuid> {
+    let mut validation = Validation::new(jsonwebtoken_algorithm(algorithm));
     validation.validate_exp = true;
 
-    let token_data = decode::<JwtClaims>(token, public_key, &validation)
+    let token_data = decode::<Map<String, Value>>(token, public_key, &validation)
         .map_err(|e| anyhow::anyhow!("Invalid JWT token: {}", e))?;
 
-    let uuid = Uuid::parse_str(&token_data.claims.uuid)
-        .map_err(|e| anyhow::anyhow!("Invalid UUID in token: {}", e))?;
+    uuid_from_claims(&token_data.claims, uuid_claim)
+}
+
+/// Read the user UUID from a claim, which must be a UUID string
+fn uuid_from_claims(claims: &Map<String, Value>, uuid_claim: &str) -> Result<Uuid> {
+    let uuid = claims
+        .get(uuid_claim)
+        .ok_or_else(|| anyhow::anyhow!("Missing {} claim in token", uuid_claim))?
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("The {} claim in token is not a string", uuid_claim))?;
 
-    Ok(uuid)
+    Uuid::parse_str(uuid).map_err(|e| anyhow::anyhow!("Invalid UUID in token: {}", e))
 }
 
 /// Extract user UUID from validated JWT
@@ -65,14 +146,15 @@ where
         parts: &mut axum::http::request::Parts,
         _state: &S,
     ) -> Result<Self, Self::Rejection> {
-        // Get public key from request extensions (set by middleware)
-        let public_key = parts
+        // Get JWT verifier from request extensions (set by middleware)
+        // The middleware leaves it out when no JWT key is configured
+        let verifier = parts
             .extensions
-            .get::<Arc<DecodingKey>>()
+            .get::<Arc<JwtVerifier>>()
             .ok_or_else(|| {
                 (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Public key not found in state".to_string(),
+                    StatusCode::UNAUTHORIZED,
+                    "JWT auth disabled".to_string(),
                 )
             })?
             .clone();
@@ -84,7 +166,7 @@ where
             )
         })?;
 
-        let uuid = validate_jwt(&token, &public_key).map_err(|e| {
+        let uuid = verifier.validate(&token).await.map_err(|e| {
             (
                 StatusCode::UNAUTHORIZED,
                 format!("Authentication failed: {}", e),
@@ -95,8 +177,26 @@ where
     }
 }
 
+/// Request extension set by the middleware when ADMIN_ALLOW_BASIC_AUTH is enabled
+#[derive(Clone, Copy)]
+pub struct AllowAdminBasicAuth;
+
+/// Extract the password from an HTTP Basic `Authorization` value (without the "Basic " prefix)
+/// The username is ignored
+fn basic_auth_password(credentials: &str) -> Option<String> {
+    use base64::Engine;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(credentials.trim())
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (_username, password) = decoded.split_once(':')?;
+    Some(password.to_string())
+}
+
 /// Extract admin token from Authorization header
 /// Marker struct to indicate admin authentication is required
+/// Accepts `Bearer ADMIN_TOKEN`, or HTTP Basic with ADMIN_TOKEN as the password
+/// if ADMIN_ALLOW_BASIC_AUTH is enabled
 pub struct AuthAdmin;
 
 impl std::fmt::Debug for AuthAdmin {
@@ -133,14 +233,25 @@ where
                 )
             })?;
 
-        if !auth_header.starts_with("Bearer ") {
+        let token = if let Some(token) = auth_header.strip_prefix("Bearer ") {
+            token.to_string()
+        } else if let (Some(credentials), Some(_)) = (
+            auth_header.strip_prefix("Basic "),
+            parts.extensions.get::<AllowAdminBasicAuth>(),
+        ) {
+            // Legacy tooling that can only send basic auth
+            basic_auth_password(credentials).ok_or_else(|| {
+                (
+                    StatusCode::UNAUTHORIZED,
+                    "Invalid basic authorization credentials".to_string(),
+                )
+            })?
+        } else {
             return Err((
                 StatusCode::UNAUTHORIZED,
                 "Invalid authorization header format".to_string(),
             ));
-        }
-
-        let token = auth_header[7..].to_string();
+        };
 
         // Get admin token from request extensions (set by middleware)
         let admin_token = parts
@@ -188,3 +299,48 @@ where
         Ok(AuthAdmin)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_auth_password_ignores_username() {
+        // "admin:secret:with:colons"
+        assert_eq!(
+            basic_auth_password("YWRtaW46c2VjcmV0OndpdGg6Y29sb25z").as_deref(),
+            Some("secret:with:colons")
+        );
+        // ":secret"
+        assert_eq!(basic_auth_password("OnNlY3JldA==").as_deref(), Some("secret"));
+        // "no-colon"
+        assert_eq!(basic_auth_password("bm8tY29sb24="), None);
+        assert_eq!(basic_auth_password("not base64!"), None);
+    }
+
+    #[test]
+    fn test_decode_key_rejects_algorithm_mismatch() {
+        let ec_key = "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE3FJp+rRX61iBSnjka4fOKrYqPFzu3Lly6AtJj5tDsydl00EyHblqq+0c4pJYOn8vo3z8YurZWWv4pTtWKwM/1g==";
+
+        assert!(decode_key(ec_key, JwtAlgorithm::Es256).is_ok());
+        let error = decode_key(ec_key, JwtAlgorithm::Rs256).err().unwrap();
+        assert!(error.to_string().contains("not an RSA public key"));
+    }
+
+    #[test]
+    fn test_uuid_from_configured_claim() {
+        let claims = serde_json::json!({
+            "sub": "069a79f4-44e9-4726-a5be-fca90e38aaf5",
+            "uuid": 42,
+            "exp": 1234567890
+        });
+        let claims = claims.as_object().unwrap();
+
+        assert_eq!(
+            uuid_from_claims(claims, "sub").unwrap(),
+            Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap()
+        );
+        assert!(uuid_from_claims(claims, "uuid").is_err());
+        assert!(uuid_from_claims(claims, "user").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::config::Config;
+use crate::retrieval::{MojangError, MojangRetriever};
+use anyhow::{anyhow, Result};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A progress line is logged every this many users
+const PROGRESS_INTERVAL: usize = 100;
+
+/// Outcome of `backfill_usernames`
+#[derive(Debug, Default, PartialEq)]
+pub struct BackfillReport {
+    pub updated: usize,
+    pub not_found: usize,
+    pub rate_limited: usize,
+    pub failed: usize,
+}
+
+/// `texture-provider2 backfill-usernames`: fill `username_mappings` with the current
+/// Mojang name of every user that has textures, e.g. after importing legacy data that
+/// only had UUIDs. Goes through the Mojang client, so a 429 pauses requests for its
+/// Retry-After; a user still rate limited after the pause, unknown to Mojang or failing
+/// otherwise is skipped and counted rather than aborting the run
+pub async fn backfill_usernames(
+    config: &Config,
+    db: &PgPool,
+    mojang: &MojangRetriever,
+) -> Result<BackfillReport> {
+    let user_uuids = sqlx::query_scalar!(
+        r#"
+        SELECT DISTINCT user_uuid
+        FROM textures
+        ORDER BY user_uuid
+        "#
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| anyhow!("Failed to list users with textures: {}", e))?;
+
+    let total = user_uuids.len();
+    tracing::info!(total, "Backfilling usernames from Mojang");
+
+    let mut report = BackfillReport::default();
+    for (index, user_uuid) in user_uuids.into_iter().enumerate() {
+        match fetch_username(mojang, user_uuid).await {
+            Ok(Some(username)) => match save_mapping(config, db, user_uuid, &username).await {
+                Ok(()) => report.updated += 1,
+                Err(e) => {
+                    tracing::error!(%user_uuid, "Failed to save username mapping: {}", e);
+                    report.failed += 1;
+                }
+            },
+            Ok(None) => {
+                tracing::debug!(%user_uuid, "No Mojang profile, skipping");
+                report.not_found += 1;
+            }
+            Err(e) if e.downcast_ref::<MojangError>().is_some() => {
+                tracing::warn!(%user_uuid, "Skipping user: {}", e);
+                report.rate_limited += 1;
+            }
+            Err(e) => {
+                tracing::warn!(%user_uuid, "Skipping user: {}", e);
+                report.failed += 1;
+            }
+        }
+
+        if (index + 1) % PROGRESS_INTERVAL == 0 || index + 1 == total {
+            tracing::info!(
+                done = index + 1,
+                total,
+                updated = report.updated,
+                not_found = report.not_found,
+                rate_limited = report.rate_limited,
+                failed = report.failed,
+                "Backfill progress"
+            );
+        }
+    }
+
+    Ok(report)
+}
+
+/// Current username of a user, waiting out one rate limit pause before giving up
+async fn fetch_username(mojang: &MojangRetriever, user_uuid: Uuid) -> Result<Option<String>> {
+    match mojang.fetch_username(user_uuid).await {
+        Err(e) => match e.downcast_ref::<MojangError>() {
+            Some(MojangError::RateLimited(pause)) => {
+                tracing::info!(pause_secs = pause.as_secs(), "Rate limited by Mojang, waiting");
+                tokio::time::sleep(*pause).await;
+                mojang.fetch_username(user_uuid).await
+            }
+            None => Err(e),
+        },
+        result => result,
+    }
+}
+
+/// Map a username to a user, taking it from any other user that held it before
+async fn save_mapping(
+    config: &Config,
+    db: &PgPool,
+    user_uuid: Uuid,
+    username: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM username_mappings
+        WHERE username = $1 AND user_uuid <> $2
+        "#,
+        config.username_key(username),
+        user_uuid
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO username_mappings (user_uuid, username, display_username, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (user_uuid, username)
+        DO UPDATE SET display_username = EXCLUDED.display_username, updated_at = NOW()
+        "#,
+        user_uuid,
+        config.username_key(username),
+        username
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+/// Outcome of `normalize_usernames`
+#[derive(Debug, Default, PartialEq)]
+pub struct NormalizeReport {
+    /// Mappings whose username was lowercased
+    pub normalized: u64,
+    /// Mappings dropped because a more recent one had the same name in another case
+    pub merged: u64,
+}
+
+/// `texture-provider2 normalize-usernames`: bring mappings stored before
+/// CASE_INSENSITIVE_USERNAMES was enabled to the lowercase form lookups compare against.
+/// Of mappings whose names only differ in case, the most recently updated one is kept, as
+/// if it had been the last to claim the name. The name as stored is kept as its display form
+pub async fn normalize_usernames(db: &PgPool) -> Result<NormalizeReport> {
+    let mut tx = db.begin().await?;
+    let merged = sqlx::query!(
+        r#"
+        WITH ranked AS (
+            SELECT user_uuid, username,
+                ROW_NUMBER() OVER (
+                    PARTITION BY LOWER(username)
+                    ORDER BY updated_at DESC, (username = LOWER(username)) DESC, user_uuid
+                ) AS rank
+            FROM username_mappings
+        )
+        DELETE FROM username_mappings m
+        USING ranked r
+        WHERE m.user_uuid = r.user_uuid AND m.username = r.username AND r.rank > 1
+        "#
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| anyhow!("Failed to merge duplicate usernames: {}", e))?
+    .rows_affected();
+    let normalized = sqlx::query!(
+        r#"
+        UPDATE username_mappings
+        SET username = LOWER(username), display_username = COALESCE(display_username, username)
+        WHERE username <> LOWER(username)
+        "#
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| anyhow!("Failed to lowercase usernames: {}", e))?
+    .rows_affected();
+    tx.commit().await?;
+
+    Ok(NormalizeReport { normalized, merged })
+}
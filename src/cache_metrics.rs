@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Counters of every cache layer by cache name, rendered by GET /metrics
+static REGISTRY: Mutex<BTreeMap<&'static str, Arc<CacheMetrics>>> = Mutex::new(BTreeMap::new());
+
+/// Hit and miss counters of one cache layer
+/// Caching components get theirs from `register` when they are built and count every
+/// lookup, so TTLs and sizes can be tuned from the ratio
+#[derive(Debug, Default)]
+pub struct CacheMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheMetrics {
+    /// Counters of the named cache, shared by every instance of that cache
+    pub fn register(cache: &'static str) -> Arc<Self> {
+        REGISTRY.lock().unwrap().entry(cache).or_default().clone()
+    }
+
+    /// Count a lookup answered from the cache
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a lookup the cache could not answer
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a lookup, a hit if `hit` is true
+    pub fn record(&self, hit: bool) {
+        if hit {
+            self.hit()
+        } else {
+            self.miss()
+        }
+    }
+}
+
+/// Every registered cache's counters in the Prometheus text format, as
+/// `cache_hits_total{cache="..."}` and `cache_misses_total{cache="..."}`
+pub fn render_prometheus() -> String {
+    let counts: Vec<(&str, u64, u64)> = REGISTRY
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(cache, metrics)| {
+            (
+                *cache,
+                metrics.hits.load(Ordering::Relaxed),
+                metrics.misses.load(Ordering::Relaxed),
+            )
+        })
+        .collect();
+
+    let mut output = String::new();
+    let _ = writeln!(output, "# HELP cache_hits_total Lookups answered from a cache");
+    let _ = writeln!(output, "# TYPE cache_hits_total counter");
+    for (cache, hits, _) in &counts {
+        let _ = writeln!(output, "cache_hits_total{{cache=\"{}\"}} {}", cache, hits);
+    }
+    let _ = writeln!(output, "# HELP cache_misses_total Lookups a cache could not answer");
+    let _ = writeln!(output, "# TYPE cache_misses_total counter");
+    for (cache, _, misses) in &counts {
+        let _ = writeln!(output, "cache_misses_total{{cache=\"{}\"}} {}", cache, misses);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instances_of_a_cache_share_counters() {
+        let first = CacheMetrics::register("test_shared");
+        let second = CacheMetrics::register("test_shared");
+        first.hit();
+        second.record(true);
+        second.miss();
+
+        let rendered = render_prometheus();
+        assert!(rendered.contains("# TYPE cache_hits_total counter\n"));
+        assert!(rendered.contains("cache_hits_total{cache=\"test_shared\"} 2\n"));
+        assert!(rendered.contains("cache_misses_total{cache=\"test_shared\"} 1\n"));
+    }
+}
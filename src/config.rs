@@ -1,32 +1,211 @@
+use crate::models::{SkinModel, TextureType};
 use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use uuid::Uuid;
 
-#[derive(Debug, Deserialize, Clone)]
+/// Hash of the official default Steve (classic) skin on textures.minecraft.net
+const DEFAULT_STEVE_HASH: &str = "1a4af718455d58aab3011401517e43cb6f84b5f9cbd717f8df0334e0b88b8ecf";
+/// Hash of the official default Alex (slim) skin on textures.minecraft.net
+const DEFAULT_ALEX_HASH: &str = "46acd06e8483b176e8ea39fc12fe105eb3a2a4970f5100057e9d84d4b60bdfa7";
+
+/// Default upload size limit (1 MB), plenty for vanilla-sized PNG textures
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 1_048_576;
+/// Default upload size limit with HD skins allowed (8 MB), enough for a 1024x1024 PNG
+const DEFAULT_MAX_UPLOAD_SIZE_HD: usize = 8_388_608;
+/// Default resource pack size limit (100 MB)
+const DEFAULT_MAX_PACK_SIZE: usize = 104_857_600;
+/// Default size limit of textures embedded with ?embed=true (64 KB), above vanilla textures
+const DEFAULT_MAX_EMBED_SIZE: usize = 65_536;
+
+/// Canned ACLs accepted by S3_OBJECT_ACL
+const S3_CANNED_ACLS: &[&str] = &[
+    "private",
+    "public-read",
+    "public-read-write",
+    "authenticated-read",
+    "aws-exec-read",
+    "bucket-owner-read",
+    "bucket-owner-full-control",
+];
+
+/// Storage classes accepted by S3_STORAGE_CLASS
+/// Archive classes (GLACIER, DEEP_ARCHIVE) are left out, their objects cannot be read
+/// without a restore
+const S3_STORAGE_CLASSES: &[&str] = &[
+    "STANDARD",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER_IR",
+    "REDUCED_REDUNDANCY",
+];
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct Config {
     pub database_url: String,
-    pub jwt_public_key: String,
+    /// Base64 public key verifying user JWTs, unless jwt_jwks_url is set
+    pub jwt_public_key: Option<String>,
+    /// JWKS endpoint of the identity provider; its keys are used instead of jwt_public_key
+    pub jwt_jwks_url: Option<String>,
+    /// Signing algorithm of user JWTs, which also decides the type of jwt_public_key
+    pub jwt_algorithm: JwtAlgorithm,
+    /// Claim holding the user UUID in JWTs, e.g. sub for OIDC tokens
+    pub jwt_uuid_claim: String,
+    /// Public URL of the service, including ROUTE_PREFIX
     pub base_url: String,
+    /// Path every route is mounted under, e.g. /textures
+    pub route_prefix: Option<String>,
     pub storage_type: StorageType,
+    pub storage_primary: Option<StorageType>,
+    pub storage_secondary: Option<StorageType>,
+    pub storage_migrate_on_read: bool,
+    /// Public host of stored files (e.g. a CDN they are synced to), used for file URLs
+    /// as `<base>/<hash>.<ext>` instead of the backend's own URLs
+    pub storage_public_base_url: Option<String>,
+    /// Store texture files under a prefix of their type, as `<type>/<hash>.<ext>`
+    pub storage_type_prefix: bool,
+    pub verify_hash_on_read: bool,
+    /// Serve the cape as the elytra when a user has no elytra texture
+    pub derive_elytra_from_cape: bool,
+    /// Also write texture metadata to storage as `<hash>.json`
+    pub store_metadata_sidecar: bool,
+    /// Record who uploaded each texture, how and under which filename
+    pub record_upload_source: bool,
     pub retrieval_type: RetrievalType,
     pub retrieval_chain: Option<Vec<RetrievalType>>,
+    /// Chains used for skins and capes instead of RETRIEVAL_CHAIN
+    pub retrieval_chain_skin: Option<Vec<RetrievalType>>,
+    pub retrieval_chain_cape: Option<Vec<RetrievalType>>,
+    pub chain_mode: ChainMode,
+    /// Alternative texture type names, keyed by uppercase alias
+    pub texture_type_aliases: HashMap<String, TextureType>,
+    /// Type whose texture is served when a user has none of another, e.g. ELYTRA -> CAPE
+    pub type_fallback: HashMap<TextureType, TextureType>,
     pub local_storage_path: Option<String>,
+    /// Compression of files written to local storage
+    pub local_compress: LocalCompression,
     pub s3_bucket: Option<String>,
     pub s3_region: Option<String>,
     pub s3_endpoint: Option<String>,
     pub s3_access_key: Option<String>,
     pub s3_secret_key: Option<String>,
+    /// Canned ACL applied to uploaded objects, e.g. public-read
+    pub s3_object_acl: Option<String>,
+    /// Cache-Control metadata set on uploaded objects
+    pub s3_cache_control: Option<String>,
+    /// Storage class of uploaded objects, STANDARD if not set
+    pub s3_storage_class: Option<String>,
+    pub storage_max_retries: u32,
+    pub storage_retry_base_delay_ms: u64,
     pub server_port: u16,
+    /// PEM certificate chain and private key; the server speaks HTTPS when both are set
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    /// Plain HTTP port redirecting every request to HTTPS (requires TLS)
+    pub http_redirect_port: Option<u16>,
+    pub max_concurrent_requests: usize,
+    /// Start in read-only maintenance mode, refusing writes with 503
+    pub read_only: bool,
+    /// Decompress upload request bodies sent with Content-Encoding: gzip
+    pub accept_gzip_uploads: bool,
+    /// Spill uploads larger than upload_memory_limit to temporary files
+    pub stream_uploads_to_disk: bool,
+    /// Largest upload kept in memory with stream_uploads_to_disk
+    pub upload_memory_limit: usize,
+    /// Directory of spilled uploads, the system temporary directory if not set
+    pub upload_temp_dir: Option<String>,
+    /// Upper bound on the time to produce a response, for everything but uploads
+    pub request_timeout_secs: u64,
+    /// Requests taking at least this long are logged as slow (0 disables)
+    pub slow_request_threshold_ms: u64,
+    /// Upper bound on the time to receive and handle an upload
+    pub upload_timeout_secs: u64,
+    pub textures_signing_key: Option<String>,
     pub admin_token: Option<String>,
+    pub admin_allow_basic_auth: bool,
+    /// Require a user JWT (or the admin token) on the /get, /download and /files endpoints
+    pub require_auth_for_reads: bool,
+    /// Secret for HMAC-signed /download/:hash URLs; downloads are unsigned if not set
+    pub download_signing_secret: Option<String>,
+    /// Lifetime of the URLs signed by /api/sign/:hash
+    pub download_url_ttl_seconds: u64,
     pub username_cache_seconds: u64,
     pub hash_cache_seconds: u64,
+    /// Cache lifetime of /files/{hash} responses, sent as immutable
+    pub file_cache_seconds: u64,
+    pub json_cache_seconds: u64,
+    pub json_cache_seconds_volatile: u64,
     pub use_database_username_in_mojang_requests: bool,
+    /// Match usernames case-insensitively and store them lowercase in username_mappings
+    pub case_insensitive_usernames: bool,
     pub cors_allowed_origins: Option<String>,
+    pub log_format: LogFormat,
+    pub default_skin_steve_url: String,
+    pub default_skin_steve_hash: String,
+    pub default_skin_alex_url: String,
+    pub default_skin_alex_hash: String,
+    pub default_skin_model_by_uuid: bool,
+    pub serve_default_on_miss: bool,
+    pub allow_hd_capes: bool,
+    pub allow_hd_skins: bool,
+    /// Force the base layer of uploaded skins to full alpha before hashing
+    pub normalize_skin_alpha: bool,
+    /// Drop text and other ancillary chunks from uploaded PNGs before hashing
+    pub strip_png_text_chunks: bool,
+    /// Refuse uploads whose hash is already stored as another texture type (409)
+    pub reject_hash_type_conflicts: bool,
+    pub max_upload_size: usize,
+    /// Size limit of resource packs uploaded to /api/pack
+    pub max_pack_size: usize,
+    /// Largest texture embedded in /get/{uuid}/{type}?embed=true responses
+    pub max_embed_size: usize,
+    pub default_cape_url: Option<String>,
+    pub default_on_miss_cache_seconds: u64,
+    /// Download the default textures at startup instead of on first use
+    pub preload_defaults: bool,
+    /// How long a lookup that found no texture is remembered (0 disables)
+    pub negative_cache_seconds: u64,
+    /// Consecutive Mojang failures after which Mojang is skipped for a while (0 disables)
+    pub mojang_circuit_breaker_threshold: u32,
+    /// How long Mojang is skipped once the circuit breaker opens
+    pub mojang_circuit_breaker_cooldown_seconds: u64,
+    /// URL template of the http_api retriever, with {uuid}, {username} and {type} placeholders
+    pub http_retriever_url: Option<String>,
+    /// JSON pointer to the textures in http_api responses (empty for the whole body)
+    pub http_retriever_textures_pointer: String,
+    pub http_retriever_timeout_secs: u64,
+    /// Proxy of outbound HTTP requests, instead of HTTP_PROXY/HTTPS_PROXY
+    pub outbound_proxy_url: Option<String>,
+    pub outbound_min_tls_version: Option<MinTlsVersion>,
+    /// Directory of the filesystem retriever, holding `<uuid>/<type>.png` files
+    pub filesystem_retriever_path: Option<String>,
+    /// Skin served to every user by the static retriever, a file or a URL
+    pub static_skin_path: Option<String>,
+    pub static_skin_url: Option<String>,
+    pub static_skin_model: Option<SkinModel>,
+    /// Whether users may upload textures themselves; admin uploads work either way
+    pub uploads_enabled: bool,
+    /// Users who may not upload textures themselves, on top of the upload_blocks table
+    pub upload_denylist: HashSet<Uuid>,
+    /// Webhook POSTed every upload for moderation
+    pub moderation_webhook_url: Option<String>,
+    /// Wait for the webhook and refuse uploads it does not answer with 2xx
+    pub moderation_webhook_sync: bool,
+    pub moderation_webhook_timeout_secs: u64,
+    /// Service asked to approve every upload before it is stored
+    pub moderation_check_url: Option<String>,
+    /// Accept uploads when the moderation check fails instead of refusing them
+    pub moderation_check_fail_open: bool,
+    pub moderation_check_timeout_secs: u64,
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
 pub enum StorageType {
     Local,
     S3,
+    /// Writes to STORAGE_PRIMARY, reads fall back to STORAGE_SECONDARY
+    Migrating,
 }
 
 impl std::str::FromStr for StorageType {
@@ -36,6 +215,7 @@ impl std::str::FromStr for StorageType {
         match s.to_lowercase().as_str() {
             "local" => Ok(StorageType::Local),
             "s3" => Ok(StorageType::S3),
+            "migrating" => Ok(StorageType::Migrating),
             _ => Err(anyhow::anyhow!("Invalid storage type: {}", s)),
         }
     }
@@ -46,6 +226,10 @@ pub enum RetrievalType {
     Storage,
     Mojang,
     DefaultSkin,
+    GroupDefaultSkin,
+    HttpApi,
+    Filesystem,
+    Static,
 }
 
 impl std::str::FromStr for RetrievalType {
@@ -56,72 +240,617 @@ impl std::str::FromStr for RetrievalType {
             "storage" => Ok(RetrievalType::Storage),
             "mojang" => Ok(RetrievalType::Mojang),
             "default_skin" => Ok(RetrievalType::DefaultSkin),
+            "group_default_skin" => Ok(RetrievalType::GroupDefaultSkin),
+            "http_api" => Ok(RetrievalType::HttpApi),
+            "filesystem" => Ok(RetrievalType::Filesystem),
+            "static" => Ok(RetrievalType::Static),
             _ => Err(anyhow::anyhow!("Invalid retrieval type: {}", s)),
         }
     }
 }
 
+/// How a retrieval chain queries its handlers (CHAIN_MODE)
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum ChainMode {
+    /// One handler after another, in priority order
+    Sequential,
+    /// All eligible handlers at once; the first to find the texture wins
+    Race,
+}
+
+impl std::str::FromStr for ChainMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sequential" | "" => Ok(ChainMode::Sequential),
+            "race" => Ok(ChainMode::Race),
+            _ => Err(anyhow::anyhow!("Invalid chain mode: {}", s)),
+        }
+    }
+}
+
+/// Compression applied by local storage (LOCAL_COMPRESS)
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum LocalCompression {
+    None,
+    /// Files are stored as `<hash>.<extension>.zst`
+    Zstd,
+}
+
+impl std::str::FromStr for LocalCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" | "" => Ok(LocalCompression::None),
+            "zstd" => Ok(LocalCompression::Zstd),
+            _ => Err(anyhow::anyhow!("Invalid local compression: {}", s)),
+        }
+    }
+}
+
+/// Signing algorithm of user JWTs (JWT_ALGORITHM)
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum JwtAlgorithm {
+    /// ECDSA P-256, as issued by LaunchServer
+    Es256,
+    /// RSA PKCS#1 v1.5 with SHA-256
+    Rs256,
+}
+
+impl std::str::FromStr for JwtAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "es256" | "" => Ok(JwtAlgorithm::Es256),
+            "rs256" => Ok(JwtAlgorithm::Rs256),
+            _ => Err(anyhow::anyhow!("Invalid JWT algorithm: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub enum LogFormat {
+    Pretty,
+    Compact,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "compact" => Ok(LogFormat::Compact),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!("Invalid log format: {}", s)),
+        }
+    }
+}
+
+/// Lowest TLS version accepted on outbound HTTPS connections (OUTBOUND_MIN_TLS_VERSION)
+/// The native TLS backend cannot require TLS 1.3, so 1.2 is the highest minimum
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum MinTlsVersion {
+    Tls10,
+    Tls11,
+    Tls12,
+}
+
+impl std::str::FromStr for MinTlsVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.0" => Ok(MinTlsVersion::Tls10),
+            "1.1" => Ok(MinTlsVersion::Tls11),
+            "1.2" => Ok(MinTlsVersion::Tls12),
+            _ => Err(anyhow::anyhow!(
+                "Invalid TLS version: {}. Valid versions are 1.0, 1.1 and 1.2",
+                s
+            )),
+        }
+    }
+}
+
 impl Config {
     pub fn from_env() -> Result<Self, anyhow::Error> {
-        // Parse retrieval_chain from comma-separated list if provided
-        let retrieval_chain = env::var("RETRIEVAL_CHAIN")
+        Self::from_vars(&process_env())
+    }
+
+    /// Read the configuration again for /api/reload: the process environment with the
+    /// values of the .env file on top, as `dotenvy::dotenv_override` would set them
+    /// The process environment itself is left alone, other threads may be reading it
+    pub fn from_env_and_dotenv() -> Result<Self, anyhow::Error> {
+        let mut vars = process_env();
+        if let Ok(dotenv) = dotenvy::dotenv_iter() {
+            for item in dotenv {
+                let (key, value) =
+                    item.map_err(|e| anyhow::anyhow!("Invalid .env file: {}", e))?;
+                vars.insert(key, value);
+            }
+        }
+        Self::from_vars(&vars)
+    }
+
+    /// Build the configuration from environment variables given as a map
+    pub fn from_vars(vars: &HashMap<String, String>) -> Result<Self, anyhow::Error> {
+        // Parse retrieval chains from comma-separated lists if provided
+        let retrieval_chain = parse_retrieval_chain(vars, "RETRIEVAL_CHAIN")?;
+        let retrieval_chain_skin = parse_retrieval_chain(vars, "RETRIEVAL_CHAIN_SKIN")?;
+        let retrieval_chain_cape = parse_retrieval_chain(vars, "RETRIEVAL_CHAIN_CAPE")?;
+
+        let upload_denylist = env_var(vars, "UPLOAD_DENYLIST")
+            .map(|list| parse_uuid_list(&list))
+            .unwrap_or_else(|_| Ok(HashSet::new()))
+            .map_err(|e| anyhow::anyhow!("Invalid UPLOAD_DENYLIST: {}", e))?;
+
+        let texture_type_aliases = env_var(vars, "TEXTURE_TYPE_ALIASES")
+            .map(|aliases| parse_texture_type_aliases(&aliases))
+            .unwrap_or_else(|_| Ok(HashMap::new()))?;
+
+        let type_fallback = env_var(vars, "TYPE_FALLBACK")
+            .map(|fallbacks| parse_type_fallback(&fallbacks))
+            .unwrap_or_else(|_| Ok(HashMap::new()))?;
+
+        // HD skins raise the default upload limit, so parse the flag first
+        let allow_hd_skins = env_var(vars, "ALLOW_HD_SKINS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid ALLOW_HD_SKINS: {}", e))?;
+        let max_upload_size = match env_var(vars, "MAX_UPLOAD_SIZE") {
+            Ok(size) => size
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid MAX_UPLOAD_SIZE: {}", e))?,
+            Err(_) if allow_hd_skins => DEFAULT_MAX_UPLOAD_SIZE_HD,
+            Err(_) => DEFAULT_MAX_UPLOAD_SIZE,
+        };
+
+        // Generated URLs must point inside the prefix the routes are mounted under
+        let route_prefix = env_var(vars, "ROUTE_PREFIX")
             .ok()
-            .map(|chain_str| {
-                chain_str
-                    .split(',')
-                    .map(|s| s.trim().parse::<RetrievalType>())
-                    .collect::<Result<Vec<_>, _>>()
-            })
-            .transpose()?;
+            .and_then(|prefix| normalize_route_prefix(&prefix));
+        let base_url = env_var(vars, "BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let base_url = match &route_prefix {
+            Some(prefix) => format!("{}{}", base_url.trim_end_matches('/'), prefix),
+            None => base_url,
+        };
 
         Ok(Config {
-            database_url: env::var("DATABASE_URL")
+            database_url: env_var(vars, "DATABASE_URL")
                 .map_err(|_| anyhow::anyhow!("DATABASE_URL must be set"))?,
-            jwt_public_key: env::var("JWT_PUBLIC_KEY")
-                .map_err(|_| anyhow::anyhow!("JWT_PUBLIC_KEY must be set"))?,
-            base_url: env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string()),
-            storage_type: env::var("STORAGE_TYPE")
+            jwt_public_key: env_var(vars, "JWT_PUBLIC_KEY").ok(),
+            jwt_jwks_url: env_var(vars, "JWT_JWKS_URL").ok(),
+            jwt_algorithm: env_var(vars, "JWT_ALGORITHM")
+                .unwrap_or_else(|_| "es256".to_string())
+                .parse()?,
+            jwt_uuid_claim: env_var(vars, "JWT_UUID_CLAIM")
+                .ok()
+                .filter(|claim| !claim.is_empty())
+                .unwrap_or_else(|| "uuid".to_string()),
+            base_url,
+            route_prefix,
+            storage_type: env_var(vars, "STORAGE_TYPE")
                 .unwrap_or_else(|_| "local".to_string())
                 .parse()?,
-            retrieval_type: env::var("RETRIEVAL_TYPE")
+            storage_primary: env_var(vars, "STORAGE_PRIMARY")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?,
+            storage_secondary: env_var(vars, "STORAGE_SECONDARY")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?,
+            storage_migrate_on_read: env_var(vars, "STORAGE_MIGRATE_ON_READ")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid STORAGE_MIGRATE_ON_READ: {}", e))?,
+            storage_public_base_url: env_var(vars, "STORAGE_PUBLIC_BASE_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            storage_type_prefix: env_var(vars, "STORAGE_TYPE_PREFIX")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid STORAGE_TYPE_PREFIX: {}", e))?,
+            verify_hash_on_read: env_var(vars, "VERIFY_HASH_ON_READ")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid VERIFY_HASH_ON_READ: {}", e))?,
+            derive_elytra_from_cape: env_var(vars, "DERIVE_ELYTRA_FROM_CAPE")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid DERIVE_ELYTRA_FROM_CAPE: {}", e))?,
+            store_metadata_sidecar: env_var(vars, "STORE_METADATA_SIDECAR")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid STORE_METADATA_SIDECAR: {}", e))?,
+            record_upload_source: env_var(vars, "RECORD_UPLOAD_SOURCE")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid RECORD_UPLOAD_SOURCE: {}", e))?,
+            retrieval_type: env_var(vars, "RETRIEVAL_TYPE")
                 .unwrap_or_else(|_| "storage".to_string())
                 .parse()?,
             retrieval_chain,
-            local_storage_path: env::var("LOCAL_STORAGE_PATH").ok(),
-            s3_bucket: env::var("S3_BUCKET").ok(),
-            s3_region: env::var("S3_REGION").ok(),
-            s3_endpoint: env::var("S3_ENDPOINT").ok(),
-            s3_access_key: env::var("S3_ACCESS_KEY").ok(),
-            s3_secret_key: env::var("S3_SECRET_KEY").ok(),
-            server_port: env::var("SERVER_PORT")
+            retrieval_chain_skin,
+            retrieval_chain_cape,
+            chain_mode: env_var(vars, "CHAIN_MODE")
+                .unwrap_or_else(|_| "sequential".to_string())
+                .parse()?,
+            texture_type_aliases,
+            type_fallback,
+            local_storage_path: env_var(vars, "LOCAL_STORAGE_PATH").ok(),
+            local_compress: env_var(vars, "LOCAL_COMPRESS")
+                .unwrap_or_else(|_| "none".to_string())
+                .parse()?,
+            s3_bucket: env_var(vars, "S3_BUCKET").ok(),
+            s3_region: env_var(vars, "S3_REGION").ok(),
+            s3_endpoint: env_var(vars, "S3_ENDPOINT").ok(),
+            s3_access_key: env_var(vars, "S3_ACCESS_KEY").ok(),
+            s3_secret_key: env_var(vars, "S3_SECRET_KEY").ok(),
+            s3_object_acl: env_var(vars, "S3_OBJECT_ACL").ok(),
+            s3_cache_control: env_var(vars, "S3_CACHE_CONTROL").ok(),
+            s3_storage_class: env_var(vars, "S3_STORAGE_CLASS").ok(),
+            storage_max_retries: env_var(vars, "STORAGE_MAX_RETRIES")
+                .unwrap_or_else(|_| "3".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid STORAGE_MAX_RETRIES: {}", e))?,
+            storage_retry_base_delay_ms: env_var(vars, "STORAGE_RETRY_BASE_DELAY_MS")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid STORAGE_RETRY_BASE_DELAY_MS: {}", e))?,
+            server_port: env_var(vars, "SERVER_PORT")
                 .unwrap_or_else(|_| "3000".to_string())
                 .parse()
                 .map_err(|e| anyhow::anyhow!("Invalid SERVER_PORT: {}", e))?,
-            admin_token: env::var("ADMIN_TOKEN").ok(),
-            username_cache_seconds: env::var("USERNAME_CACHE_SECONDS")
+            tls_cert_path: env_var(vars, "TLS_CERT_PATH").ok().filter(|path| !path.is_empty()),
+            tls_key_path: env_var(vars, "TLS_KEY_PATH").ok().filter(|path| !path.is_empty()),
+            http_redirect_port: env_var(vars, "HTTP_REDIRECT_PORT")
+                .ok()
+                .filter(|port| !port.is_empty())
+                .map(|port| port.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("Invalid HTTP_REDIRECT_PORT: {}", e))?,
+            accept_gzip_uploads: env_var(vars, "ACCEPT_GZIP_UPLOADS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid ACCEPT_GZIP_UPLOADS: {}", e))?,
+            stream_uploads_to_disk: env_var(vars, "STREAM_UPLOADS_TO_DISK")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid STREAM_UPLOADS_TO_DISK: {}", e))?,
+            upload_memory_limit: env_var(vars, "UPLOAD_MEMORY_LIMIT")
+                .unwrap_or_else(|_| "262144".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid UPLOAD_MEMORY_LIMIT: {}", e))?,
+            upload_temp_dir: env_var(vars, "UPLOAD_TEMP_DIR").ok().filter(|dir| !dir.is_empty()),
+            read_only: env_var(vars, "READ_ONLY")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid READ_ONLY: {}", e))?,
+            max_concurrent_requests: env_var(vars, "MAX_CONCURRENT_REQUESTS")
+                .unwrap_or_else(|_| "256".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid MAX_CONCURRENT_REQUESTS: {}", e))?,
+            request_timeout_secs: env_var(vars, "REQUEST_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid REQUEST_TIMEOUT_SECS: {}", e))?,
+            slow_request_threshold_ms: env_var(vars, "SLOW_REQUEST_THRESHOLD_MS")
+                .unwrap_or_else(|_| "1000".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid SLOW_REQUEST_THRESHOLD_MS: {}", e))?,
+            upload_timeout_secs: env_var(vars, "UPLOAD_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid UPLOAD_TIMEOUT_SECS: {}", e))?,
+            textures_signing_key: env_var(vars, "TEXTURES_SIGNING_KEY").ok(),
+            admin_token: env_var(vars, "ADMIN_TOKEN").ok(),
+            admin_allow_basic_auth: env_var(vars, "ADMIN_ALLOW_BASIC_AUTH")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid ADMIN_ALLOW_BASIC_AUTH: {}", e))?,
+            require_auth_for_reads: env_var(vars, "REQUIRE_AUTH_FOR_READS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid REQUIRE_AUTH_FOR_READS: {}", e))?,
+            download_signing_secret: env_var(vars, "DOWNLOAD_SIGNING_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            download_url_ttl_seconds: env_var(vars, "DOWNLOAD_URL_TTL_SECONDS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid DOWNLOAD_URL_TTL_SECONDS: {}", e))?,
+            username_cache_seconds: env_var(vars, "USERNAME_CACHE_SECONDS")
                 .unwrap_or_else(|_| "28800".to_string()) // 8 hours default
                 .parse()
                 .map_err(|e| anyhow::anyhow!("Invalid USERNAME_CACHE_SECONDS: {}", e))?,
-            hash_cache_seconds: env::var("HASH_CACHE_SECONDS")
+            hash_cache_seconds: env_var(vars, "HASH_CACHE_SECONDS")
                 .unwrap_or_else(|_| "1209600".to_string()) // 14 days default
                 .parse()
                 .map_err(|e| anyhow::anyhow!("Invalid HASH_CACHE_SECONDS: {}", e))?,
-            use_database_username_in_mojang_requests: env::var("USE_DATABASE_USERNAME_IN_MOJANG_REQUESTS")
+            file_cache_seconds: env_var(vars, "FILE_CACHE_SECONDS")
+                .unwrap_or_else(|_| "31536000".to_string()) // 1 year default
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid FILE_CACHE_SECONDS: {}", e))?,
+            json_cache_seconds: env_var(vars, "JSON_CACHE_SECONDS")
+                .unwrap_or_else(|_| "300".to_string()) // 5 minutes default
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid JSON_CACHE_SECONDS: {}", e))?,
+            json_cache_seconds_volatile: env_var(vars, "JSON_CACHE_SECONDS_VOLATILE")
+                .unwrap_or_else(|_| "60".to_string()) // 1 minute default
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid JSON_CACHE_SECONDS_VOLATILE: {}", e))?,
+            use_database_username_in_mojang_requests: env_var(vars, "USE_DATABASE_USERNAME_IN_MOJANG_REQUESTS")
                 .unwrap_or_else(|_| "true".to_string()) // 14 days default
                 .parse()
                 .map_err(|e| anyhow::anyhow!("Invalid USE_DATABASE_USERNAME_IN_MOJANG_REQUESTS: {}", e))?,
-            cors_allowed_origins: env::var("CORS_ALLOWED_ORIGINS").ok(),
+            case_insensitive_usernames: env_var(vars, "CASE_INSENSITIVE_USERNAMES")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid CASE_INSENSITIVE_USERNAMES: {}", e))?,
+            cors_allowed_origins: env_var(vars, "CORS_ALLOWED_ORIGINS").ok(),
+            log_format: env_var(vars, "LOG_FORMAT")
+                .unwrap_or_else(|_| "pretty".to_string())
+                .parse()?,
+            default_skin_steve_url: env_var(vars, "DEFAULT_SKIN_STEVE_URL").unwrap_or_else(|_| {
+                format!("https://textures.minecraft.net/texture/{}", DEFAULT_STEVE_HASH)
+            }),
+            default_skin_steve_hash: env_var(vars, "DEFAULT_SKIN_STEVE_HASH")
+                .unwrap_or_else(|_| DEFAULT_STEVE_HASH.to_string()),
+            default_skin_alex_url: env_var(vars, "DEFAULT_SKIN_ALEX_URL").unwrap_or_else(|_| {
+                format!("https://textures.minecraft.net/texture/{}", DEFAULT_ALEX_HASH)
+            }),
+            default_skin_alex_hash: env_var(vars, "DEFAULT_SKIN_ALEX_HASH")
+                .unwrap_or_else(|_| DEFAULT_ALEX_HASH.to_string()),
+            default_skin_model_by_uuid: env_var(vars, "DEFAULT_SKIN_MODEL_BY_UUID")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid DEFAULT_SKIN_MODEL_BY_UUID: {}", e))?,
+            serve_default_on_miss: env_var(vars, "SERVE_DEFAULT_ON_MISS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid SERVE_DEFAULT_ON_MISS: {}", e))?,
+            allow_hd_capes: env_var(vars, "ALLOW_HD_CAPES")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid ALLOW_HD_CAPES: {}", e))?,
+            allow_hd_skins,
+            normalize_skin_alpha: env_var(vars, "NORMALIZE_SKIN_ALPHA")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid NORMALIZE_SKIN_ALPHA: {}", e))?,
+            strip_png_text_chunks: env_var(vars, "STRIP_PNG_TEXT_CHUNKS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid STRIP_PNG_TEXT_CHUNKS: {}", e))?,
+            reject_hash_type_conflicts: env_var(vars, "REJECT_HASH_TYPE_CONFLICTS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid REJECT_HASH_TYPE_CONFLICTS: {}", e))?,
+            max_upload_size,
+            max_pack_size: env_var(vars, "MAX_PACK_SIZE")
+                .map(|size| size.parse())
+                .unwrap_or(Ok(DEFAULT_MAX_PACK_SIZE))
+                .map_err(|e| anyhow::anyhow!("Invalid MAX_PACK_SIZE: {}", e))?,
+            max_embed_size: env_var(vars, "MAX_EMBED_SIZE")
+                .map(|size| size.parse())
+                .unwrap_or(Ok(DEFAULT_MAX_EMBED_SIZE))
+                .map_err(|e| anyhow::anyhow!("Invalid MAX_EMBED_SIZE: {}", e))?,
+            default_cape_url: env_var(vars, "DEFAULT_CAPE_URL").ok(),
+            default_on_miss_cache_seconds: env_var(vars, "DEFAULT_ON_MISS_CACHE_SECONDS")
+                .unwrap_or_else(|_| "300".to_string()) // 5 minutes default
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid DEFAULT_ON_MISS_CACHE_SECONDS: {}", e))?,
+            preload_defaults: env_var(vars, "PRELOAD_DEFAULTS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid PRELOAD_DEFAULTS: {}", e))?,
+            negative_cache_seconds: env_var(vars, "NEGATIVE_CACHE_SECONDS")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid NEGATIVE_CACHE_SECONDS: {}", e))?,
+            mojang_circuit_breaker_threshold: env_var(vars, "MOJANG_CIRCUIT_BREAKER_THRESHOLD")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid MOJANG_CIRCUIT_BREAKER_THRESHOLD: {}", e))?,
+            mojang_circuit_breaker_cooldown_seconds: env_var(
+                vars,
+                "MOJANG_CIRCUIT_BREAKER_COOLDOWN_SECONDS",
+            )
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .map_err(|e| {
+                anyhow::anyhow!("Invalid MOJANG_CIRCUIT_BREAKER_COOLDOWN_SECONDS: {}", e)
+            })?,
+            http_retriever_url: env_var(vars, "HTTP_RETRIEVER_URL").ok(),
+            http_retriever_textures_pointer: env_var(vars, "HTTP_RETRIEVER_TEXTURES_POINTER")
+                .unwrap_or_default(),
+            http_retriever_timeout_secs: env_var(vars, "HTTP_RETRIEVER_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid HTTP_RETRIEVER_TIMEOUT_SECS: {}", e))?,
+            outbound_proxy_url: env_var(vars, "OUTBOUND_PROXY_URL").ok(),
+            outbound_min_tls_version: env_var(vars, "OUTBOUND_MIN_TLS_VERSION")
+                .ok()
+                .map(|version| version.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("Invalid OUTBOUND_MIN_TLS_VERSION: {}", e))?,
+            filesystem_retriever_path: env_var(vars, "FILESYSTEM_RETRIEVER_PATH").ok(),
+            static_skin_path: env_var(vars, "STATIC_SKIN_PATH").ok(),
+            static_skin_url: env_var(vars, "STATIC_SKIN_URL").ok(),
+            static_skin_model: env_var(vars, "STATIC_SKIN_MODEL")
+                .ok()
+                .map(|model| match model.to_lowercase().as_str() {
+                    "classic" => Ok(SkinModel::Classic),
+                    "slim" => Ok(SkinModel::Slim),
+                    _ => Err(anyhow::anyhow!(
+                        "Invalid STATIC_SKIN_MODEL: {}. Valid models are classic and slim",
+                        model
+                    )),
+                })
+                .transpose()?,
+            uploads_enabled: env_var(vars, "UPLOADS_ENABLED")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid UPLOADS_ENABLED: {}", e))?,
+            upload_denylist,
+            moderation_webhook_url: env_var(vars, "MODERATION_WEBHOOK_URL").ok(),
+            moderation_webhook_sync: env_var(vars, "MODERATION_WEBHOOK_SYNC")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid MODERATION_WEBHOOK_SYNC: {}", e))?,
+            moderation_webhook_timeout_secs: env_var(vars, "MODERATION_WEBHOOK_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid MODERATION_WEBHOOK_TIMEOUT_SECS: {}", e))?,
+            moderation_check_url: env_var(vars, "MODERATION_CHECK_URL").ok(),
+            moderation_check_fail_open: env_var(vars, "MODERATION_CHECK_FAIL_OPEN")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid MODERATION_CHECK_FAIL_OPEN: {}", e))?,
+            moderation_check_timeout_secs: env_var(vars, "MODERATION_CHECK_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid MODERATION_CHECK_TIMEOUT_SECS: {}", e))?,
         })
     }
 
+    /// Form of a username stored in and looked up from username_mappings.username:
+    /// lowercase with CASE_INSENSITIVE_USERNAMES, as given otherwise
+    pub fn username_key(&self, username: &str) -> String {
+        username_key(username, self.case_insensitive_usernames)
+    }
+
+    /// Chain configured for one texture type (RETRIEVAL_CHAIN_SKIN, RETRIEVAL_CHAIN_CAPE)
+    /// None when the type uses the global retriever
+    pub fn retrieval_chain_for(&self, texture_type: TextureType) -> Option<&Vec<RetrievalType>> {
+        match texture_type {
+            TextureType::SKIN => self.retrieval_chain_skin.as_ref(),
+            TextureType::CAPE => self.retrieval_chain_cape.as_ref(),
+            TextureType::ELYTRA => None,
+        }
+    }
+
+    /// Whether a retriever of this type is created, as RETRIEVAL_TYPE or in any chain
+    fn uses_retrieval_type(&self, retrieval_type: RetrievalType) -> bool {
+        self.retrieval_type == retrieval_type
+            || [
+                &self.retrieval_chain,
+                &self.retrieval_chain_skin,
+                &self.retrieval_chain_cape,
+            ]
+            .into_iter()
+            .flatten()
+            .any(|chain| chain.contains(&retrieval_type))
+    }
+
     pub fn validate(&self) -> Result<(), anyhow::Error> {
-        if self.storage_type == StorageType::Local {
+        if self.max_concurrent_requests == 0 {
+            return Err(anyhow::anyhow!("MAX_CONCURRENT_REQUESTS must be at least 1"));
+        }
+
+        if self.request_timeout_secs == 0 || self.upload_timeout_secs == 0 {
+            return Err(anyhow::anyhow!(
+                "REQUEST_TIMEOUT_SECS and UPLOAD_TIMEOUT_SECS must be at least 1"
+            ));
+        }
+
+        if self.max_upload_size == 0 {
+            return Err(anyhow::anyhow!("MAX_UPLOAD_SIZE must be at least 1"));
+        }
+
+        if self.tls_cert_path.is_some() != self.tls_key_path.is_some() {
+            return Err(anyhow::anyhow!(
+                "TLS_CERT_PATH and TLS_KEY_PATH must be set together"
+            ));
+        }
+
+        if let Some(redirect_port) = self.http_redirect_port {
+            if self.tls_cert_path.is_none() {
+                return Err(anyhow::anyhow!(
+                    "HTTP_REDIRECT_PORT requires TLS_CERT_PATH and TLS_KEY_PATH"
+                ));
+            }
+            if redirect_port == self.server_port {
+                return Err(anyhow::anyhow!(
+                    "HTTP_REDIRECT_PORT must differ from SERVER_PORT"
+                ));
+            }
+        }
+
+        check_type_fallback_cycles(&self.type_fallback)?;
+
+        let uses_http_api = self.uses_retrieval_type(RetrievalType::HttpApi);
+        if uses_http_api {
+            match &self.http_retriever_url {
+                Some(url) if url.contains("{uuid}") || url.contains("{username}") => {}
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "HTTP_RETRIEVER_URL must be set, with a {{uuid}} or {{username}} placeholder, for the http_api retriever"
+                    ))
+                }
+            }
+            let pointer = &self.http_retriever_textures_pointer;
+            if !pointer.is_empty() && !pointer.starts_with('/') {
+                return Err(anyhow::anyhow!(
+                    "HTTP_RETRIEVER_TEXTURES_POINTER must be empty or start with /"
+                ));
+            }
+        }
+
+        let uses_filesystem = self.uses_retrieval_type(RetrievalType::Filesystem);
+        if uses_filesystem && self.filesystem_retriever_path.is_none() {
+            return Err(anyhow::anyhow!(
+                "FILESYSTEM_RETRIEVER_PATH must be set for the filesystem retriever"
+            ));
+        }
+
+        let uses_static = self.uses_retrieval_type(RetrievalType::Static);
+        if uses_static && self.static_skin_path.is_some() == self.static_skin_url.is_some() {
+            return Err(anyhow::anyhow!(
+                "Exactly one of STATIC_SKIN_PATH and STATIC_SKIN_URL must be set for the static retriever"
+            ));
+        }
+
+        if self.storage_type == StorageType::Migrating {
+            let (primary, secondary) = match (&self.storage_primary, &self.storage_secondary) {
+                (Some(primary), Some(secondary)) => (primary, secondary),
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "STORAGE_PRIMARY and STORAGE_SECONDARY must be set for migrating storage"
+                    ))
+                }
+            };
+            if *primary == StorageType::Migrating || *secondary == StorageType::Migrating {
+                return Err(anyhow::anyhow!(
+                    "STORAGE_PRIMARY and STORAGE_SECONDARY must be local or s3"
+                ));
+            }
+            if primary == secondary {
+                return Err(anyhow::anyhow!(
+                    "STORAGE_PRIMARY and STORAGE_SECONDARY must be different storage types"
+                ));
+            }
+            self.validate_storage(primary)?;
+            self.validate_storage(secondary)
+        } else {
+            self.validate_storage(&self.storage_type)
+        }
+    }
+
+    /// Check the settings required by a single (local or S3) storage backend
+    fn validate_storage(&self, storage_type: &StorageType) -> Result<(), anyhow::Error> {
+        if *storage_type == StorageType::Local {
             if self.local_storage_path.is_none() {
                 return Err(anyhow::anyhow!(
                     "LOCAL_STORAGE_PATH must be set for local storage"
                 ));
             }
-        } else if self.storage_type == StorageType::S3 {
+        } else if *storage_type == StorageType::S3 {
             if self.s3_bucket.is_none() {
                 return Err(anyhow::anyhow!("S3_BUCKET must be set for S3 storage"));
             }
@@ -135,7 +864,267 @@ impl Config {
                     "Both S3_ACCESS_KEY and S3_SECRET_KEY must be set together, or both omitted to use IAM roles"
                 ));
             }
+            if let Some(acl) = &self.s3_object_acl {
+                if !S3_CANNED_ACLS.contains(&acl.as_str()) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid S3_OBJECT_ACL: {}. Valid ACLs are: {}",
+                        acl,
+                        S3_CANNED_ACLS.join(", ")
+                    ));
+                }
+            }
+            if let Some(storage_class) = &self.s3_storage_class {
+                if !S3_STORAGE_CLASSES.contains(&storage_class.as_str()) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid S3_STORAGE_CLASS: {}. Valid storage classes are: {}",
+                        storage_class,
+                        S3_STORAGE_CLASSES.join(", ")
+                    ));
+                }
+            }
         }
         Ok(())
     }
+
+    /// Take the settings that can change without a restart from a freshly read configuration
+    /// (/api/reload), returning the environment variables whose values changed
+    /// Everything else, e.g. DATABASE_URL or STORAGE_TYPE, is set up once at startup and kept
+    pub fn apply_reloadable(&mut self, reloaded: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+
+        macro_rules! reload {
+            ($($field:ident => $var:literal),* $(,)?) => {
+                $(
+                    if self.$field != reloaded.$field {
+                        self.$field = reloaded.$field.clone();
+                        changed.push($var);
+                    }
+                )*
+            };
+        }
+
+        reload! {
+            username_cache_seconds => "USERNAME_CACHE_SECONDS",
+            hash_cache_seconds => "HASH_CACHE_SECONDS",
+            file_cache_seconds => "FILE_CACHE_SECONDS",
+            json_cache_seconds => "JSON_CACHE_SECONDS",
+            json_cache_seconds_volatile => "JSON_CACHE_SECONDS_VOLATILE",
+            default_on_miss_cache_seconds => "DEFAULT_ON_MISS_CACHE_SECONDS",
+            default_skin_steve_url => "DEFAULT_SKIN_STEVE_URL",
+            default_skin_steve_hash => "DEFAULT_SKIN_STEVE_HASH",
+            default_skin_alex_url => "DEFAULT_SKIN_ALEX_URL",
+            default_skin_alex_hash => "DEFAULT_SKIN_ALEX_HASH",
+            default_skin_model_by_uuid => "DEFAULT_SKIN_MODEL_BY_UUID",
+            default_cape_url => "DEFAULT_CAPE_URL",
+            allow_hd_capes => "ALLOW_HD_CAPES",
+            allow_hd_skins => "ALLOW_HD_SKINS",
+            normalize_skin_alpha => "NORMALIZE_SKIN_ALPHA",
+            strip_png_text_chunks => "STRIP_PNG_TEXT_CHUNKS",
+            reject_hash_type_conflicts => "REJECT_HASH_TYPE_CONFLICTS",
+            record_upload_source => "RECORD_UPLOAD_SOURCE",
+            uploads_enabled => "UPLOADS_ENABLED",
+            upload_denylist => "UPLOAD_DENYLIST",
+            stream_uploads_to_disk => "STREAM_UPLOADS_TO_DISK",
+            upload_memory_limit => "UPLOAD_MEMORY_LIMIT",
+            upload_temp_dir => "UPLOAD_TEMP_DIR",
+            slow_request_threshold_ms => "SLOW_REQUEST_THRESHOLD_MS",
+        }
+
+        changed
+    }
+}
+
+/// The process environment, without variables that are not valid UTF-8
+/// (which `std::env::var` would report as not set either)
+fn process_env() -> HashMap<String, String> {
+    env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .collect()
+}
+
+/// Value of an environment variable in `vars`, like `std::env::var`
+fn env_var(vars: &HashMap<String, String>, name: &str) -> Result<String, env::VarError> {
+    vars.get(name).cloned().ok_or(env::VarError::NotPresent)
+}
+
+/// Normalize ROUTE_PREFIX to a leading slash and no trailing slash, e.g. `textures/` -> `/textures`
+/// Returns None for an empty prefix, which keeps the routes at the root
+fn normalize_route_prefix(prefix: &str) -> Option<String> {
+    let prefix = prefix.trim().trim_matches('/');
+    (!prefix.is_empty()).then(|| format!("/{}", prefix))
+}
+
+/// Parse a comma-separated list of UUIDs, ignoring empty entries
+/// Parse a comma-separated list of retrieval types from an environment variable
+fn parse_retrieval_chain(
+    vars: &HashMap<String, String>,
+    var: &str,
+) -> Result<Option<Vec<RetrievalType>>, anyhow::Error> {
+    env_var(vars, var)
+        .ok()
+        .map(|chain_str| {
+            chain_str
+                .split(',')
+                .map(|s| s.trim().parse::<RetrievalType>())
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()
+}
+
+fn parse_uuid_list(list: &str) -> Result<HashSet<Uuid>, uuid::Error> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(Uuid::parse_str)
+        .collect()
+}
+
+/// Parse comma-separated `alias=CANONICAL` pairs, e.g. `body=SKIN,cloak=CAPE`
+/// Aliases are case-insensitive and may not shadow a canonical type name
+fn parse_texture_type_aliases(
+    aliases: &str,
+) -> Result<HashMap<String, TextureType>, anyhow::Error> {
+    let mut map = HashMap::new();
+
+    for pair in aliases.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let (alias, canonical) = pair.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!("Invalid TEXTURE_TYPE_ALIASES entry '{}', expected alias=TYPE", pair)
+        })?;
+        let alias = alias.trim().to_uppercase();
+
+        if alias.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Empty alias in TEXTURE_TYPE_ALIASES entry '{}'",
+                pair
+            ));
+        }
+        if TextureType::all_types().contains(&alias.as_str()) {
+            return Err(anyhow::anyhow!(
+                "TEXTURE_TYPE_ALIASES cannot redefine the texture type {}",
+                alias
+            ));
+        }
+
+        let canonical = canonical.trim().parse::<TextureType>().map_err(|e| {
+            anyhow::anyhow!("Invalid TEXTURE_TYPE_ALIASES entry '{}': {}", pair, e)
+        })?;
+        map.insert(alias, canonical);
+    }
+
+    Ok(map)
+}
+
+/// Parse comma-separated `TYPE:FALLBACK` pairs, e.g. `ELYTRA:CAPE`
+fn parse_type_fallback(
+    fallbacks: &str,
+) -> Result<HashMap<TextureType, TextureType>, anyhow::Error> {
+    let mut map = HashMap::new();
+
+    for pair in fallbacks.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let (texture_type, fallback) = pair.split_once(':').ok_or_else(|| {
+            anyhow::anyhow!("Invalid TYPE_FALLBACK entry '{}', expected TYPE:FALLBACK", pair)
+        })?;
+        let parse = |name: &str| {
+            name.trim().parse::<TextureType>().map_err(|e| {
+                anyhow::anyhow!("Invalid TYPE_FALLBACK entry '{}': {}", pair, e)
+            })
+        };
+        if map.insert(parse(texture_type)?, parse(fallback)?).is_some() {
+            return Err(anyhow::anyhow!(
+                "TYPE_FALLBACK lists {} more than once",
+                texture_type.trim()
+            ));
+        }
+    }
+
+    Ok(map)
+}
+
+/// Make sure following TYPE_FALLBACK from any type ends, so lookups cannot loop
+fn check_type_fallback_cycles(
+    fallbacks: &HashMap<TextureType, TextureType>,
+) -> Result<(), anyhow::Error> {
+    for start in fallbacks.keys() {
+        let mut seen = vec![*start];
+        let mut current = *start;
+        while let Some(next) = fallbacks.get(&current) {
+            if seen.contains(next) {
+                return Err(anyhow::anyhow!(
+                    "TYPE_FALLBACK has a cycle through {}",
+                    next
+                ));
+            }
+            seen.push(*next);
+            current = *next;
+        }
+    }
+
+    Ok(())
+}
+
+/// Form of a username stored in username_mappings.username, see `Config::username_key`
+/// Lookups compare it exactly, so they can use the index on the column
+pub fn username_key(username: &str, case_insensitive: bool) -> String {
+    if case_insensitive {
+        username.to_lowercase()
+    } else {
+        username.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vars_reads_the_given_map() {
+        let vars = HashMap::from([
+            ("DATABASE_URL".to_string(), "postgres://localhost/textures".to_string()),
+            ("ALLOW_HD_SKINS".to_string(), "true".to_string()),
+            ("ROUTE_PREFIX".to_string(), "textures/".to_string()),
+        ]);
+        let config = Config::from_vars(&vars).unwrap();
+        assert_eq!(config.database_url, "postgres://localhost/textures");
+        assert!(config.allow_hd_skins);
+        assert_eq!(config.max_upload_size, DEFAULT_MAX_UPLOAD_SIZE_HD);
+        assert_eq!(config.base_url, "http://localhost:3000/textures");
+
+        assert!(Config::from_vars(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_type_fallback_rejects_cycles() {
+        let fallbacks = parse_type_fallback("ELYTRA:CAPE, cape:skin").unwrap();
+        assert_eq!(fallbacks.get(&TextureType::ELYTRA), Some(&TextureType::CAPE));
+        assert!(check_type_fallback_cycles(&fallbacks).is_ok());
+
+        assert!(parse_type_fallback("ELYTRA").is_err());
+        assert!(parse_type_fallback("ELYTRA:HAT").is_err());
+        assert!(parse_type_fallback("ELYTRA:CAPE,ELYTRA:SKIN").is_err());
+
+        let cycle = parse_type_fallback("ELYTRA:CAPE,CAPE:SKIN,SKIN:ELYTRA").unwrap();
+        assert!(check_type_fallback_cycles(&cycle).is_err());
+        let self_loop = parse_type_fallback("CAPE:CAPE").unwrap();
+        assert!(check_type_fallback_cycles(&self_loop).is_err());
+    }
+
+    #[test]
+    fn test_parse_texture_type_aliases() {
+        let aliases = parse_texture_type_aliases("body=SKIN, cloak=cape").unwrap();
+        assert_eq!(aliases.get("BODY"), Some(&TextureType::SKIN));
+        assert_eq!(aliases.get("CLOAK"), Some(&TextureType::CAPE));
+
+        assert!(parse_texture_type_aliases("body").is_err());
+        assert!(parse_texture_type_aliases("body=HAT").is_err());
+        assert!(parse_texture_type_aliases("skin=CAPE").is_err());
+    }
+
+    #[test]
+    fn test_normalize_route_prefix() {
+        assert_eq!(normalize_route_prefix("textures"), Some("/textures".to_string()));
+        assert_eq!(normalize_route_prefix("/textures/"), Some("/textures".to_string()));
+        assert_eq!(normalize_route_prefix("/a/b"), Some("/a/b".to_string()));
+        assert_eq!(normalize_route_prefix("/"), None);
+        assert_eq!(normalize_route_prefix(""), None);
+    }
 }
@@ -0,0 +1,179 @@
+use crate::config::Config;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs and verifies expiring texture download URLs (DOWNLOAD_SIGNING_SECRET)
+/// A URL is valid until `exp` (unix seconds) if `sig` is the hex HMAC-SHA256 of
+/// `<path>:<exp>`, keyed with the secret. The path is that of the URL below BASE_URL,
+/// e.g. `/download/<hash>` or `/files/<hash>.png`, so a signature is good for one route only
+pub struct DownloadSigner {
+    secret: Vec<u8>,
+    ttl_seconds: i64,
+}
+
+impl DownloadSigner {
+    /// Create from configuration, or None if downloads are not signed
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config
+            .download_signing_secret
+            .as_ref()
+            .map(|secret| DownloadSigner {
+                secret: secret.as_bytes().to_vec(),
+                ttl_seconds: config.download_url_ttl_seconds.max(1) as i64,
+            })
+    }
+
+    /// DOWNLOAD_URL_TTL_SECONDS, the shortest lifetime of a URL signed here
+    pub fn ttl_seconds(&self) -> u64 {
+        self.ttl_seconds as u64
+    }
+
+    fn mac(&self, path: &str, exp: i64) -> HmacSha256 {
+        // HMAC accepts keys of any length
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC key of any size");
+        mac.update(format!("{}:{}", path, exp).as_bytes());
+        mac
+    }
+
+    /// Signature of a path valid until `exp`
+    pub fn sign(&self, path: &str, exp: i64) -> String {
+        hex::encode(self.mac(path, exp).finalize().into_bytes())
+    }
+
+    /// Check a signature in constant time, and that `exp` has not passed
+    pub fn verify(&self, path: &str, exp: i64, sig: &str, now: i64) -> bool {
+        if exp <= now {
+            return false;
+        }
+        match hex::decode(sig) {
+            Ok(sig) => self.mac(path, exp).verify_slice(&sig).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Build a signed `/download/:hash` URL valid for DOWNLOAD_URL_TTL_SECONDS
+    /// Returns the URL and its expiry (unix seconds)
+    pub fn signed_url(&self, base_url: &str, hash: &str, now: i64) -> (String, i64) {
+        let exp = now + self.ttl_seconds;
+        let path = format!("/download/{}", hash);
+        let url = format!(
+            "{}{}?exp={}&sig={}",
+            base_url.trim_end_matches('/'),
+            path,
+            exp,
+            self.sign(&path, exp)
+        );
+        (url, exp)
+    }
+
+    /// Sign a URL handed out in an API response, if it points at this service
+    /// URLs of other hosts (Mojang, S3, ...) are returned as they are. The expiry is
+    /// rounded up to a multiple of the TTL, at least one TTL ahead, so a texture keeps
+    /// the same URL (and responses their ETag) for a whole TTL window
+    pub fn sign_url(&self, base_url: &str, url: &str, now: i64) -> String {
+        let Some(path) = url
+            .strip_prefix(base_url.trim_end_matches('/'))
+            .filter(|path| path.starts_with('/'))
+        else {
+            return url.to_string();
+        };
+        let (path, separator) = match path.split_once('?') {
+            Some((path, _)) => (path, '&'),
+            None => (path, '?'),
+        };
+
+        let exp = (now / self.ttl_seconds + 2) * self.ttl_seconds;
+        format!(
+            "{}{}exp={}&sig={}",
+            url,
+            separator,
+            exp,
+            self.sign(path, exp)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> DownloadSigner {
+        DownloadSigner {
+            secret: b"secret".to_vec(),
+            ttl_seconds: 60,
+        }
+    }
+
+    #[test]
+    fn test_signatures_are_bound_to_path_and_expiry() {
+        let signer = signer();
+        let now = 1_700_000_000;
+        let sig = signer.sign("/download/abc", now + 60);
+
+        assert!(signer.verify("/download/abc", now + 60, &sig, now));
+        assert!(!signer.verify("/download/abd", now + 60, &sig, now));
+        assert!(!signer.verify("/files/abc", now + 60, &sig, now));
+        assert!(!signer.verify("/download/abc", now + 61, &sig, now));
+        assert!(!signer.verify("/download/abc", now + 60, &sig, now + 60));
+        assert!(!signer.verify("/download/abc", now + 60, "not-hex", now));
+        // The separator keeps hash and expiry apart
+        assert_ne!(
+            signer.sign("/download/abc1", 23),
+            signer.sign("/download/abc", 123)
+        );
+
+        let (url, exp) = signer.signed_url("http://localhost/", "abc", now);
+        assert_eq!(exp, now + 60);
+        assert_eq!(
+            url,
+            format!("http://localhost/download/abc?exp={}&sig={}", exp, sig)
+        );
+    }
+
+    #[test]
+    fn test_sign_url_signs_only_urls_of_this_service() {
+        let signer = signer();
+        let now = 1_700_000_010;
+        let exp = 1_700_000_100;
+
+        assert_eq!(
+            signer.sign_url(
+                "http://localhost/textures",
+                "http://localhost/textures/files/abc.png",
+                now
+            ),
+            format!(
+                "http://localhost/textures/files/abc.png?exp={}&sig={}",
+                exp,
+                signer.sign("/files/abc.png", exp)
+            )
+        );
+        // Stable within the TTL window
+        assert_eq!(
+            signer.sign_url("http://localhost", "http://localhost/download/abc", now),
+            signer.sign_url(
+                "http://localhost",
+                "http://localhost/download/abc",
+                now + 29
+            )
+        );
+        assert_eq!(
+            signer.sign_url(
+                "http://localhost",
+                "http://textures.minecraft.net/texture/abc",
+                now
+            ),
+            "http://textures.minecraft.net/texture/abc"
+        );
+        assert_eq!(
+            signer.sign_url(
+                "http://localhost",
+                "http://localhost.evil/download/abc",
+                now
+            ),
+            "http://localhost.evil/download/abc"
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::models::ErrorResponse;
+use axum::body::Bytes;
+use axum::extract::path::ErrorKind;
+use axum::extract::rejection::PathRejection;
+use axum::extract::{FromRequest, FromRequestParts, Path, Request};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::de::DeserializeOwned;
+
+/// Path extractor for routes with UUID parameters
+/// Works like `Path<T>`, but a malformed UUID is rejected with a JSON `ErrorResponse`
+/// and 400 instead of axum's plain text rejection.
+/// Only UUIDs are deserialized from the path (other parameters are taken as strings),
+/// so any deserialization failure is reported as an invalid UUID.
+pub struct UuidPath<T>(pub T);
+
+#[axum::async_trait]
+impl<S, T> FromRequestParts<S> for UuidPath<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        match Path::<T>::from_request_parts(parts, state).await {
+            Ok(Path(value)) => Ok(UuidPath(value)),
+            Err(rejection) => Err(path_rejection_response(rejection)),
+        }
+    }
+}
+
+/// Turn a path rejection into our JSON error body
+fn path_rejection_response(rejection: PathRejection) -> (StatusCode, Json<ErrorResponse>) {
+    let (status, error) = match rejection {
+        PathRejection::FailedToDeserializePathParams(e) => {
+            let detail = match e.into_kind() {
+                ErrorKind::ParseErrorAtKey { value, .. }
+                | ErrorKind::ParseErrorAtIndex { value, .. }
+                | ErrorKind::ParseError { value, .. } => value,
+                kind => kind.to_string(),
+            };
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid UUID in path: {}", detail),
+            )
+        }
+        rejection => (rejection.status(), rejection.body_text()),
+    };
+
+    (status, Json(ErrorResponse { error }))
+}
+
+/// Body extractor for routes whose JSON body may be left out
+/// An empty body gives `None`. Unlike `Option<Json<T>>`, a body that is present but is
+/// not valid JSON for `T` is rejected with a JSON `ErrorResponse` and 400 instead of
+/// being ignored. The Content-Type is not checked.
+pub struct OptionalJson<T>(pub Option<T>);
+
+#[axum::async_trait]
+impl<S, T> FromRequest<S> for OptionalJson<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = (StatusCode, Json<ErrorResponse>);
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let bytes = Bytes::from_request(request, state)
+            .await
+            .map_err(|rejection| {
+                (
+                    rejection.status(),
+                    Json(ErrorResponse {
+                        error: rejection.body_text(),
+                    }),
+                )
+            })?;
+
+        parse_optional_json(&bytes).map(OptionalJson)
+    }
+}
+
+/// Parse a body that may be empty
+fn parse_optional_json<T: DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<Option<T>, (StatusCode, Json<ErrorResponse>)> {
+    if bytes.trim_ascii().is_empty() {
+        return Ok(None);
+    }
+
+    serde_json::from_slice(bytes).map(Some).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("Invalid JSON body: {}", e),
+            }),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::BlockUploadsRequest;
+
+    #[test]
+    fn test_parse_optional_json() {
+        let parse = parse_optional_json::<BlockUploadsRequest>;
+
+        assert!(parse(b"").unwrap().is_none());
+        assert!(parse(b" \n").unwrap().is_none());
+        assert_eq!(
+            parse(br#"{"reason": "spam"}"#)
+                .unwrap()
+                .unwrap()
+                .reason
+                .as_deref(),
+            Some("spam")
+        );
+        assert!(parse(br#"{}"#).unwrap().unwrap().reason.is_none());
+
+        let (status, _) = parse(br#"{"reason": "spam""#).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = parse(br#"{"reason": 1}"#).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
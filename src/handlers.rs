@@ -1,44 +1,102 @@
-use crate::auth::{AuthAdmin, AuthUser};
+use crate::auth::{AuthAdmin, AuthUser, JwtVerifier};
+use crate::cache_metrics::{render_prometheus, CacheMetrics};
 use crate::config::Config;
+use crate::download_signing::DownloadSigner;
+use crate::extract::{OptionalJson, UuidPath};
+use crate::hashing::Sha1Hasher;
+use crate::moderation::{ModerationCheck, ModerationError, ModerationWebhook, UploadEvent};
 use crate::models::{
-    TextureMetadata, TextureResponse, TextureType, TexturesResponse, UploadOptions,
+    AssignCapeRequest, DEFAULT_CONTENT_TYPE, ExportRecord, EmbeddedTextureResponse, ImportErrorMode, ImportQuery, ImportSummary, TextureQuery, TextureResponse,
+    MojangTexturesResponse, ProfileProperty, TextureMetadata, TextureType, TexturesFormat, TexturesResponse, UploadOptions, UsernameDownloadQuery,
+    ActiveCapeRequest, BanHashRequest, BlockUploadsRequest, DownloadQuery, InvalidateResponse, OwnedCape,
+    content_type_for_extension, PackInfoResponse, PackUploadResponse, ReadOnlyState, ReloadResponse, SetTextureMetadataRequest, SignedUrlResponse,
+    METADATA_SIDECAR_EXTENSION, PACK_EXTENSION,
 };
-use crate::retrieval::{download_file_from_url, TextureRetriever};
-use crate::storage::StorageBackend;
+use crate::retrieval::backend::RetrievedTexture;
+use crate::retrieval::{
+    download_file_from_url, find_retriever, DefaultSkinRetriever, GroupDefaultSkinRetriever,
+    MissFallback, MojangError, MojangRetriever, NegativeCacheRetriever, TextureRetriever,
+};
+use crate::png_chunks::strip_ancillary_chunks;
+use crate::skin_alpha::normalize_skin_alpha;
+use crate::sprite_sheet::{compose_sheet, SheetCache};
+use crate::storage::{StorageBackend, StorageError};
+use crate::textures_property::{encode_textures_property, TexturesSigner};
+use crate::upload_spool::{SpoolError, UploadedFile};
 use anyhow::{anyhow, Result};
 use axum::{
     body::Body,
-    extract::{Multipart, Path, State},
-    http::{header, StatusCode},
+    extract::{multipart::Field, Multipart, Path, Query, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
     response::{IntoResponse, Json, Response},
 };
-use jsonwebtoken::DecodingKey;
+use futures::TryStreamExt;
 use sqlx::PgPool;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use uuid::Uuid;
 
-/// Maximum file size for uploads (1 MB)
-/// PNG texture files for Minecraft skins/capes should never exceed this
-const MAX_FILE_SIZE: usize = 1_048_576; // 1 MB in bytes
+/// Largest multiple of the vanilla texture size accepted as HD (1024x1024 skins)
+const MAX_HD_SCALE: u32 = 16;
+
+/// Maximum size of small text multipart fields (options, uuid, username)
+const MAX_TEXT_FIELD_SIZE: usize = 8_192; // 8 KB
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
     pub storage: Arc<dyn StorageBackend>,
     pub retriever: Arc<dyn TextureRetriever>,
-    pub public_key: Arc<DecodingKey>,
-    pub config: Config,
+    /// Mojang retriever addressable directly, regardless of the configured chain
+    pub mojang: Arc<MojangRetriever>,
+    /// Default skin logic addressable directly, regardless of the configured chain
+    pub default_skin: Arc<DefaultSkinRetriever>,
+    /// Default textures served by the download endpoints on a miss (SERVE_DEFAULT_ON_MISS)
+    pub miss_fallback: Option<Arc<MissFallback>>,
+    /// Verifies user JWTs; None without JWT_PUBLIC_KEY or JWT_JWKS_URL
+    pub jwt_verifier: Option<Arc<JwtVerifier>>,
+    /// Signs Mojang-style textures properties, if TEXTURES_SIGNING_KEY is set
+    pub textures_signer: Option<Arc<TexturesSigner>>,
+    /// Remembered texture misses (NEGATIVE_CACHE_SECONDS), cleared when a user's textures change
+    pub negative_cache: Option<Arc<NegativeCacheRetriever>>,
+    /// Webhook notified of uploads (MODERATION_WEBHOOK_URL)
+    pub moderation: Option<Arc<ModerationWebhook>>,
+    /// Content check run before uploads are stored (MODERATION_CHECK_URL)
+    pub moderation_check: Option<Arc<ModerationCheck>>,
+    /// Signs texture URLs and verifies them on download (DOWNLOAD_SIGNING_SECRET)
+    pub download_signer: Option<Arc<DownloadSigner>>,
+    /// Read-only maintenance mode, from READ_ONLY and toggled by /api/readonly
+    pub read_only: Arc<AtomicBool>,
+    /// Settings reloadable by /api/reload are swapped in here; read it with `config()`
+    pub config: Arc<RwLock<Arc<Config>>>,
+    /// SHA1 of resource packs by SHA256 hash, filled in as packs are served
+    pub pack_sha1: Arc<PackSha1Cache>,
+    /// Sprite sheets served by /sheet/:uuid, by skin and cape hash
+    pub sprite_sheets: Arc<SheetCache>,
+}
+
+impl AppState {
+    /// Current configuration, including settings changed by /api/reload
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
 }
 
 /// GET /get/{uuid} - Get all textures for a user
+/// With ?debug=true and an admin token, each texture includes the retriever that provided it
+/// With ?format=mojang, returns a Mojang session server style profile instead
+#[tracing::instrument(skip_all, fields(%user_uuid))]
 pub async fn get_textures(
     State(state): State<AppState>,
-    Path(user_uuid): Path<Uuid>,
-) -> Result<Json<TexturesResponse>, (StatusCode, String)> {
-    let mut response = TexturesResponse {
-        SKIN: None,
-        CAPE: None,
-    };
+    UuidPath(user_uuid): UuidPath<Uuid>,
+    Query(query): Query<TextureQuery>,
+    admin: Option<AuthAdmin>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let include_source = query.debug && admin.is_some();
+    let mut response = TexturesResponse::default();
 
     // Use the retriever's get_textures method to retrieve all textures at once
     let textures = state
@@ -53,36 +111,149 @@ pub async fn get_textures(
             )
         })?;
 
-    // Extract SKIN if available
-    if let Some(retrieved) = textures.get("SKIN") {
-        response.SKIN = Some(TextureResponse {
-            url: retrieved.url.clone(),
-            digest: retrieved.hash.clone(),
-            metadata: retrieved.metadata.clone(),
-        });
-    } else {
-        tracing::debug!("No SKIN texture found for user {}", user_uuid);
+    if query.format == TexturesFormat::Mojang {
+        return mojang_textures_response(&state, &headers, user_uuid, &textures, !query.unsigned)
+            .await;
     }
 
-    // Extract CAPE if available
-    if let Some(retrieved) = textures.get("CAPE") {
-        response.CAPE = Some(TextureResponse {
-            url: retrieved.url.clone(),
-            digest: retrieved.hash.clone(),
-            metadata: retrieved.metadata.clone(),
-        });
-    } else {
-        tracing::debug!("No CAPE texture found for user {}", user_uuid);
+    // Extract each texture type if available
+    for texture_type in TextureType::ALL {
+        match textures.get(&texture_type.to_string()) {
+            Some(retrieved) => response.set(
+                texture_type,
+                TextureResponse {
+                    url: signed_texture_url(&state, retrieved.url.clone()),
+                    digest: retrieved.hash.clone(),
+                    metadata: retrieved.metadata.clone(),
+                    source: include_source.then(|| retrieved.source.clone()),
+                },
+            ),
+            None => tracing::debug!("No {} texture found for user {}", texture_type, user_uuid),
+        }
     }
 
-    Ok(Json(response))
+    let volatile = textures.values().any(|t| t.source == "mojang");
+    Ok(cached_json_response(&state, &headers, &response, include_source, volatile))
+}
+
+/// Build the ?format=mojang response: the textures encoded as a base64 `textures`
+/// property, signed if requested (`?unsigned=false`) and TEXTURES_SIGNING_KEY is set
+async fn mojang_textures_response(
+    state: &AppState,
+    headers: &HeaderMap,
+    user_uuid: Uuid,
+    textures: &HashMap<String, RetrievedTexture>,
+    signed: bool,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    // The profile name is optional, so a failed lookup only omits it
+    let username = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(display_username, username) AS "username!"
+        FROM username_mappings
+        WHERE user_uuid = $1
+        LIMIT 1
+        "#,
+        user_uuid
+    )
+    .fetch_optional(&state.db)
+    .await
+    .unwrap_or_else(|e| {
+        tracing::warn!("Failed to look up username for {}: {}", user_uuid, e);
+        None
+    });
+
+    let internal_error = |e: anyhow::Error| {
+        tracing::error!("Failed to build textures property: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to build textures property: {}", e),
+        )
+    };
+
+    let textures: HashMap<String, RetrievedTexture> = textures
+        .iter()
+        .map(|(texture_type, texture)| {
+            let texture = RetrievedTexture {
+                url: signed_texture_url(state, texture.url.clone()),
+                ..texture.clone()
+            };
+            (texture_type.clone(), texture)
+        })
+        .collect();
+    let value = encode_textures_property(user_uuid, username.as_deref(), &textures)
+        .map_err(internal_error)?;
+    // Unsigned requests skip the RSA signing entirely
+    let signature = state
+        .textures_signer
+        .as_ref()
+        .filter(|_| signed)
+        .map(|signer| signer.sign(&value))
+        .transpose()
+        .map_err(internal_error)?;
+
+    let response = MojangTexturesResponse {
+        id: user_uuid.simple().to_string(),
+        name: username,
+        properties: vec![ProfileProperty {
+            name: "textures".to_string(),
+            value,
+            signature,
+        }],
+    };
+
+    let json = serde_json::to_vec(&response).map_err(|e| internal_error(e.into()))?;
+    let etag = mojang_textures_etag(&response.id, response.name.as_deref(), &textures, signed);
+    let volatile = textures.values().any(|t| t.source == "mojang");
+    Ok(json_response_with_etag(state, headers, json, etag, false, volatile))
+}
+
+/// ETag of a `?format=mojang` response
+/// The textures property embeds the current time, so the ETag covers the profile and
+/// textures it describes (type, hash, URL and model) rather than the serialized body
+fn mojang_textures_etag(
+    id: &str,
+    name: Option<&str>,
+    textures: &HashMap<String, RetrievedTexture>,
+    signed: bool,
+) -> String {
+    let mut entries: Vec<String> = textures
+        .iter()
+        .map(|(texture_type, texture)| {
+            format!(
+                "{}\n{}\n{}\n{}",
+                texture_type,
+                texture.hash,
+                texture.url,
+                texture
+                    .metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.model.as_deref())
+                    .unwrap_or_default()
+            )
+        })
+        .collect();
+    entries.sort();
+    let source = format!(
+        "{}\n{}\n{}\n{}",
+        id,
+        name.unwrap_or_default(),
+        signed,
+        entries.join("\n\n")
+    );
+    content_etag(source.as_bytes())
 }
 
 /// GET /get/{uuid}/{texture_type} - Get specific texture
+/// With ?debug=true and an admin token, the response includes the retriever that provided it
+#[tracing::instrument(skip_all, fields(%user_uuid, texture_type = %texture_type_str))]
 pub async fn get_texture(
     State(state): State<AppState>,
-    Path((user_uuid, texture_type_str)): Path<(Uuid, String)>,
-) -> Result<Json<TextureResponse>, (StatusCode, String)> {
+    UuidPath((user_uuid, texture_type_str)): UuidPath<(Uuid, String)>,
+    Query(query): Query<TextureQuery>,
+    admin: Option<AuthAdmin>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let include_source = query.debug && admin.is_some();
     let texture_type: TextureType = texture_type_str.parse().map_err(|e| {
         (
             StatusCode::BAD_REQUEST,
@@ -108,18 +279,294 @@ pub async fn get_texture(
             )
         })?;
 
-    Ok(Json(TextureResponse {
-        url: retrieved.url,
+    let volatile = retrieved.source == "mojang";
+    let data = if query.embed {
+        Some(embedded_texture_data(&state, &retrieved).await?)
+    } else {
+        None
+    };
+    let response = TextureResponse {
+        url: signed_texture_url(&state, retrieved.url),
         digest: retrieved.hash,
         metadata: retrieved.metadata,
-    }))
+        source: include_source.then_some(retrieved.source),
+    };
+
+    if let Some(data) = data {
+        let response = EmbeddedTextureResponse {
+            data,
+            texture: response,
+        };
+        return Ok(cached_json_response(&state, &headers, &response, include_source, volatile));
+    }
+
+    Ok(cached_json_response(&state, &headers, &response, include_source, volatile))
+}
+
+/// Base64 of a texture file for ?embed=true
+/// The file is looked up by the texture's hash, falling back to its URL, instead of
+/// running the chain again: `data` is then always the file `digest` names, even if the
+/// user's texture changes in between, and Mojang's profile is not requested twice.
+/// Files over MAX_EMBED_SIZE, e.g. HD skins, are refused rather than inlined
+async fn embedded_texture_data(
+    state: &AppState,
+    texture: &RetrievedTexture,
+) -> Result<String, (StatusCode, String)> {
+    use base64::Engine;
+
+    let bytes = match state.retriever.get_texture_bytes_by_hash(&texture.hash).await {
+        Ok(Some(retrieved)) => Ok(Some(retrieved.bytes)),
+        Ok(None) => download_file_from_url(&texture.url).await,
+        Err(e) => Err(e),
+    };
+    let bytes = bytes
+        .map_err(|e| {
+            tracing::error!("Failed to retrieve texture bytes: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to retrieve texture: {}", e),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Texture file not found for {}", texture.hash),
+            )
+        })?;
+
+    let max_embed_size = state.config().max_embed_size;
+    if bytes.len() > max_embed_size {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Texture is too large to embed ({} bytes, limit {}), download it from its url instead",
+                bytes.len(),
+                max_embed_size
+            ),
+        ));
+    }
+
+    Ok(base64::engine::general_purpose::STANDARD.encode(&bytes))
+}
+
+/// GET /default/{uuid} - Get the default skin a user would get without a custom one
+/// Only runs the default skin logic (Steve, or Alex by UUID parity with
+/// DEFAULT_SKIN_MODEL_BY_UUID), whatever textures the user has
+#[tracing::instrument(skip_all, fields(%user_uuid))]
+pub async fn get_default_skin(
+    State(state): State<AppState>,
+    UuidPath(user_uuid): UuidPath<Uuid>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let retrieved = state.default_skin.default_skin_for(user_uuid);
+    let response = TextureResponse {
+        url: signed_texture_url(&state, retrieved.url),
+        digest: retrieved.hash,
+        metadata: retrieved.metadata,
+        source: None,
+    };
+
+    cached_json_response(&state, &headers, &response, false, false)
+}
+
+/// GET /get/{uuid}/{texture_type}/metadata - Get only the metadata of a texture
+/// For clients that cache texture bytes by hash and only need e.g. the skin model
+#[tracing::instrument(skip_all, fields(%user_uuid, texture_type = %texture_type_str))]
+pub async fn get_texture_metadata(
+    State(state): State<AppState>,
+    UuidPath((user_uuid, texture_type_str)): UuidPath<(Uuid, String)>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let texture_type: TextureType = texture_type_str.parse().map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid texture type: {}", e),
+        )
+    })?;
+
+    let retrieved = state
+        .retriever
+        .get_texture(user_uuid, texture_type)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to retrieve texture: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to retrieve texture: {}", e),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Texture not found for {}", texture_type_str),
+            )
+        })?;
+
+    let volatile = retrieved.source == "mojang";
+    let metadata = retrieved.metadata.ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("No metadata for {}", texture_type_str),
+        )
+    })?;
+
+    Ok(cached_json_response(&state, &headers, &metadata, false, volatile))
+}
+
+/// Build a JSON response for the /get endpoints with Cache-Control and ETag headers
+/// The ETag is derived from the response content (texture hashes and metadata), so a
+/// matching If-None-Match gets 304 Not Modified. Textures from volatile sources (Mojang)
+/// use the shorter JSON_CACHE_SECONDS_VOLATILE, and admin debug responses are never cached.
+fn cached_json_response<T: serde::Serialize>(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: &T,
+    debug: bool,
+    volatile: bool,
+) -> Response<Body> {
+    let json = match serde_json::to_vec(body) {
+        Ok(json) => json,
+        Err(e) => {
+            tracing::error!("Failed to serialize response: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let etag = content_etag(&json);
+    json_response_with_etag(state, headers, json, etag, debug, volatile)
+}
+
+/// Quoted ETag for the given content
+fn content_etag(content: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("\"{}\"", &hex::encode(Sha256::digest(content))[..32])
+}
+
+/// Build a JSON response with Cache-Control and a caller-provided ETag
+/// For bodies that are not stable across requests (such as the Mojang textures property,
+/// which embeds the current time), so the ETag must be derived from what they describe
+fn json_response_with_etag(
+    state: &AppState,
+    headers: &HeaderMap,
+    json: Vec<u8>,
+    etag: String,
+    debug: bool,
+    volatile: bool,
+) -> Response<Body> {
+    let mut max_age = if volatile {
+        state.config().json_cache_seconds_volatile
+    } else {
+        state.config().json_cache_seconds
+    };
+    // Signed texture URLs stay valid for at least a TTL, so a cached response must not
+    // outlive that
+    if let Some(signer) = &state.download_signer {
+        max_age = max_age.min(signer.ttl_seconds());
+    }
+    let cache_control = if debug {
+        "no-store".to_string()
+    } else {
+        format!("public, max-age={}", max_age)
+    };
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+
+    let cache_headers = [
+        (header::CACHE_CONTROL, cache_control),
+        (header::ETAG, etag),
+    ];
+
+    if not_modified {
+        (StatusCode::NOT_MODIFIED, cache_headers).into_response()
+    } else {
+        (
+            cache_headers,
+            [(header::CONTENT_TYPE, "application/json")],
+            json,
+        )
+            .into_response()
+    }
+}
+
+/// Whether an If-Match header value accepts the current texture hash
+/// Hashes are compared as the ETags they are served with (`"<hash>"`); `*` matches
+/// any existing texture. Nothing matches when the user has no texture yet
+fn if_match_satisfied(if_match: &str, current_hash: Option<&str>) -> bool {
+    let Some(current_hash) = current_hash else {
+        return false;
+    };
+    let etag = hash_etag(current_hash);
+    if_match
+        .split(',')
+        .any(|tag| tag.trim() == etag || tag.trim() == "*")
+}
+
+/// Enforce the If-Match precondition of an upload against the user's current texture
+/// of the type (the active cape for capes), so a client only replaces the texture it
+/// last read. Uploads without the header are not checked.
+/// The current texture is locked, so when run in the transaction of the write, a
+/// concurrent upload checked against the same hash waits and then sees the new one
+async fn check_upload_precondition(
+    executor: impl sqlx::PgExecutor<'_>,
+    headers: &HeaderMap,
+    user_uuid: Uuid,
+    texture_type: TextureType,
+) -> Result<(), (StatusCode, String)> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return Ok(());
+    };
+    let if_match = if_match.to_str().map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            "Invalid If-Match header".to_string(),
+        )
+    })?;
+
+    let current_hash = sqlx::query_scalar!(
+        r#"
+        SELECT file_hash
+        FROM textures
+        WHERE user_uuid = $1 AND texture_type = $2 AND active
+        ORDER BY updated_at DESC
+        LIMIT 1
+        FOR UPDATE
+        "#,
+        user_uuid,
+        texture_type.to_string()
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to load current texture: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check upload precondition".to_string(),
+        )
+    })?;
+
+    if !if_match_satisfied(if_match, current_hash.as_deref()) {
+        return Err((
+            StatusCode::PRECONDITION_FAILED,
+            format!("{} changed since it was read", texture_type),
+        ));
+    }
+    Ok(())
 }
 
 /// POST /upload - Upload a texture file
+/// With `If-Match: "<hash>"`, the texture is only replaced if the current one still has
+/// that hash, otherwise 412 Precondition Failed
+#[tracing::instrument(skip_all, fields(%user_uuid, texture_type = %texture_type_str))]
 pub async fn upload_texture(
     State(state): State<AppState>,
     AuthUser(user_uuid): AuthUser,
     Path(texture_type_str): Path<String>,
+    headers: HeaderMap,
     mut multipart: Multipart,
 ) -> Result<Json<TextureResponse>, (StatusCode, String)> {
     let texture_type: TextureType = texture_type_str.parse().map_err(|e| {
@@ -128,7 +575,9 @@ pub async fn upload_texture(
             format!("Invalid texture type: {}", e),
         )
     })?;
-    let mut file_bytes: Option<Vec<u8>> = None;
+    ensure_upload_allowed(&state, user_uuid).await?;
+
+    let mut file: Option<UploadedFile> = None;
     let mut options: Option<UploadOptions> = None;
 
     while let Some(field) = multipart.next_field().await.map_err(|e| {
@@ -141,42 +590,10 @@ pub async fn upload_texture(
 
         match name.as_str() {
             "file" => {
-                let data = field.bytes().await.map_err(|e| {
-                    (
-                        StatusCode::BAD_REQUEST,
-                        format!("Failed to read file: {}", e),
-                    )
-                })?;
-
-                // Validate file size
-                if data.len() > MAX_FILE_SIZE {
-                    return Err((
-                        StatusCode::BAD_REQUEST,
-                        format!(
-                            "File size {} bytes exceeds maximum allowed size of {} bytes (1 MB)",
-                            data.len(),
-                            MAX_FILE_SIZE
-                        ),
-                    ));
-                }
-
-                // Validate PNG
-                if !is_png(&data) {
-                    return Err((
-                        StatusCode::BAD_REQUEST,
-                        "File must be a PNG image".to_string(),
-                    ));
-                }
-
-                file_bytes = Some(data.to_vec());
+                file = Some(read_texture_file(field, texture_type, &state).await?);
             }
             "options" => {
-                let json_str = field.text().await.map_err(|e| {
-                    (
-                        StatusCode::BAD_REQUEST,
-                        format!("Failed to read options: {}", e),
-                    )
-                })?;
+                let json_str = read_text_field(field, "options").await?;
                 options = Some(serde_json::from_str(&json_str).map_err(|e| {
                     (
                         StatusCode::BAD_REQUEST,
@@ -188,18 +605,19 @@ pub async fn upload_texture(
         }
     }
 
-    let file_bytes =
-        file_bytes.ok_or_else(|| (StatusCode::BAD_REQUEST, "No file provided".to_string()))?;
+    let file = file.ok_or(UploadFileError::Missing)?;
 
-    let options = options.unwrap_or(UploadOptions { modelSlim: false });
+    let options = options.unwrap_or_default();
 
-    // Calculate hash
-    let hash = state.storage.calculate_hash(&file_bytes);
+    let hash = file.hash().to_string();
+    check_upload_content(&state, user_uuid, texture_type, &file).await?;
+    let origin = texture_origin(&state, user_uuid.to_string(), "upload", file.file_name());
+    // Checked before storing the file to fail early; checked again when writing
+    check_upload_precondition(&state.db, &headers, user_uuid, texture_type).await?;
 
     // Store file with proper extension
-    let file_url = state
-        .storage
-        .store_file(file_bytes, &hash, texture_type.file_extension())
+    let file_url = file
+        .store(state.storage.as_ref(), texture_type)
         .await
         .map_err(|e| {
             tracing::error!("Failed to store file: {}", e);
@@ -209,54 +627,74 @@ pub async fn upload_texture(
             )
         })?;
 
-    // Prepare metadata
-    let metadata = if options.modelSlim {
-        Some(serde_json::json!({ "model": "slim" }))
-    } else {
-        None
+    let upload_event = UploadEvent {
+        uuid: user_uuid,
+        texture_type,
+        hash: hash.clone(),
+        url: file_url.clone(),
     };
+    if let Err(e) = review_upload(&state, &upload_event).await {
+        remove_orphaned_file(&state, &hash, texture_type).await;
+        return Err(e);
+    }
 
-    // Insert or update in database
-    sqlx::query!(
-        r#"
-        INSERT INTO textures (user_uuid, texture_type, file_hash, file_url, metadata)
-        VALUES ($1, $2, $3, $4, $5)
-        ON CONFLICT (user_uuid, texture_type)
-        DO UPDATE SET file_hash = $3, file_url = $4, metadata = $5, updated_at = NOW()
-        "#,
-        user_uuid,
-        texture_type.to_string(),
-        hash,
-        file_url,
-        metadata
-    )
-    .execute(&state.db)
-    .await
-    .map_err(|e| {
+    // Prepare metadata
+    let texture_metadata = options.texture_metadata(texture_type);
+    let metadata = texture_metadata
+        .as_ref()
+        .and_then(|m| serde_json::to_value(m).ok());
+
+    // Insert or update in database, in one transaction with the If-Match check: the
+    // moderation webhook may have taken a while, and another upload may have passed the
+    // same check in the meantime
+    let save_error = |e: sqlx::Error| {
         tracing::error!("Failed to save texture: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             "Failed to save texture".to_string(),
         )
-    })?;
+    };
+    let saved = async {
+        let mut tx = state.db.begin().await.map_err(save_error)?;
+        check_upload_precondition(&mut *tx, &headers, user_uuid, texture_type).await?;
+        upsert_texture(
+            &mut *tx,
+            user_uuid,
+            texture_type,
+            &hash,
+            &file_url,
+            metadata,
+            None,
+            origin.as_ref(),
+        )
+        .await
+        .map_err(save_error)?;
+        tx.commit().await.map_err(save_error)
+    }
+    .await;
+    if let Err(e) = saved {
+        remove_orphaned_file(&state, &hash, texture_type).await;
+        return Err(e);
+    }
+
+    store_metadata_sidecar(&state, &hash, texture_metadata.as_ref()).await;
+    invalidate_user_caches(&state, user_uuid);
+    notify_upload(&state, upload_event);
 
     Ok(Json(TextureResponse {
-        url: file_url,
+        url: signed_texture_url(&state, file_url),
         digest: hash,
-        metadata: if options.modelSlim {
-            Some(TextureMetadata {
-                model: Some("slim".to_string()),
-            })
-        } else {
-            None
-        },
+        metadata: texture_metadata,
+        source: None,
     }))
 }
 
 /// GET /download/{texture_type}/{uuid} - Download texture file
+#[tracing::instrument(skip_all, fields(%user_uuid, texture_type = %texture_type_str))]
 pub async fn download_texture(
     State(state): State<AppState>,
-    Path((texture_type_str, user_uuid)): Path<(String, Uuid)>,
+    UuidPath((texture_type_str, user_uuid)): UuidPath<(String, Uuid)>,
+    Query(query): Query<DownloadQuery>,
 ) -> Result<Response<Body>, (StatusCode, String)> {
     let texture_type: TextureType = texture_type_str.parse().map_err(|e| {
         (
@@ -276,404 +714,1013 @@ pub async fn download_texture(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Failed to retrieve texture: {}", e),
             )
-        })?
-        .ok_or_else(|| {
-            (
-                StatusCode::NOT_FOUND,
-                format!("Texture not found for {}", texture_type_str),
-            )
         })?;
 
-    Ok(([(header::CONTENT_TYPE, "image/png")], retrieved.bytes).into_response())
+    let response = match retrieved {
+        Some(retrieved) => (
+            [
+                (header::CONTENT_TYPE, texture_type.content_type().to_string()),
+                (header::ETAG, hash_etag(&retrieved.hash)),
+            ],
+            retrieved.bytes,
+        )
+            .into_response(),
+        None => default_on_miss(&state, texture_type, Some(user_uuid))
+            .await
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("Texture not found for {}", texture_type_str),
+                )
+            })?,
+    };
+
+    let filename = format!(
+        "{}_{}.{}",
+        user_uuid,
+        texture_type.to_string().to_lowercase(),
+        texture_type.file_extension()
+    );
+    Ok(with_attachment(response, query.download, &filename))
 }
 
-/// GET /files/{hash}.{ext} - Serve texture files directly from storage
-/// This provides efficient file distribution for files that have been uploaded
-pub async fn serve_texture_file(
+/// GET /sheet/{uuid} - A user's skin and cape composited into one PNG
+/// See `compose_sheet` for the layout. Sheets are cached by the hashes of the two textures
+#[tracing::instrument(skip_all, fields(%user_uuid))]
+pub async fn get_sprite_sheet(
     State(state): State<AppState>,
-    Path((hash)): Path<(String)>,
+    UuidPath(user_uuid): UuidPath<Uuid>,
 ) -> Result<Response<Body>, (StatusCode, String)> {
-    // Get file bytes from storage by hash
-    let file_bytes = state.storage.get_file(&hash, "png").await.map_err(|e| {
-        tracing::error!("Failed to get file: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to get file".to_string(),
-        )
-    })?;
+    let fetch = |texture_type| {
+        let state = &state;
+        async move {
+            state
+                .retriever
+                .get_texture_bytes(user_uuid, texture_type)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Failed to retrieve texture: {}", e);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to retrieve texture: {}", e),
+                    )
+                })
+        }
+    };
+    let (skin, cape) = tokio::try_join!(fetch(TextureType::SKIN), fetch(TextureType::CAPE))?;
+    let skin = skin
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Texture not found for SKIN".to_string()))?;
+    let cape_hash = cape.as_ref().map(|cape| cape.hash.as_str());
+
+    let sheet = match state.sprite_sheets.get(&skin.hash, cape_hash) {
+        Some(sheet) => sheet,
+        None => {
+            let cape_bytes = cape.as_ref().map(|cape| cape.bytes.as_slice());
+            let sheet = Arc::new(compose_sheet(&skin.bytes, cape_bytes).map_err(|e| {
+                tracing::error!("Failed to compose sprite sheet: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to compose sprite sheet".to_string(),
+                )
+            })?);
+            state
+                .sprite_sheets
+                .insert(&skin.hash, cape_hash, sheet.clone());
+            sheet
+        }
+    };
 
-    Ok(([(header::CONTENT_TYPE, "image/png")], file_bytes).into_response())
-}
+    let etag = match cape_hash {
+        Some(cape_hash) => format!("\"{}-{}\"", skin.hash, cape_hash),
+        None => hash_etag(&skin.hash),
+    };
 
-/// Check if bytes represent a PNG file
-fn is_png(bytes: &[u8]) -> bool {
-    bytes.len() >= 8 && bytes[0..8] == [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]
+    Ok((
+        [
+            (header::CONTENT_TYPE, DEFAULT_CONTENT_TYPE.to_string()),
+            (header::ETAG, etag),
+        ],
+        sheet.to_vec(),
+    )
+        .into_response())
 }
 
-/// POST /api/upload/:type - Upload a texture for any user (admin only)
-/// Requires admin bearer token. User UUID is provided in the "user" form field.
-pub async fn admin_upload_texture(
+/// HEAD /download/{texture_type}/{uuid} - Headers of the GET without the body
+/// A texture uploaded for the user is answered from its size in storage; derived textures,
+/// other retrievers and default skins go through the GET handler with the body dropped
+#[tracing::instrument(skip_all, fields(%user_uuid, texture_type = %texture_type_str))]
+pub async fn head_download_texture(
     State(state): State<AppState>,
-    AuthAdmin: AuthAdmin,
-    Path(texture_type_str): Path<String>,
-    mut multipart: Multipart,
-) -> Result<Json<TextureResponse>, (StatusCode, String)> {
-    let texture_type: TextureType = texture_type_str.parse().map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            format!("Invalid texture type: {}", e),
+    UuidPath((texture_type_str, user_uuid)): UuidPath<(String, Uuid)>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    if let Ok(texture_type) = texture_type_str.parse::<TextureType>() {
+        let record = sqlx::query!(
+            r#"
+            SELECT file_hash
+            FROM textures
+            WHERE user_uuid = $1 AND texture_type = $2 AND active
+            "#,
+            user_uuid,
+            texture_type.to_string()
         )
-    })?;
-
-    let mut file_bytes: Option<Vec<u8>> = None;
-    let mut options: Option<UploadOptions> = None;
-    let mut user_uuid: Option<Uuid> = None;
-    let mut user_username: Option<String> = None;
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to query database: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database query failed".to_string(),
+            )
+        })?;
 
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        (
-            StatusCode::BAD_REQUEST,
-            format!("Invalid multipart data: {}", e),
-        )
-    })? {
-        let name = field.name().unwrap_or("").to_string();
+        if let Some(record) = record {
+            let key = state.storage.texture_key(&record.file_hash, texture_type);
+            match state.storage.file_size(&key, texture_type.file_extension()).await {
+                Ok(Some(size)) => {
+                    let filename = format!(
+                        "{}_{}.{}",
+                        user_uuid,
+                        texture_type.to_string().to_lowercase(),
+                        texture_type.file_extension()
+                    );
+                    let response =
+                        head_response(texture_type.content_type(), size, &record.file_hash, None);
+                    return Ok(with_attachment(response, query.download, &filename));
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to get size of {}: {}", record.file_hash, e),
+            }
+        }
+    }
 
-        match name.as_str() {
-            "file" => {
-                let data = field.bytes().await.map_err(|e| {
-                    (
-                        StatusCode::BAD_REQUEST,
-                        format!("Failed to read file: {}", e),
-                    )
-                })?;
+    download_texture(
+        State(state),
+        UuidPath((texture_type_str, user_uuid)),
+        Query(query),
+    )
+    .await
+}
 
-                // Validate file size
-                if data.len() > MAX_FILE_SIZE {
-                    return Err((
-                        StatusCode::BAD_REQUEST,
-                        format!(
-                            "File size {} bytes exceeds maximum allowed size of {} bytes (1 MB)",
-                            data.len(),
-                            MAX_FILE_SIZE
-                        ),
-                    ));
-                }
+/// URL of a texture as handed out to clients, signed with DOWNLOAD_SIGNING_SECRET if it
+/// points at this service
+fn signed_texture_url(state: &AppState, url: String) -> String {
+    match &state.download_signer {
+        Some(signer) => signer.sign_url(
+            &state.config().base_url,
+            &url,
+            chrono::Utc::now().timestamp(),
+        ),
+        None => url,
+    }
+}
 
-                // Validate PNG
-                if !is_png(&data) {
-                    return Err((
-                        StatusCode::BAD_REQUEST,
-                        "File must be a PNG image".to_string(),
-                    ));
-                }
+/// ETag of a file served by hash; its contents never change
+fn hash_etag(hash: &str) -> String {
+    format!("\"{}\"", hash)
+}
 
-                file_bytes = Some(data.to_vec());
-            }
-            "options" => {
-                let json_str = field.text().await.map_err(|e| {
-                    (
-                        StatusCode::BAD_REQUEST,
-                        format!("Failed to read options: {}", e),
-                    )
-                })?;
-                options = Some(serde_json::from_str(&json_str).map_err(|e| {
-                    (
-                        StatusCode::BAD_REQUEST,
-                        format!("Invalid options JSON: {}", e),
-                    )
-                })?);
-            }
-            "uuid" => {
-                let uuid_str = field.text().await.map_err(|e| {
-                    (
-                        StatusCode::BAD_REQUEST,
-                        format!("Failed to read user UUID: {}", e),
-                    )
-                })?;
-                user_uuid =
-                    Some(Uuid::parse_str(&uuid_str).map_err(|e| {
-                        (StatusCode::BAD_REQUEST, format!("Invalid user UUID: {}", e))
-                    })?);
-            }
-            "username" => {
-                let username_str = field.text().await.map_err(|e| {
-                    (
-                        StatusCode::BAD_REQUEST,
-                        format!("Failed to read username: {}", e),
-                    )
-                })?;
-                user_username = Some(username_str);
-            }
-            _ => {}
+/// Send a response as an attachment named `filename` when ?download=true was given,
+/// so browsers save it with an extension; otherwise it stays inline for <img> tags
+fn with_attachment(mut response: Response<Body>, download: bool, filename: &str) -> Response<Body> {
+    if download {
+        if let Ok(value) = format!("attachment; filename=\"{}\"", filename).parse() {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_DISPOSITION, value);
         }
     }
+    response
+}
 
-    let user_uuid = user_uuid.ok_or_else(|| {
+/// Response to a HEAD request for a stored file, with the headers a GET would send
+fn head_response(
+    content_type: &str,
+    size: u64,
+    hash: &str,
+    cache_control: Option<String>,
+) -> Response<Body> {
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_LENGTH, size.to_string()),
+            (header::ETAG, hash_etag(hash)),
+        ],
+        Body::empty(),
+    )
+        .into_response();
+    if let Some(cache_control) = cache_control.and_then(|value| value.parse().ok()) {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, cache_control);
+    }
+    response
+}
+
+/// GET /files/{hash}.{ext} - Serve texture files directly from storage
+/// This provides efficient file distribution for files that have been uploaded
+#[tracing::instrument(skip_all, fields(%hash))]
+pub async fn serve_texture_file(
+    State(state): State<AppState>,
+    Path((hash)): Path<(String)>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    // Stream the file from storage by hash
+    // A missing file is a 404, so CDNs do not retry it like a storage failure
+    let (file_body, texture_type) = match state.storage.get_file_stream_any_type(&hash).await {
+        Ok(found) => found,
+        Err(StorageError::NotFound(_)) => {
+            return Err((StatusCode::NOT_FOUND, "File not found".to_string()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get file: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get file".to_string(),
+            ));
+        }
+    };
+
+    let response = (
+        [
+            (header::CONTENT_TYPE, texture_type.content_type().to_string()),
+            (header::CACHE_CONTROL, file_cache_control(&state)),
+            (header::ETAG, hash_etag(&hash)),
+        ],
+        file_body,
+    )
+        .into_response();
+    let filename = format!("{}.{}", hash, texture_type.file_extension());
+    Ok(with_attachment(response, query.download, &filename))
+}
+
+/// Cache-Control of /files/{hash}: the URL is the content hash, so the file never changes
+fn file_cache_control(state: &AppState) -> String {
+    format!(
+        "public, max-age={}, immutable",
+        state.config().file_cache_seconds
+    )
+}
+
+/// HEAD /files/{hash}.{ext} - Headers of the GET without reading the file
+#[tracing::instrument(skip_all, fields(%hash))]
+pub async fn head_texture_file(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let size = state.storage.file_size_any_type(&hash).await.map_err(|e| {
+        tracing::error!("Failed to get file: {}", e);
         (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get file".to_string(),
+        )
+    })?;
+
+    match size {
+        Some((size, texture_type)) => {
+            let response = head_response(
+                texture_type.content_type(),
+                size,
+                &hash,
+                Some(file_cache_control(&state)),
+            );
+            let filename = format!("{}.{}", hash, texture_type.file_extension());
+            Ok(with_attachment(response, query.download, &filename))
+        }
+        None => Err((StatusCode::NOT_FOUND, "File not found".to_string())),
+    }
+}
+
+/// Maximum number of hashes accepted by a single POST /files/exists request
+const MAX_EXISTS_HASHES: usize = 256;
+
+/// POST /files/exists - Check which of the given file hashes are stored
+/// Lets clients that cache textures by hash skip downloads of files that are gone
+#[tracing::instrument(skip_all, fields(hashes = hashes.len()))]
+pub async fn files_exist(
+    State(state): State<AppState>,
+    Json(hashes): Json<Vec<String>>,
+) -> Result<Json<HashMap<String, bool>>, (StatusCode, String)> {
+    if hashes.len() > MAX_EXISTS_HASHES {
+        return Err((
             StatusCode::BAD_REQUEST,
-            "User UUID not provided".to_string(),
+            format!("At most {} hashes can be checked at once", MAX_EXISTS_HASHES),
+        ));
+    }
+
+    let checks = hashes.into_iter().map(|hash| {
+        let storage = state.storage.clone();
+        async move {
+            // Hashes are hex; anything else cannot name a stored file
+            let exists = if !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                storage.exists_any_type(&hash).await?
+            } else {
+                false
+            };
+            Ok::<_, anyhow::Error>((hash, exists))
+        }
+    });
+
+    let results = futures::future::try_join_all(checks).await.map_err(|e| {
+        tracing::error!("Failed to check files: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to check files".to_string(),
         )
     })?;
 
-    if let Some(username) = user_username {
-        sqlx::query!(
-            r#"
-        INSERT INTO username_mappings (user_uuid, username, updated_at)
-        VALUES ($1, $2, NOW())
-        ON CONFLICT (user_uuid, username)
-        DO UPDATE SET updated_at = NOW()
-        "#,
-            user_uuid,
-            username
+    Ok(Json(results.into_iter().collect()))
+}
+
+/// Read a small multipart text field, rejecting it as soon as it exceeds MAX_TEXT_FIELD_SIZE
+/// Unlike `Field::text`, this never buffers more than the limit
+async fn read_text_field(
+    mut field: Field<'_>,
+    description: &str,
+) -> Result<String, (StatusCode, String)> {
+    let mut data: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = field.chunk().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to read {}: {}", description, e),
         )
-        .execute(&state.db)
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to update username mapping: {}", e);
+    })? {
+        if data.len() + chunk.len() > MAX_TEXT_FIELD_SIZE {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Field {} exceeds maximum allowed size of {} bytes",
+                    description, MAX_TEXT_FIELD_SIZE
+                ),
+            ));
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    String::from_utf8(data).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Failed to read {}: {}", description, e),
+        )
+    })
+}
+
+/// Read an uploaded texture file field, validating its size, PNG signature and dimensions
+async fn read_texture_file(
+    field: Field<'_>,
+    texture_type: TextureType,
+    state: &AppState,
+) -> Result<UploadedFile, (StatusCode, String)> {
+    let config = state.config();
+    let file_name = field.file_name().map(str::to_string);
+
+    let file = if config.stream_uploads_to_disk {
+        spool_upload(field, &config, config.max_upload_size).await?
+    } else {
+        let data = field.bytes().await.map_err(|e| {
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to update username mapping".to_string(),
+                StatusCode::BAD_REQUEST,
+                format!("Failed to read file: {}", e),
             )
         })?;
-    }
 
-    let file_bytes =
-        file_bytes.ok_or_else(|| (StatusCode::BAD_REQUEST, "No file provided".to_string()))?;
+        // Validate file size
+        if data.len() > config.max_upload_size {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "File size {} bytes exceeds maximum allowed size of {} bytes",
+                    data.len(),
+                    config.max_upload_size
+                ),
+            ));
+        }
 
-    let options = options.unwrap_or(UploadOptions { modelSlim: false });
+        let hash = state.storage.calculate_hash(&data);
+        UploadedFile::in_memory(data.to_vec(), hash)
+    };
 
-    // Calculate hash
-    let hash = state.storage.calculate_hash(&file_bytes);
+    check_png(&file)?;
 
-    // Store file with proper extension
-    let file_url = state
-        .storage
-        .store_file(file_bytes, &hash, texture_type.file_extension())
-        .await
-        .map_err(|e| {
-            tracing::error!("Failed to store file: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to store file".to_string(),
-            )
-        })?;
+    validate_texture_dimensions(file.head(), texture_type, &config)?;
 
-    // Prepare metadata
-    let metadata = if options.modelSlim {
-        Some(serde_json::json!({ "model": "slim" }))
+    let file = if config.strip_png_text_chunks {
+        rewrite_upload(file, state, strip_ancillary_chunks).await?
     } else {
-        None
+        file
     };
 
-    // Insert or update in database
-    sqlx::query!(
-        r#"
-        INSERT INTO textures (user_uuid, texture_type, file_hash, file_url, metadata)
-        VALUES ($1, $2, $3, $4, $5)
-        ON CONFLICT (user_uuid, texture_type)
-        DO UPDATE SET file_hash = $3, file_url = $4, metadata = $5, updated_at = NOW()
-        "#,
-        user_uuid,
-        texture_type.to_string(),
-        hash,
-        file_url,
-        metadata
-    )
-    .execute(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to save texture: {}", e);
+    let file = if texture_type == TextureType::SKIN && config.normalize_skin_alpha {
+        rewrite_upload(file, state, normalize_skin_alpha).await?
+    } else {
+        file
+    };
+
+    Ok(file.with_file_name(file_name))
+}
+
+/// Replace an uploaded PNG with the result of `rewrite`, if it returns one, e.g. a skin
+/// with an opaque base layer (NORMALIZE_SKIN_ALPHA) or without text chunks
+/// (STRIP_PNG_TEXT_CHUNKS). This changes the bytes, so a rewritten file is hashed again
+async fn rewrite_upload(
+    file: UploadedFile,
+    state: &AppState,
+    rewrite: fn(&[u8]) -> Result<Option<Vec<u8>>>,
+) -> Result<UploadedFile, (StatusCode, String)> {
+    let bytes = file.bytes().await.map_err(|e| {
+        tracing::error!("Failed to read buffered upload: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to save texture".to_string(),
+            "Failed to read upload".to_string(),
         )
     })?;
 
-    Ok(Json(TextureResponse {
-        url: file_url,
-        digest: hash,
-        metadata: if options.modelSlim {
-            Some(TextureMetadata {
-                model: Some("slim".to_string()),
-            })
-        } else {
-            None
-        },
-    }))
+    let rewritten = rewrite(&bytes).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    drop(bytes);
+
+    Ok(match rewritten {
+        Some(bytes) => {
+            let hash = state.storage.calculate_hash(&bytes);
+            UploadedFile::in_memory(bytes, hash)
+        }
+        None => file,
+    })
 }
 
-/// GET /download/:hash - Download skin by hash
-/// Uses the retrieval chain to get texture bytes by hash (StorageRetriever, EmbeddedDefaultSkinRetriever, etc.)
-/// Falls back to http/https download if the texture has an external URL in the database
-pub async fn download_by_hash(
-    State(state): State<AppState>,
-    Path(hash): Path<String>,
-) -> Result<Response<Body>, (StatusCode, String)> {
-    let cache_max_age = state.config.hash_cache_seconds;
-    let cache_control = format!("public, max-age={}", cache_max_age);
-    // Try to get from retriever chain by hash
-    // The chain will try StorageRetriever (handles both S3 and local storage),
-    // then EmbeddedDefaultSkinRetriever, then other retrievers in order
-    match state.retriever.get_texture_bytes_by_hash(&hash).await {
-        Ok(Some(retrieved)) => {
-            return Ok((
-                [
-                    (header::CONTENT_TYPE, "image/png"),
-                    (header::CACHE_CONTROL, cache_control.as_str()),
-                ],
-                retrieved.bytes,
+/// Read an uploaded file of up to `max_size` bytes in chunks, spilling it to a temporary
+/// file once it is larger than UPLOAD_MEMORY_LIMIT
+async fn spool_upload(
+    field: Field<'_>,
+    config: &Config,
+    max_size: usize,
+) -> Result<UploadedFile, (StatusCode, String)> {
+    let temp_dir = config
+        .upload_temp_dir
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+
+    UploadedFile::spool(field, config.upload_memory_limit, max_size, &temp_dir)
+    .await
+    .map_err(|e| match e {
+        SpoolError::Read(_) | SpoolError::TooLarge(_) => (StatusCode::BAD_REQUEST, e.to_string()),
+        SpoolError::Io(_) => {
+            tracing::error!("Failed to spool upload to {}: {}", temp_dir.display(), e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to buffer upload".to_string(),
             )
-                .into_response());
         }
-        Ok(None) => {
-            tracing::debug!("Retriever chain did not provide texture for hash: {}", hash);
+    })
+}
+
+/// Image sizes accepted for uploads of a texture type
+/// HD textures are power-of-two multiples of the vanilla size
+fn allowed_dimensions(texture_type: TextureType, config: &Config) -> Vec<(u32, u32)> {
+    match texture_type {
+        TextureType::SKIN => {
+            // 64x32 is the legacy pre-1.8 layout
+            let mut sizes = vec![(64, 64), (64, 32)];
+            if config.allow_hd_skins {
+                sizes.extend(hd_scales(MAX_HD_SCALE).map(|scale| (64 * scale, 64 * scale)));
+            }
+            sizes
         }
-        Err(e) => {
-            tracing::warn!("Retriever chain error for hash {}: {}", hash, e);
+        // Elytras use the cape texture layout
+        TextureType::CAPE | TextureType::ELYTRA => {
+            // Only the top-left 22x17 is used, but capes are always stored as 64x32
+            let max_scale = if config.allow_hd_skins {
+                MAX_HD_SCALE
+            } else if config.allow_hd_capes {
+                2
+            } else {
+                1
+            };
+            let mut sizes = vec![(64, 32)];
+            sizes.extend(hd_scales(max_scale).map(|scale| (64 * scale, 32 * scale)));
+            sizes
         }
     }
+}
 
-    // Check database for a texture with this hash to potentially fetch from external URL
-    // This handles cases where textures are stored with http/https URLs (e.g., Mojang API URLs)
-    let texture_record = sqlx::query!(
-        r#"
-        SELECT file_url
-        FROM textures
-        WHERE file_hash = $1
-        LIMIT 1
-        "#,
-        hash
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to query database: {}", e);
+/// HD scale factors: powers of two from 2 up to `max_scale`
+fn hd_scales(max_scale: u32) -> impl Iterator<Item = u32> {
+    std::iter::successors(Some(2u32), |scale| scale.checked_mul(2))
+        .take_while(move |scale| *scale <= max_scale)
+}
+
+/// Reject textures whose dimensions are not allowed for their type
+fn validate_texture_dimensions(
+    data: &[u8],
+    texture_type: TextureType,
+    config: &Config,
+) -> Result<(), (StatusCode, String)> {
+    let allowed = allowed_dimensions(texture_type, config);
+
+    let (width, height) = png_dimensions(data).ok_or_else(|| {
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Database query failed".to_string(),
+            StatusCode::BAD_REQUEST,
+            "Invalid PNG: missing IHDR header".to_string(),
         )
     })?;
 
-    if let Some(record) = texture_record {
-        // If we have a record with an http/https URL, try to fetch from there
-        if record.file_url.starts_with("http://") || record.file_url.starts_with("https://") {
-            tracing::debug!("Attempting to fetch texture from URL: {}", record.file_url);
+    if allowed.contains(&(width, height)) {
+        return Ok(());
+    }
 
-            match download_file_from_url(&record.file_url).await {
-                Ok(Some(bytes)) => {
-                    return Ok((
-                        [
-                            (header::CONTENT_TYPE, "image/png"),
-                            (header::CACHE_CONTROL, cache_control.as_str()),
-                        ],
-                        bytes,
-                    )
-                        .into_response());
-                }
-                Ok(None) => {
-                    tracing::warn!("Failed to download texture from URL: {}", record.file_url);
-                }
-                Err(err) => {
-                    tracing::error!("Error downloading texture from URL: {}", err);
-                }
-            }
-        }
-    }
-
-    // If all attempts fail, return 404
+    let allowed = allowed
+        .iter()
+        .map(|(w, h)| format!("{}x{}", w, h))
+        .collect::<Vec<_>>()
+        .join(", ");
     Err((
-        StatusCode::NOT_FOUND,
-        format!("Texture not found for hash: {}", hash),
+        StatusCode::BAD_REQUEST,
+        format!(
+            "Invalid {} dimensions {}x{}, allowed: {}",
+            texture_type, width, height, allowed
+        ),
     ))
 }
 
-/// GET /api/get/:username/:uuid - Get all textures for a user by username/uuid (admin only)
-/// This endpoint requires an admin token and will update the username<->uuid mapping
-/// Returns the same content as /get/:uuid but updates the unreliable username mapping
-pub async fn get_textures_by_username_uuid(
-    State(state): State<AppState>,
-    AuthAdmin: AuthAdmin,
-    Path((username, user_uuid)): Path<(String, Uuid)>,
-) -> Result<Json<TexturesResponse>, (StatusCode, String)> {
-    // Update or insert the username<->uuid mapping
+/// Who wrote a texture row and how, kept for auditing (RECORD_UPLOAD_SOURCE)
+struct TextureOrigin {
+    /// UUID of the uploading user, or "admin" for the admin API
+    uploaded_by: String,
+    /// upload, admin_upload, mojang or gallery
+    source: &'static str,
+    original_filename: Option<String>,
+}
+
+/// `uploaded_by` of textures written through the admin API
+const ADMIN_UPLOADER: &str = "admin";
+
+/// Origin to record for a texture write, or None if RECORD_UPLOAD_SOURCE is disabled
+fn texture_origin(
+    state: &AppState,
+    uploaded_by: String,
+    source: &'static str,
+    original_filename: Option<&str>,
+) -> Option<TextureOrigin> {
+    state.config().record_upload_source.then(|| TextureOrigin {
+        uploaded_by,
+        source,
+        original_filename: original_filename.map(str::to_string),
+    })
+}
+
+/// Insert or update a user's texture row
+/// `mojang_hash` is the hash from the textures.minecraft.net URL of a texture pulled from
+/// Mojang; textures from anywhere else pass None, which clears a previous Mojang hash
+/// Capes are added to the capes the user owns and become the active one;
+/// other texture types replace the user's previous texture
+/// `origin` is recorded in the audit columns, which are cleared if it is None
+#[allow(clippy::too_many_arguments)]
+async fn upsert_texture<'e>(
+    executor: impl sqlx::PgExecutor<'e>,
+    user_uuid: Uuid,
+    texture_type: TextureType,
+    hash: &str,
+    file_url: &str,
+    metadata: Option<serde_json::Value>,
+    mojang_hash: Option<&str>,
+    origin: Option<&TextureOrigin>,
+) -> sqlx::Result<()> {
+    let uploaded_by = origin.map(|origin| origin.uploaded_by.as_str());
+    let source = origin.map(|origin| origin.source);
+    let original_filename = origin.and_then(|origin| origin.original_filename.as_deref());
+
+    if texture_type == TextureType::CAPE {
+        sqlx::query!(
+            r#"
+            WITH deactivated AS (
+                UPDATE textures SET active = FALSE
+                WHERE user_uuid = $1 AND texture_type = $2 AND file_hash <> $3 AND active
+            )
+            INSERT INTO textures (user_uuid, texture_type, file_hash, file_url, metadata, mojang_hash,
+                uploaded_by, source, original_filename)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (user_uuid, texture_type, file_hash)
+            DO UPDATE SET file_url = $4, metadata = $5, mojang_hash = $6, uploaded_by = $7, source = $8,
+                original_filename = $9, active = TRUE, updated_at = NOW()
+            "#,
+            user_uuid,
+            texture_type.to_string(),
+            hash,
+            file_url,
+            metadata,
+            mojang_hash,
+            uploaded_by,
+            source,
+            original_filename
+        )
+        .execute(executor)
+        .await?;
+
+        return Ok(());
+    }
+
     sqlx::query!(
         r#"
-        INSERT INTO username_mappings (user_uuid, username, updated_at)
-        VALUES ($1, $2, NOW())
-        ON CONFLICT (user_uuid, username)
-        DO UPDATE SET updated_at = NOW()
+        INSERT INTO textures (user_uuid, texture_type, file_hash, file_url, metadata, mojang_hash,
+            uploaded_by, source, original_filename)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (user_uuid, texture_type) WHERE texture_type <> 'CAPE'
+        DO UPDATE SET file_hash = $3, file_url = $4, metadata = $5, mojang_hash = $6, uploaded_by = $7,
+            source = $8, original_filename = $9, updated_at = NOW()
         "#,
         user_uuid,
-        username
+        texture_type.to_string(),
+        hash,
+        file_url,
+        metadata,
+        mojang_hash,
+        uploaded_by,
+        source,
+        original_filename
     )
-    .execute(&state.db)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Best-effort removal of a file stored for a database write that then failed
+/// Files are content-addressed and may be shared, so the file is kept while any
+/// texture, group default skin or gallery cape still references its hash
+/// (or when that cannot be checked). With STORAGE_TYPE_PREFIX this keeps a type's copy
+/// while the hash is referenced as any type. The metadata sidecar, shared by every
+/// texture with the hash, goes together with the file
+async fn remove_orphaned_file(state: &AppState, hash: &str, texture_type: TextureType) {
+    let referenced = sqlx::query_scalar!(
+        r#"
+        SELECT (
+            EXISTS(SELECT 1 FROM textures WHERE file_hash = $1)
+            OR EXISTS(SELECT 1 FROM group_default_skins WHERE skin_hash = $1)
+            OR EXISTS(SELECT 1 FROM cape_gallery WHERE cape_hash = $1)
+        ) AS "referenced!"
+        "#,
+        hash
+    )
+    .fetch_one(&state.db)
+    .await;
+
+    let key = state.storage.texture_key(hash, texture_type);
+    let extension = texture_type.file_extension();
+    match referenced {
+        Ok(false) => {
+            match state.storage.delete_file(&key, extension).await {
+                Ok(()) => tracing::info!("Removed orphaned file {}.{}", key, extension),
+                Err(e) => tracing::warn!(
+                    "Failed to remove orphaned file {}.{}: {}",
+                    key,
+                    extension,
+                    e
+                ),
+            }
+            // Removed even with STORE_METADATA_SIDECAR off, it may predate the setting
+            if let Err(e) = state
+                .storage
+                .delete_file(hash, METADATA_SIDECAR_EXTENSION)
+                .await
+            {
+                tracing::warn!("Failed to remove metadata sidecar of {}: {}", hash, e);
+            }
+        }
+        Ok(true) => {
+            tracing::debug!("Keeping file {}.{}, still referenced", key, extension)
+        }
+        Err(e) => tracing::warn!(
+            "Could not check references to {}.{}, leaving it in storage: {}",
+            key,
+            extension,
+            e
+        ),
+    }
+}
+
+/// Refuse an upload whose hash is in banned_hashes with 403
+/// Files are addressed by hash, so this blocks exact re-uploads of known-bad images
+async fn ensure_hash_not_banned(state: &AppState, hash: &str) -> Result<(), (StatusCode, String)> {
+    let banned = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM banned_hashes WHERE hash = $1) AS "banned!""#,
+        hash
+    )
+    .fetch_one(&state.db)
     .await
     .map_err(|e| {
-        tracing::error!("Failed to update username mapping: {}", e);
+        tracing::error!("Failed to check banned hashes: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to update username mapping".to_string(),
+            "Database query failed".to_string(),
         )
     })?;
 
-    tracing::info!("Updated username mapping: {} <-> {}", username, user_uuid);
+    if banned {
+        tracing::info!("Rejected upload of banned texture {}", hash);
+        return Err((
+            StatusCode::FORBIDDEN,
+            "This texture is banned".to_string(),
+        ));
+    }
 
-    // Now get the textures using the UUID (reuse existing logic)
-    let textures = state
-        .retriever
-        .get_textures(user_uuid)
+    Ok(())
+}
+
+/// Refuse with 409 an upload whose hash is already stored as another texture type, when
+/// REJECT_HASH_TYPE_CONFLICTS is set
+/// Identical bytes share one file and hash, so a skin that is also a cape would make the
+/// metadata served for the hash depend on which of the two is meant
+async fn ensure_no_hash_type_conflict(
+    state: &AppState,
+    hash: &str,
+    texture_type: TextureType,
+) -> Result<(), (StatusCode, String)> {
+    if !state.config().reject_hash_type_conflicts {
+        return Ok(());
+    }
+
+    let existing_type = sqlx::query_scalar!(
+        r#"
+        SELECT texture_type
+        FROM textures
+        WHERE file_hash = $1 AND texture_type <> $2
+        ORDER BY created_at
+        LIMIT 1
+        "#,
+        hash,
+        texture_type.to_string()
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to check texture types of {}: {}", hash, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Database query failed".to_string(),
+        )
+    })?;
+
+    match existing_type {
+        Some(existing_type) => Err((
+            StatusCode::CONFLICT,
+            format!(
+                "This file is already stored as a {} texture and cannot be uploaded as a {}",
+                existing_type, texture_type
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Check an upload before it is stored: refuse banned hashes and, with
+/// REJECT_HASH_TYPE_CONFLICTS, hashes stored as another type, then run the pre-store
+/// moderation check (MODERATION_CHECK_URL)
+/// Rejected textures get 422 and are never stored. If the check itself fails, the upload
+/// is accepted or refused with 503 depending on MODERATION_CHECK_FAIL_OPEN
+async fn check_upload_content(
+    state: &AppState,
+    user_uuid: Uuid,
+    texture_type: TextureType,
+    file: &UploadedFile,
+) -> Result<(), (StatusCode, String)> {
+    ensure_hash_not_banned(state, file.hash()).await?;
+    ensure_no_hash_type_conflict(state, file.hash(), texture_type).await?;
+
+    let Some(moderation_check) = &state.moderation_check else {
+        return Ok(());
+    };
+    let hash = file.hash();
+    let bytes = file.bytes().await.map_err(|e| {
+        tracing::error!("Failed to read spooled upload {}: {}", hash, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read upload".to_string(),
+        )
+    })?;
+
+    match moderation_check.check(user_uuid, texture_type, hash, &bytes).await {
+        Ok(verdict) if verdict.allowed => Ok(()),
+        Ok(verdict) => {
+            tracing::info!(
+                "Moderation rejected {} {} of user {}: {:?}",
+                texture_type,
+                hash,
+                user_uuid,
+                verdict.reason
+            );
+            let message = match verdict.reason {
+                Some(reason) => format!("Upload rejected by moderation: {}", reason),
+                None => "Upload rejected by moderation".to_string(),
+            };
+            Err((StatusCode::UNPROCESSABLE_ENTITY, message))
+        }
+        Err(e) if moderation_check.fails_open() => {
+            tracing::warn!("Moderation check failed, accepting upload {}: {}", hash, e);
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("Moderation check failed for upload {}: {}", hash, e);
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Moderation service unavailable".to_string(),
+            ))
+        }
+    }
+}
+
+/// Have a synchronous moderation webhook (MODERATION_WEBHOOK_SYNC) approve an upload
+/// Called once the file is stored, so the webhook can fetch it, but before it is assigned
+/// to the user; the caller removes the file again on rejection
+async fn review_upload(state: &AppState, event: &UploadEvent) -> Result<(), (StatusCode, String)> {
+    let Some(moderation) = state.moderation.as_ref().filter(|m| m.is_synchronous()) else {
+        return Ok(());
+    };
+
+    match moderation.review(event).await {
+        Ok(()) => Ok(()),
+        Err(e @ ModerationError::Rejected(_)) => {
+            tracing::info!("Upload {} of user {} rejected: {}", event.hash, event.uuid, e);
+            Err((StatusCode::FORBIDDEN, "Upload rejected by moderation".to_string()))
+        }
+        Err(e @ ModerationError::Unavailable(_)) => {
+            tracing::error!("Failed to review upload {}: {}", event.hash, e);
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Moderation service unavailable".to_string(),
+            ))
+        }
+    }
+}
+
+/// Report a saved upload to the moderation webhook in the background
+/// Synchronous webhooks have already seen it in `review_upload`
+fn notify_upload(state: &AppState, event: UploadEvent) {
+    if let Some(moderation) = &state.moderation {
+        if !moderation.is_synchronous() {
+            moderation.notify(event);
+        }
+    }
+}
+
+/// Reject user uploads with 403 while UPLOADS_ENABLED is false, and those of users on
+/// UPLOAD_DENYLIST or in the upload_blocks table
+async fn ensure_upload_allowed(
+    state: &AppState,
+    user_uuid: Uuid,
+) -> Result<(), (StatusCode, String)> {
+    if !state.config().uploads_enabled {
+        tracing::info!("Rejected upload of user {}, uploads are disabled", user_uuid);
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Uploads are disabled".to_string(),
+        ));
+    }
+
+    let blocked = state.config().upload_denylist.contains(&user_uuid)
+        || sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM upload_blocks WHERE user_uuid = $1) AS "blocked!""#,
+            user_uuid
+        )
+        .fetch_one(&state.db)
         .await
         .map_err(|e| {
-            tracing::error!("Failed to retrieve textures: {}", e);
+            tracing::error!("Failed to check upload blocks: {}", e);
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to retrieve textures: {}", e),
+                "Database query failed".to_string(),
             )
         })?;
 
-    let mut response = TexturesResponse {
-        SKIN: None,
-        CAPE: None,
+    if blocked {
+        tracing::info!("Rejected upload of blocked user {}", user_uuid);
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Uploads are blocked for this user".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Drop cached lookups for a user whose textures just changed, so the change is visible at once
+/// Returns the names of the caches that held entries for the user
+fn invalidate_user_caches(state: &AppState, user_uuid: Uuid) -> Vec<String> {
+    let mut invalidated = Vec::new();
+    if let Some(negative_cache) = &state.negative_cache {
+        if negative_cache.invalidate(user_uuid) {
+            invalidated.push("negative".to_string());
+        }
+    }
+    invalidated
+}
+
+/// Write the texture metadata next to the file in storage (STORE_METADATA_SIDECAR)
+/// so storage alone is enough to restore it. The database stays the source of truth,
+/// so a failure is only logged. Textures without metadata get an empty sidecar,
+/// replacing any left from an earlier upload of the same file.
+/// The sidecar is keyed by hash only, so users sharing a file share it, holding the
+/// metadata of whoever uploaded the file last.
+async fn store_metadata_sidecar(
+    state: &AppState,
+    hash: &str,
+    metadata: Option<&TextureMetadata>,
+) {
+    if !state.config().store_metadata_sidecar {
+        return;
+    }
+
+    let sidecar = metadata.cloned().unwrap_or_default();
+    let result = match serde_json::to_vec(&sidecar) {
+        Ok(bytes) => state
+            .storage
+            .store_file(bytes, hash, METADATA_SIDECAR_EXTENSION)
+            .await
+            .map(|_| ())
+            .map_err(anyhow::Error::from),
+        Err(e) => Err(e.into()),
     };
 
-    // Extract SKIN if available
-    if let Some(retrieved) = textures.get("SKIN") {
-        response.SKIN = Some(TextureResponse {
-            url: retrieved.url.clone(),
-            digest: retrieved.hash.clone(),
-            metadata: retrieved.metadata.clone(),
-        });
-    } else {
-        tracing::debug!("No SKIN texture found for user {}", user_uuid);
+    if let Err(e) = result {
+        tracing::warn!("Failed to store metadata sidecar of {}: {}", hash, e);
     }
+}
 
-    // Extract CAPE if available
-    if let Some(retrieved) = textures.get("CAPE") {
-        response.CAPE = Some(TextureResponse {
-            url: retrieved.url.clone(),
-            digest: retrieved.hash.clone(),
-            metadata: retrieved.metadata.clone(),
-        });
-    } else {
-        tracing::debug!("No CAPE texture found for user {}", user_uuid);
+/// Best-effort removal of every file stored by a failed multi-texture upload
+async fn remove_orphaned_files(state: &AppState, stored: &[(String, TextureType)]) {
+    for (hash, texture_type) in stored {
+        remove_orphaned_file(state, hash, *texture_type).await;
     }
+}
 
-    Ok(Json(response))
+/// Smallest possible PNG: the 8-byte signature and a complete IHDR chunk
+const MIN_PNG_SIZE: usize = 33;
+
+/// Why an upload's file field was rejected before its image was decoded
+#[derive(Debug, thiserror::Error)]
+enum UploadFileError {
+    #[error("No file provided")]
+    Missing,
+    #[error("File is empty")]
+    Empty,
+    #[error("File is too small to be a PNG image ({0} bytes)")]
+    TooSmall(usize),
+    #[error("File must be a PNG image")]
+    NotPng,
 }
 
-/// GET /download/username/:texture_type/:username - Download texture by username
-/// This endpoint looks up the UUID from username and returns the texture with cache headers
-/// Cache lifetime is configurable via USERNAME_CACHE_SECONDS (default 8 hours)
-///
-/// Flow:
-/// 1. Try to find username in local mappings
-/// 2. If not found, use the retrieval chain which may include Mojang API resolution
-/// 3. Save the new mapping if chain successfully resolved it
-/// 4. Return the texture with cache headers
-pub async fn download_texture_by_username(
+impl From<UploadFileError> for (StatusCode, String) {
+    fn from(e: UploadFileError) -> Self {
+        (StatusCode::BAD_REQUEST, e.to_string())
+    }
+}
+
+/// Check that an uploaded file is a PNG, telling empty and truncated files apart
+/// from files in some other format
+fn check_png(file: &UploadedFile) -> Result<(), UploadFileError> {
+    match file.size() {
+        0 => Err(UploadFileError::Empty),
+        size if size < MIN_PNG_SIZE => Err(UploadFileError::TooSmall(size)),
+        _ if !is_png(file.head()) => Err(UploadFileError::NotPng),
+        _ => Ok(()),
+    }
+}
+
+/// Check if bytes represent a PNG file
+fn is_png(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && bytes[0..8] == [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]
+}
+
+/// Check if bytes start with a ZIP local file header, as resource packs do
+fn is_zip(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"PK\x03\x04")
+}
+
+/// Read the width and height of a PNG from its IHDR chunk
+/// IHDR must be the first chunk: after the 8-byte signature come its length and type
+/// (4 bytes each), then the width and height as big-endian u32
+fn png_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.len() < 24 || &bytes[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(bytes[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(bytes[20..24].try_into().ok()?);
+    Some((width, height))
+}
+
+/// POST /api/upload/:type - Upload a texture for any user (admin only)
+/// Requires admin bearer token. User UUID is provided in the "user" form field.
+#[tracing::instrument(
+    skip_all,
+    fields(user_uuid = tracing::field::Empty, texture_type = %texture_type_str)
+)]
+pub async fn admin_upload_texture(
     State(state): State<AppState>,
-    Path((texture_type_str, username)): Path<(String, String)>,
-) -> Result<Response<Body>, (StatusCode, String)> {
+    AuthAdmin: AuthAdmin,
+    Path(texture_type_str): Path<String>,
+    mut multipart: Multipart,
+) -> Result<Json<TextureResponse>, (StatusCode, String)> {
     let texture_type: TextureType = texture_type_str.parse().map_err(|e| {
         (
             StatusCode::BAD_REQUEST,
@@ -681,115 +1728,2311 @@ pub async fn download_texture_by_username(
         )
     })?;
 
-    // Try to look up the UUID from username in local database first
-    let user_uuid = match sqlx::query!(
-        r#"
-        SELECT user_uuid
-        FROM username_mappings
-        WHERE username = $1
-        LIMIT 1
+    let mut file: Option<UploadedFile> = None;
+    let mut options: Option<UploadOptions> = None;
+    let mut user_uuid: Option<Uuid> = None;
+    let mut user_username: Option<String> = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid multipart data: {}", e),
+        )
+    })? {
+        let name = field.name().unwrap_or("").to_string();
+
+        match name.as_str() {
+            "file" => {
+                file = Some(read_texture_file(field, texture_type, &state).await?);
+            }
+            "options" => {
+                let json_str = read_text_field(field, "options").await?;
+                options = Some(serde_json::from_str(&json_str).map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid options JSON: {}", e),
+                    )
+                })?);
+            }
+            "uuid" => {
+                let uuid_str = read_text_field(field, "user UUID").await?;
+                user_uuid =
+                    Some(Uuid::parse_str(&uuid_str).map_err(|e| {
+                        (StatusCode::BAD_REQUEST, format!("Invalid user UUID: {}", e))
+                    })?);
+            }
+            "username" => {
+                let username_str = read_text_field(field, "username").await?;
+                user_username = Some(username_str);
+            }
+            _ => {}
+        }
+    }
+
+    let user_uuid = user_uuid.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "User UUID not provided".to_string(),
+        )
+    })?;
+    tracing::Span::current().record("user_uuid", tracing::field::display(user_uuid));
+
+    if let Some(username) = user_username {
+        sqlx::query!(
+            r#"
+        INSERT INTO username_mappings (user_uuid, username, display_username, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (user_uuid, username)
+        DO UPDATE SET display_username = EXCLUDED.display_username, updated_at = NOW()
         "#,
-        username
+            user_uuid,
+            state.config().username_key(&username),
+            username
+        )
+        .execute(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to update username mapping: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update username mapping".to_string(),
+            )
+        })?;
+    }
+
+    let file = file.ok_or(UploadFileError::Missing)?;
+
+    let options = options.unwrap_or_default();
+
+    let hash = file.hash().to_string();
+    check_upload_content(&state, user_uuid, texture_type, &file).await?;
+    let origin = texture_origin(
+        &state,
+        ADMIN_UPLOADER.to_string(),
+        "admin_upload",
+        file.file_name(),
+    );
+
+    // Store file with proper extension
+    let file_url = file
+        .store(state.storage.as_ref(), texture_type)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to store file: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to store file".to_string(),
+            )
+        })?;
+
+    let upload_event = UploadEvent {
+        uuid: user_uuid,
+        texture_type,
+        hash: hash.clone(),
+        url: file_url.clone(),
+    };
+    if let Err(e) = review_upload(&state, &upload_event).await {
+        remove_orphaned_file(&state, &hash, texture_type).await;
+        return Err(e);
+    }
+
+    // Prepare metadata
+    let texture_metadata = options.texture_metadata(texture_type);
+    let metadata = texture_metadata
+        .as_ref()
+        .and_then(|m| serde_json::to_value(m).ok());
+
+    // Insert or update in database
+    if let Err(e) = upsert_texture(
+        &state.db,
+        user_uuid,
+        texture_type,
+        &hash,
+        &file_url,
+        metadata,
+        None,
+        origin.as_ref(),
     )
-    .fetch_optional(&state.db)
     .await
     {
-        Ok(Some(result)) => {
-            tracing::debug!(
-                "Resolved username {} to UUID {} from local mapping",
-                username,
-                result.user_uuid
-            );
-            Some(result.user_uuid)
+        tracing::error!("Failed to save texture: {}", e);
+        remove_orphaned_file(&state, &hash, texture_type).await;
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to save texture".to_string(),
+        ));
+    }
+
+    store_metadata_sidecar(&state, &hash, texture_metadata.as_ref()).await;
+    invalidate_user_caches(&state, user_uuid);
+    notify_upload(&state, upload_event);
+
+    Ok(Json(TextureResponse {
+        url: signed_texture_url(&state, file_url),
+        digest: hash,
+        metadata: texture_metadata,
+        source: None,
+    }))
+}
+
+/// POST /api/upload-multi - Upload several textures for a user in one request (admin only)
+/// Form fields: "uuid", optional "username", one file field per texture type ("skin", "cape")
+/// and optional per-file options ("skin_options", "cape_options").
+/// All database writes happen in a single transaction, so either every texture is saved or none is.
+#[tracing::instrument(skip_all, fields(user_uuid = tracing::field::Empty))]
+pub async fn admin_upload_multi(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    mut multipart: Multipart,
+) -> Result<Json<TexturesResponse>, (StatusCode, String)> {
+    let mut files: HashMap<TextureType, UploadedFile> = HashMap::new();
+    let mut options: HashMap<TextureType, UploadOptions> = HashMap::new();
+    let mut user_uuid: Option<Uuid> = None;
+    let mut user_username: Option<String> = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid multipart data: {}", e),
+        )
+    })? {
+        let name = field.name().unwrap_or("").to_string();
+
+        match name.as_str() {
+            "uuid" => {
+                let uuid_str = read_text_field(field, "user UUID").await?;
+                user_uuid =
+                    Some(Uuid::parse_str(&uuid_str).map_err(|e| {
+                        (StatusCode::BAD_REQUEST, format!("Invalid user UUID: {}", e))
+                    })?);
+            }
+            "username" => {
+                user_username = Some(read_text_field(field, "username").await?);
+            }
+            _ => {
+                if let Some(type_name) = name.strip_suffix("_options") {
+                    let Ok(texture_type) = type_name.parse::<TextureType>() else {
+                        continue;
+                    };
+                    let json_str = read_text_field(field, &name).await?;
+                    let texture_options = serde_json::from_str(&json_str).map_err(|e| {
+                        (
+                            StatusCode::BAD_REQUEST,
+                            format!("Invalid {} JSON: {}", name, e),
+                        )
+                    })?;
+                    options.insert(texture_type, texture_options);
+                } else if let Ok(texture_type) = name.parse::<TextureType>() {
+                    if files.contains_key(&texture_type) {
+                        return Err((
+                            StatusCode::BAD_REQUEST,
+                            format!("Duplicate {} file provided", texture_type),
+                        ));
+                    }
+                    files.insert(
+                        texture_type,
+                        read_texture_file(field, texture_type, &state).await?,
+                    );
+                }
+            }
         }
-        Ok(None) => {
-            tracing::debug!("Username {} not found in local mappings", username);
-            None
+    }
+
+    let user_uuid = user_uuid.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "User UUID not provided".to_string(),
+        )
+    })?;
+    tracing::Span::current().record("user_uuid", tracing::field::display(user_uuid));
+
+    if files.is_empty() {
+        return Err(UploadFileError::Missing.into());
+    }
+
+    let mut tx = state.db.begin().await.map_err(|e| {
+        tracing::error!("Failed to start transaction: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to save textures".to_string(),
+        )
+    })?;
+
+    if let Some(username) = user_username {
+        sqlx::query!(
+            r#"
+        INSERT INTO username_mappings (user_uuid, username, display_username, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (user_uuid, username)
+        DO UPDATE SET display_username = EXCLUDED.display_username, updated_at = NOW()
+        "#,
+            user_uuid,
+            state.config().username_key(&username),
+            username
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to update username mapping: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to update username mapping".to_string(),
+            )
+        })?;
+    }
+
+    let mut response = TexturesResponse::default();
+    // Files written so far, removed again if the request fails before commit
+    let mut stored: Vec<(String, TextureType)> = Vec::new();
+    // Metadata sidecars to write once the textures are committed
+    let mut sidecars: Vec<(String, Option<TextureMetadata>)> = Vec::new();
+    // Uploads to report to the moderation webhook once committed
+    let mut upload_events: Vec<UploadEvent> = Vec::new();
+
+    for texture_type in TextureType::ALL {
+        let Some(file) = files.remove(&texture_type) else {
+            continue;
+        };
+        let texture_options = options.remove(&texture_type).unwrap_or_default();
+
+        let hash = file.hash().to_string();
+        let origin = texture_origin(
+            &state,
+            ADMIN_UPLOADER.to_string(),
+            "admin_upload",
+            file.file_name(),
+        );
+
+        let checked = check_upload_content(&state, user_uuid, texture_type, &file).await;
+        if let Err(e) = checked {
+            drop(tx);
+            remove_orphaned_files(&state, &stored).await;
+            return Err(e);
         }
-        Err(e) => {
-            tracing::error!("Failed to lookup username: {}", e);
+
+        let file_url = match file.store(state.storage.as_ref(), texture_type).await {
+            Ok(file_url) => file_url,
+            Err(e) => {
+                tracing::error!("Failed to store file: {}", e);
+                drop(tx);
+                remove_orphaned_files(&state, &stored).await;
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to store file".to_string(),
+                ));
+            }
+        };
+        stored.push((hash.clone(), texture_type));
+
+        let upload_event = UploadEvent {
+            uuid: user_uuid,
+            texture_type,
+            hash: hash.clone(),
+            url: file_url.clone(),
+        };
+        if let Err(e) = review_upload(&state, &upload_event).await {
+            drop(tx);
+            remove_orphaned_files(&state, &stored).await;
+            return Err(e);
+        }
+        upload_events.push(upload_event);
+
+        let texture_metadata = texture_options.texture_metadata(texture_type);
+        let metadata = texture_metadata
+            .as_ref()
+            .and_then(|m| serde_json::to_value(m).ok());
+
+        if let Err(e) = upsert_texture(
+            &mut *tx,
+            user_uuid,
+            texture_type,
+            &hash,
+            &file_url,
+            metadata,
+            None,
+            origin.as_ref(),
+        )
+        .await
+        {
+            tracing::error!("Failed to save texture: {}", e);
+            // Dropping the transaction rolls back the writes made so far
+            drop(tx);
+            remove_orphaned_files(&state, &stored).await;
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to lookup username".to_string(),
+                "Failed to save texture".to_string(),
             ));
         }
-    };
 
-    // If we have a local mapping, use it directly
-    let retrieved = if let Some(uuid) = user_uuid {
-        // Use the retriever chain with the UUID
-        state
-            .retriever
-            .get_texture_bytes(uuid, texture_type)
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to retrieve texture: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to retrieve texture: {}", e),
-                )
-            })?
-            .ok_or_else(|| {
-                tracing::debug!("Texture not found for {} {}", texture_type_str, uuid);
-                (
-                    StatusCode::NOT_FOUND,
-                    format!("Texture not found for {}", texture_type_str),
-                )
-            })?
-    } else {
-        // No local mapping, try the retrieval chain with username
-        // The chain may include MojangRetriever which can resolve usernames
-        tracing::info!(
-            "Attempting to retrieve texture for username {} via retrieval chain",
-            username
-        );
+        sidecars.push((hash.clone(), texture_metadata.clone()));
+
+        let texture_response = TextureResponse {
+            url: signed_texture_url(&state, file_url),
+            digest: hash,
+            metadata: texture_metadata,
+            source: None,
+        };
+
+        response.set(texture_type, texture_response);
+    }
+
+    if let Err(e) = tx.commit().await {
+        tracing::error!("Failed to commit textures: {}", e);
+        remove_orphaned_files(&state, &stored).await;
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to save textures".to_string(),
+        ));
+    }
+
+    for (hash, metadata) in &sidecars {
+        store_metadata_sidecar(&state, hash, metadata.as_ref()).await;
+    }
+    invalidate_user_caches(&state, user_uuid);
+    for upload_event in upload_events {
+        notify_upload(&state, upload_event);
+    }
+
+    Ok(Json(response))
+}
+
+/// Cache-Control of a /download/:hash response
+/// Signed URLs (DOWNLOAD_SIGNING_SECRET) are checked, and the max-age capped to their
+/// expiry, by the `require_download_signature` middleware
+fn download_cache_control(state: &AppState) -> String {
+    format!("public, max-age={}", state.config().hash_cache_seconds)
+}
+
+/// HEAD /download/:hash - Headers of GET /download/:hash without the body
+/// Files in storage are answered from their size alone; anything else (default skins,
+/// Mojang hashes, external URLs) goes through the GET handler with the body dropped
+#[tracing::instrument(skip_all, fields(%hash))]
+pub async fn head_download_by_hash(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let cache_control = download_cache_control(&state);
+
+    match state.storage.file_size_any_type(&hash).await {
+        Ok(Some((size, _))) => {
+            let response = head_response(DEFAULT_CONTENT_TYPE, size, &hash, Some(cache_control));
+            let filename = format!("{}.png", hash);
+            return Ok(with_attachment(response, query.download, &filename));
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to get size of {}: {}", hash, e),
+    }
+
+    download_by_hash(State(state), Path(hash), Query(query)).await
+}
+
+/// GET /download/:hash - Download skin by hash
+/// Uses the retrieval chain to get texture bytes by hash (StorageRetriever, EmbeddedDefaultSkinRetriever, etc.)
+/// Falls back to http/https download if the texture has an external URL in the database
+#[tracing::instrument(skip_all, fields(%hash))]
+pub async fn download_by_hash(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let filename = format!("{}.png", hash);
+    let response = texture_by_hash(&state, &hash).await?;
+    Ok(with_attachment(response, query.download, &filename))
+}
+
+/// Body of GET /download/:hash, see `download_by_hash`
+async fn texture_by_hash(state: &AppState, hash: &str) -> Result<Response<Body>, (StatusCode, String)> {
+    let cache_control = download_cache_control(state);
+    // Try to get from retriever chain by hash
+    // The chain will try StorageRetriever (handles both S3 and local storage),
+    // then EmbeddedDefaultSkinRetriever, then other retrievers in order
+    match state.retriever.get_texture_bytes_by_hash(hash).await {
+        Ok(Some(retrieved)) => {
+            return Ok((
+                [
+                    (header::CONTENT_TYPE, DEFAULT_CONTENT_TYPE.to_string()),
+                    (header::CACHE_CONTROL, cache_control),
+                    (header::ETAG, hash_etag(&retrieved.hash)),
+                ],
+                retrieved.bytes,
+            )
+                .into_response());
+        }
+        Ok(None) => {
+            tracing::debug!("Retriever chain did not provide texture for hash: {}", hash);
+        }
+        Err(e) => {
+            tracing::warn!("Retriever chain error for hash {}: {}", hash, e);
+        }
+    }
+
+    // Legacy clients may ask for the hash from the Mojang texture URL rather than ours
+    let mojang_record = sqlx::query!(
+        r#"
+        SELECT file_hash, texture_type
+        FROM textures
+        WHERE mojang_hash = $1
+        ORDER BY created_at
+        LIMIT 1
+        "#,
+        hash
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to query database: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Database query failed".to_string(),
+        )
+    })?;
 
+    if let Some(record) = mojang_record {
+        let texture_type: TextureType = record.texture_type.parse().unwrap_or(TextureType::SKIN);
         match state
-            .retriever
-            .get_texture_bytes_by_username(&username, texture_type)
+            .storage
+            .get_texture(&record.file_hash, texture_type)
             .await
         {
-            Ok(Some(texture_bytes)) => {
-                tracing::info!(
-                    "Successfully retrieved texture for username {} via retrieval chain",
-                    username
-                );
-
-                // If the retrieval succeeded, we might have resolved a UUID
-                // Try to save the mapping if we can extract it (optional optimization)
-                // For now, just return the texture
-                texture_bytes
-            }
-            Ok(None) => {
-                tracing::debug!(
-                    "Retrieval chain could not find texture for username {}",
-                    username
-                );
-                return Err((
-                    StatusCode::NOT_FOUND,
-                    format!("Username '{}' not found", username),
-                ));
+            Ok(bytes) => {
+                tracing::debug!("Resolved Mojang hash {} to {}", hash, record.file_hash);
+                return Ok((
+                    [
+                        (header::CONTENT_TYPE, texture_type.content_type().to_string()),
+                        (header::CACHE_CONTROL, cache_control.clone()),
+                        (header::ETAG, hash_etag(&record.file_hash)),
+                    ],
+                    bytes,
+                )
+                    .into_response());
             }
             Err(e) => {
-                tracing::error!("Failed to retrieve texture via chain: {}", e);
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to retrieve texture: {}", e),
-                ));
+                tracing::warn!(
+                    "Mojang hash {} maps to {} but the file is not in storage: {}",
+                    hash,
+                    record.file_hash,
+                    e
+                );
             }
         }
-    };
-
-    // Calculate cache max-age from config
-    let cache_max_age = state.config.username_cache_seconds;
-    let cache_control = format!("private, max-age={}", cache_max_age);
+    }
 
-    Ok((
-        [
-            (header::CONTENT_TYPE, "image/png"),
-            (header::CACHE_CONTROL, cache_control.as_str()),
-        ],
-        retrieved.bytes,
+    // Check database for a texture with this hash to potentially fetch from external URL
+    // This handles cases where textures are stored with http/https URLs (e.g., Mojang API URLs)
+    let texture_record = sqlx::query!(
+        r#"
+        SELECT file_url
+        FROM textures
+        WHERE file_hash = $1
+        ORDER BY created_at
+        LIMIT 1
+        "#,
+        hash
     )
-        .into_response())
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to query database: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Database query failed".to_string(),
+        )
+    })?;
+
+    if let Some(record) = texture_record {
+        // If we have a record with an http/https URL, try to fetch from there
+        if record.file_url.starts_with("http://") || record.file_url.starts_with("https://") {
+            tracing::debug!("Attempting to fetch texture from URL: {}", record.file_url);
+
+            match download_file_from_url(&record.file_url).await {
+                Ok(Some(bytes)) => {
+                    return Ok((
+                        [
+                            (header::CONTENT_TYPE, DEFAULT_CONTENT_TYPE),
+                            (header::CACHE_CONTROL, cache_control.as_str()),
+                        ],
+                        bytes,
+                    )
+                        .into_response());
+                }
+                Ok(None) => {
+                    tracing::warn!("Failed to download texture from URL: {}", record.file_url);
+                }
+                Err(err) => {
+                    tracing::error!("Error downloading texture from URL: {}", err);
+                }
+            }
+        }
+    }
+
+    // The texture type is unknown here, so a miss is served as the default skin
+    if let Some(response) = default_on_miss(state, TextureType::SKIN, None).await {
+        return Ok(response);
+    }
+
+    // If all attempts fail, return 404
+    Err((
+        StatusCode::NOT_FOUND,
+        format!("Texture not found for hash: {}", hash),
+    ))
+}
+
+/// Serve the default texture for a download that found nothing, if SERVE_DEFAULT_ON_MISS is enabled
+/// Returns None when disabled, when the type has no default (capes unless DEFAULT_CAPE_URL is set),
+/// or when the default cannot be fetched, so the caller answers 404
+async fn default_on_miss(
+    state: &AppState,
+    texture_type: TextureType,
+    user_uuid: Option<Uuid>,
+) -> Option<Response<Body>> {
+    let fallback = state.miss_fallback.as_ref()?;
+
+    let retrieved = match fallback.texture_bytes(texture_type, user_uuid).await {
+        Ok(retrieved) => retrieved?,
+        Err(e) => {
+            tracing::warn!("Failed to fetch default {} for a miss: {}", texture_type, e);
+            return None;
+        }
+    };
+
+    // Short lifetime, so a texture uploaded later replaces the default quickly
+    let cache_control = format!(
+        "public, max-age={}",
+        state.config().default_on_miss_cache_seconds
+    );
+
+    Some(
+        (
+            [
+                (header::CONTENT_TYPE, texture_type.content_type()),
+                (header::CACHE_CONTROL, cache_control.as_str()),
+            ],
+            retrieved.bytes,
+        )
+            .into_response(),
+    )
+}
+
+/// GET /api/get/:username/:uuid - Get all textures for a user by username/uuid (admin only)
+/// This endpoint requires an admin token and will update the username<->uuid mapping
+/// Returns the same content as /get/:uuid but updates the unreliable username mapping
+/// With ?debug=true, each texture includes the retriever that provided it
+#[tracing::instrument(skip_all, fields(%user_uuid, %username))]
+pub async fn get_textures_by_username_uuid(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    UuidPath((username, user_uuid)): UuidPath<(String, Uuid)>,
+    Query(query): Query<TextureQuery>,
+) -> Result<Json<TexturesResponse>, (StatusCode, String)> {
+    let include_source = query.debug;
+    // Update or insert the username<->uuid mapping
+    sqlx::query!(
+        r#"
+        INSERT INTO username_mappings (user_uuid, username, display_username, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (user_uuid, username)
+        DO UPDATE SET display_username = EXCLUDED.display_username, updated_at = NOW()
+        "#,
+        user_uuid,
+        state.config().username_key(&username),
+        username
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to update username mapping: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update username mapping".to_string(),
+        )
+    })?;
+
+    tracing::info!("Updated username mapping: {} <-> {}", username, user_uuid);
+
+    // Now get the textures using the UUID (reuse existing logic)
+    let textures = state
+        .retriever
+        .get_textures(user_uuid)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to retrieve textures: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to retrieve textures: {}", e),
+            )
+        })?;
+
+    let mut response = TexturesResponse::default();
+
+    // Extract each texture type if available
+    for texture_type in TextureType::ALL {
+        match textures.get(&texture_type.to_string()) {
+            Some(retrieved) => response.set(
+                texture_type,
+                TextureResponse {
+                    url: signed_texture_url(&state, retrieved.url.clone()),
+                    digest: retrieved.hash.clone(),
+                    metadata: retrieved.metadata.clone(),
+                    source: include_source.then(|| retrieved.source.clone()),
+                },
+            ),
+            None => tracing::debug!("No {} texture found for user {}", texture_type, user_uuid),
+        }
+    }
+
+    Ok(Json(response))
+}
+
+/// UUID stored for a username in `username_mappings`, if any
+async fn lookup_username_mapping(
+    state: &AppState,
+    username: &str,
+) -> Result<Option<Uuid>, (StatusCode, String)> {
+    match sqlx::query!(
+        r#"
+        SELECT user_uuid
+        FROM username_mappings
+        WHERE username = $1
+        LIMIT 1
+        "#,
+        state.config().username_key(username)
+    )
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(Some(result)) => {
+            tracing::debug!(
+                "Resolved username {} to UUID {} from local mapping",
+                username,
+                result.user_uuid
+            );
+            Ok(Some(result.user_uuid))
+        }
+        Ok(None) => {
+            tracing::debug!("Username {} not found in local mappings", username);
+            Ok(None)
+        }
+        Err(e) => {
+            tracing::error!("Failed to lookup username: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to lookup username".to_string(),
+            ))
+        }
+    }
+}
+
+/// Resolve a username with Mojang and make it the only mapping of that username
+/// Mappings of the name to other accounts are dropped, since a username belongs to one
+/// account at a time, as are other spellings of it with CASE_INSENSITIVE_USERNAMES
+async fn refresh_username_mapping(
+    state: &AppState,
+    username: &str,
+) -> Result<Uuid, (StatusCode, String)> {
+    let user_uuid = state
+        .mojang
+        .resolve_username_to_uuid(username)
+        .await
+        .map_err(|e| {
+            if let Some(MojangError::RateLimited(_)) = e.downcast_ref::<MojangError>() {
+                return (StatusCode::TOO_MANY_REQUESTS, e.to_string());
+            }
+            tracing::error!("Failed to resolve username with Mojang: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to resolve username: {}", e),
+            )
+        })?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Username '{}' not found", username),
+            )
+        })?;
+
+    let db_error = |e: sqlx::Error| {
+        tracing::error!("Failed to update username mapping: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update username mapping".to_string(),
+        )
+    };
+
+    let mut tx = state.db.begin().await.map_err(db_error)?;
+    sqlx::query!(
+        r#"
+        DELETE FROM username_mappings
+        WHERE username = $1 AND user_uuid <> $2
+        "#,
+        state.config().username_key(&username),
+        user_uuid
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO username_mappings (user_uuid, username, display_username, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (user_uuid, username)
+        DO UPDATE SET display_username = EXCLUDED.display_username, updated_at = NOW()
+        "#,
+        user_uuid,
+        state.config().username_key(&username),
+        username
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+    tx.commit().await.map_err(db_error)?;
+
+    tracing::info!("Refreshed username mapping: {} <-> {}", username, user_uuid);
+    Ok(user_uuid)
+}
+
+/// GET /download/username/:texture_type/:username - Download texture by username
+/// This endpoint looks up the UUID from username and returns the texture with cache headers
+/// Cache lifetime is configurable via USERNAME_CACHE_SECONDS (default 8 hours)
+///
+/// Flow:
+/// 1. Try to find username in local mappings
+/// 2. If not found, use the retrieval chain which may include Mojang API resolution
+/// 3. Save the new mapping if chain successfully resolved it
+/// 4. Return the texture with cache headers
+///
+/// With ?refresh=true and an admin token, the local mapping is skipped: the username is
+/// resolved with Mojang and the mapping replaced, for usernames that moved to another
+/// account. Anyone could otherwise spend the shared Mojang rate limit and force writes
+#[tracing::instrument(skip_all, fields(texture_type = %texture_type_str, %username))]
+pub async fn download_texture_by_username(
+    State(state): State<AppState>,
+    Path((texture_type_str, username)): Path<(String, String)>,
+    Query(query): Query<UsernameDownloadQuery>,
+    admin: Result<AuthAdmin, (StatusCode, String)>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    if query.refresh {
+        admin?;
+    }
+
+    let texture_type: TextureType = texture_type_str.parse().map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid texture type: {}", e),
+        )
+    })?;
+
+    // Try to look up the UUID from username in local database first
+    let user_uuid = if query.refresh {
+        Some(refresh_username_mapping(&state, &username).await?)
+    } else {
+        lookup_username_mapping(&state, &username).await?
+    };
+
+    // If we have a local mapping, use it directly
+    let retrieved = if let Some(uuid) = user_uuid {
+        // Use the retriever chain with the UUID
+        let retrieved = state
+            .retriever
+            .get_texture_bytes(uuid, texture_type)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to retrieve texture: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to retrieve texture: {}", e),
+                )
+            })?;
+
+        match retrieved {
+            Some(retrieved) => retrieved,
+            None => {
+                tracing::debug!("Texture not found for {} {}", texture_type_str, uuid);
+                return default_on_miss(&state, texture_type, Some(uuid))
+                    .await
+                    .ok_or_else(|| {
+                        (
+                            StatusCode::NOT_FOUND,
+                            format!("Texture not found for {}", texture_type_str),
+                        )
+                    });
+            }
+        }
+    } else {
+        // No local mapping, try the retrieval chain with username
+        // The chain may include MojangRetriever which can resolve usernames
+        tracing::info!(
+            "Attempting to retrieve texture for username {} via retrieval chain",
+            username
+        );
+
+        match state
+            .retriever
+            .get_texture_bytes_by_username(&username, texture_type)
+            .await
+        {
+            Ok(Some(texture_bytes)) => {
+                tracing::info!(
+                    "Successfully retrieved texture for username {} via retrieval chain",
+                    username
+                );
+
+                // If the retrieval succeeded, we might have resolved a UUID
+                // Try to save the mapping if we can extract it (optional optimization)
+                // For now, just return the texture
+                texture_bytes
+            }
+            Ok(None) => {
+                tracing::debug!(
+                    "Retrieval chain could not find texture for username {}",
+                    username
+                );
+                return default_on_miss(&state, texture_type, None)
+                    .await
+                    .ok_or_else(|| {
+                        (
+                            StatusCode::NOT_FOUND,
+                            format!("Username '{}' not found", username),
+                        )
+                    });
+            }
+            Err(e) => {
+                tracing::error!("Failed to retrieve texture via chain: {}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to retrieve texture: {}", e),
+                ));
+            }
+        }
+    };
+
+    // Calculate cache max-age from config
+    let cache_max_age = state.config().username_cache_seconds;
+    let cache_control = format!("private, max-age={}", cache_max_age);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, texture_type.content_type()),
+            (header::CACHE_CONTROL, cache_control.as_str()),
+        ],
+        retrieved.bytes,
+    )
+        .into_response())
+}
+
+/// POST /api/refresh/:uuid - Re-pull a user's textures from Mojang into storage (admin only)
+/// Uses the Mojang retriever directly even if it is not part of the configured chain,
+/// stores the downloaded SKIN/CAPE files and upserts the textures rows
+#[tracing::instrument(skip_all, fields(%user_uuid))]
+pub async fn refresh_textures_from_mojang(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    UuidPath(user_uuid): UuidPath<Uuid>,
+) -> Result<Json<TexturesResponse>, (StatusCode, String)> {
+    let textures = state.mojang.get_textures(user_uuid).await.map_err(|e| {
+        // Let the caller retry later instead of reporting a server failure
+        if let Some(MojangError::RateLimited(_)) = e.downcast_ref::<MojangError>() {
+            return (StatusCode::TOO_MANY_REQUESTS, e.to_string());
+        }
+        tracing::error!("Failed to fetch textures from Mojang: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to fetch textures from Mojang: {}", e),
+        )
+    })?;
+
+    let mut response = TexturesResponse::default();
+
+    for (texture_type_str, retrieved) in textures {
+        let texture_type: TextureType = match texture_type_str.parse() {
+            Ok(texture_type) => texture_type,
+            Err(_) => {
+                tracing::debug!("Skipping unsupported Mojang texture type {}", texture_type_str);
+                continue;
+            }
+        };
+
+        let file_bytes = download_file_from_url(&retrieved.url)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to download texture from Mojang: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to download texture from Mojang: {}", e),
+                )
+            })?
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("Texture not found at {}", retrieved.url),
+                )
+            })?;
+
+        let hash = state.storage.calculate_hash(&file_bytes);
+
+        let key = state.storage.texture_key(&hash, texture_type);
+        let file_url = state
+            .storage
+            .store_file(file_bytes, &key, texture_type.file_extension())
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to store file: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to store file".to_string(),
+                )
+            })?;
+
+        let metadata = retrieved
+            .metadata
+            .as_ref()
+            .and_then(|m| serde_json::to_value(m).ok());
+
+        // Keep the hash from the Mojang URL so legacy clients can download by it
+        let mojang_hash = Some(retrieved.hash.as_str()).filter(|h| !h.is_empty());
+
+        if let Err(e) = upsert_texture(
+            &state.db,
+            user_uuid,
+            texture_type,
+            &hash,
+            &file_url,
+            metadata,
+            mojang_hash,
+            texture_origin(&state, ADMIN_UPLOADER.to_string(), "mojang", None).as_ref(),
+        )
+        .await
+        {
+            tracing::error!("Failed to save texture: {}", e);
+            remove_orphaned_file(&state, &hash, texture_type).await;
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to save texture".to_string(),
+            ));
+        }
+
+        tracing::info!(
+            "Refreshed {} for user {} from Mojang: {}",
+            texture_type,
+            user_uuid,
+            hash
+        );
+
+        store_metadata_sidecar(&state, &hash, retrieved.metadata.as_ref()).await;
+
+        let texture_response = TextureResponse {
+            url: signed_texture_url(&state, file_url),
+            digest: hash,
+            metadata: retrieved.metadata,
+            source: None,
+        };
+
+        response.set(texture_type, texture_response);
+    }
+
+    invalidate_user_caches(&state, user_uuid);
+
+    Ok(Json(response))
+}
+
+/// GET /api/readonly - Whether the service is in read-only maintenance mode (admin only)
+pub async fn admin_get_read_only(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+) -> Json<ReadOnlyState> {
+    Json(ReadOnlyState {
+        read_only: state.read_only.load(Ordering::Relaxed),
+    })
+}
+
+/// POST /api/readonly - Enter or leave read-only maintenance mode (admin only)
+/// Body `{"read_only": true}`; the switch is not persisted, restarts go back to READ_ONLY
+#[tracing::instrument(skip_all, fields(read_only = request.read_only))]
+pub async fn admin_set_read_only(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    Json(request): Json<ReadOnlyState>,
+) -> Json<ReadOnlyState> {
+    let was_read_only = state.read_only.swap(request.read_only, Ordering::Relaxed);
+    if was_read_only != request.read_only {
+        tracing::warn!(
+            "Read-only maintenance mode {}",
+            if request.read_only { "enabled" } else { "disabled" }
+        );
+    }
+
+    Json(request)
+}
+
+/// POST /api/reload - Re-read the configuration and apply what can change at runtime (admin only)
+/// Values in the .env file take precedence over the process environment here, since the
+/// environment of a running process cannot be changed from outside. Cache lifetimes, default
+/// textures and upload settings are swapped in; the rest needs a restart and is ignored
+#[tracing::instrument(skip_all)]
+pub async fn admin_reload_config(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+) -> Result<Json<ReloadResponse>, (StatusCode, String)> {
+    let reloaded = Config::from_env_and_dotenv()
+        .and_then(|config| config.validate().map(|()| config))
+        .map_err(|e| {
+            tracing::warn!("Configuration reload rejected: {}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid configuration: {}", e),
+            )
+        })?;
+
+    let (changed, restart_required) = {
+        let mut current = state.config.write().unwrap();
+        let mut config = Config::clone(&current);
+        let changed = config.apply_reloadable(&reloaded);
+        // Whatever still differs is a setting that was not applied
+        let restart_required = config != reloaded;
+
+        state.default_skin.reload(&config);
+        // Keeps a default skin retriever of its own for groups without a skin
+        if let Some(group_default_skin) =
+            find_retriever::<GroupDefaultSkinRetriever>(&state.retriever)
+        {
+            group_default_skin.reload(&config);
+        }
+        if let Some(miss_fallback) = &state.miss_fallback {
+            miss_fallback.reload(&config);
+        }
+        *current = Arc::new(config);
+        (changed, restart_required)
+    };
+
+    tracing::info!("Reloaded configuration, changed: {:?}", changed);
+    if restart_required {
+        tracing::warn!("Configuration reload ignored settings that need a restart");
+    }
+
+    Ok(Json(ReloadResponse {
+        reloaded: changed,
+        restart_required,
+        note: restart_required.then(|| {
+            "Settings that are set up at startup, e.g. DATABASE_URL or STORAGE_TYPE, \
+             changed but were not applied; restart the service to apply them"
+                .to_string()
+        }),
+    }))
+}
+
+/// POST /api/invalidate/:uuid - Evict every in-memory cache entry of a user (admin only)
+/// For forcing a fresh lookup when a player reports a stale texture
+#[tracing::instrument(skip_all, fields(%user_uuid))]
+pub async fn admin_invalidate_user(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    UuidPath(user_uuid): UuidPath<Uuid>,
+) -> Json<InvalidateResponse> {
+    let invalidated = invalidate_user_caches(&state, user_uuid);
+    tracing::info!("Invalidated caches of user {}: {:?}", user_uuid, invalidated);
+
+    Json(InvalidateResponse {
+        uuid: user_uuid,
+        invalidated,
+    })
+}
+
+/// POST /api/block/:uuid - Block a user from uploading textures (admin only)
+/// An optional JSON body `{"reason": "..."}` is stored with the block; a body that is
+/// present but malformed is rejected with 400
+#[tracing::instrument(skip_all, fields(%user_uuid))]
+pub async fn admin_block_uploads(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    UuidPath(user_uuid): UuidPath<Uuid>,
+    OptionalJson(request): OptionalJson<BlockUploadsRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let reason = request.and_then(|request| request.reason);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO upload_blocks (user_uuid, reason)
+        VALUES ($1, $2)
+        ON CONFLICT (user_uuid)
+        DO UPDATE SET reason = $2
+        "#,
+        user_uuid,
+        reason
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to block uploads: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to block uploads".to_string(),
+        )
+    })?;
+
+    tracing::info!("Blocked uploads of user {}", user_uuid);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/block/:uuid - Allow a blocked user to upload again (admin only)
+/// Only lifts blocks made through the API, not UPLOAD_DENYLIST entries
+#[tracing::instrument(skip_all, fields(%user_uuid))]
+pub async fn admin_unblock_uploads(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    UuidPath(user_uuid): UuidPath<Uuid>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let result = sqlx::query!("DELETE FROM upload_blocks WHERE user_uuid = $1", user_uuid)
+        .execute(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to unblock uploads: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to unblock uploads".to_string(),
+            )
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "User is not blocked".to_string(),
+        ));
+    }
+
+    tracing::info!("Unblocked uploads of user {}", user_uuid);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /api/banned-hashes/:hash - Ban a texture hash from being uploaded (admin only)
+/// An optional JSON body `{"reason": "..."}` is stored with the ban; a body that is
+/// present but malformed is rejected with 400.
+/// Files already stored under the hash are left alone
+#[tracing::instrument(skip_all, fields(%hash))]
+pub async fn admin_ban_hash(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    Path(hash): Path<String>,
+    OptionalJson(request): OptionalJson<BanHashRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let hash = normalize_texture_hash(&hash)?;
+    let reason = request.and_then(|request| request.reason);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO banned_hashes (hash, reason)
+        VALUES ($1, $2)
+        ON CONFLICT (hash)
+        DO UPDATE SET reason = $2
+        "#,
+        hash,
+        reason
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to ban hash: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to ban hash".to_string(),
+        )
+    })?;
+
+    tracing::info!("Banned texture hash {}", hash);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /api/banned-hashes/:hash - Allow uploads of a banned hash again (admin only)
+#[tracing::instrument(skip_all, fields(%hash))]
+pub async fn admin_unban_hash(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    Path(hash): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let hash = normalize_texture_hash(&hash)?;
+
+    let result = sqlx::query!("DELETE FROM banned_hashes WHERE hash = $1", hash)
+        .execute(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to unban hash: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to unban hash".to_string(),
+            )
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Hash is not banned".to_string()));
+    }
+
+    tracing::info!("Unbanned texture hash {}", hash);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Check that a path parameter is a SHA256 texture hash, lowercased like stored hashes
+fn normalize_texture_hash(hash: &str) -> Result<String, (StatusCode, String)> {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Invalid hash: expected 64 hex characters".to_string(),
+        ));
+    }
+    Ok(hash.to_ascii_lowercase())
+}
+
+/// GET /api/sign/:hash - Create a signed /download/:hash URL (admin only)
+/// For backends handing out download links when DOWNLOAD_SIGNING_SECRET is set
+#[tracing::instrument(skip_all, fields(%hash))]
+pub async fn admin_sign_download_url(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    Path(hash): Path<String>,
+) -> Result<Json<SignedUrlResponse>, (StatusCode, String)> {
+    let signer = state.download_signer.as_ref().ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "Download signing is not configured (DOWNLOAD_SIGNING_SECRET)".to_string(),
+        )
+    })?;
+
+    if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid hash".to_string()));
+    }
+
+    let (url, expires_at) =
+        signer.signed_url(&state.config().base_url, &hash, chrono::Utc::now().timestamp());
+
+    Ok(Json(SignedUrlResponse { url, expires_at }))
+}
+
+/// Maximum length of a cape gallery name
+const MAX_GALLERY_NAME_LENGTH: usize = 64;
+
+/// Gallery cape names are used in URLs, keep them to a safe character set
+fn is_valid_gallery_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_GALLERY_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Load the whole cape gallery as name -> (hash, url)
+async fn load_cape_gallery(state: &AppState) -> Result<BTreeMap<String, TextureResponse>> {
+    let capes = sqlx::query!(
+        r#"
+        SELECT name, cape_hash
+        FROM cape_gallery
+        ORDER BY name
+        "#
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(capes
+        .into_iter()
+        .map(|cape| {
+            let key = state.storage.texture_key(&cape.cape_hash, TextureType::CAPE);
+            let url = state
+                .storage
+                .generate_url(&key, TextureType::CAPE.file_extension());
+            (
+                cape.name,
+                TextureResponse {
+                    url: signed_texture_url(state, url),
+                    digest: cape.cape_hash,
+                    metadata: None,
+                    source: None,
+                },
+            )
+        })
+        .collect())
+}
+
+/// GET /capes - List the capes available in the gallery
+#[tracing::instrument(skip_all)]
+pub async fn list_gallery_capes(
+    State(state): State<AppState>,
+) -> Result<Json<BTreeMap<String, TextureResponse>>, (StatusCode, String)> {
+    let gallery = load_cape_gallery(&state).await.map_err(|e| {
+        tracing::error!("Failed to load cape gallery: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to load cape gallery".to_string(),
+        )
+    })?;
+
+    Ok(Json(gallery))
+}
+
+/// POST /api/capes/:name - Add or replace a gallery cape (admin only)
+/// The PNG is uploaded in the "file" form field, like /api/upload/CAPE
+#[tracing::instrument(skip_all, fields(%name))]
+pub async fn admin_add_gallery_cape(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    Path(name): Path<String>,
+    mut multipart: Multipart,
+) -> Result<Json<TextureResponse>, (StatusCode, String)> {
+    if !is_valid_gallery_name(&name) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Invalid cape name: must be 1-{} characters of a-z, A-Z, 0-9, '_' or '-'",
+                MAX_GALLERY_NAME_LENGTH
+            ),
+        ));
+    }
+
+    let mut file: Option<UploadedFile> = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid multipart data: {}", e),
+        )
+    })? {
+        if field.name() == Some("file") {
+            file = Some(read_texture_file(field, TextureType::CAPE, &state).await?);
+        }
+    }
+
+    let file = file.ok_or(UploadFileError::Missing)?;
+
+    let hash = file.hash().to_string();
+
+    let file_url = file
+        .store(state.storage.as_ref(), TextureType::CAPE)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to store file: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to store file".to_string(),
+            )
+        })?;
+
+    if let Err(e) = sqlx::query!(
+        r#"
+        INSERT INTO cape_gallery (name, cape_hash)
+        VALUES ($1, $2)
+        ON CONFLICT (name)
+        DO UPDATE SET cape_hash = $2, updated_at = NOW()
+        "#,
+        name,
+        hash
+    )
+    .execute(&state.db)
+    .await
+    {
+        tracing::error!("Failed to save gallery cape: {}", e);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to save gallery cape".to_string(),
+        ));
+    }
+
+    tracing::info!("Saved gallery cape {}: {}", name, hash);
+
+    Ok(Json(TextureResponse {
+        url: signed_texture_url(&state, file_url),
+        digest: hash,
+        metadata: None,
+        source: None,
+    }))
+}
+
+/// POST /api/pack - Upload a server resource pack (admin only)
+/// The .zip is sent in the "file" form field and stored by its SHA256 like textures,
+/// up to MAX_PACK_SIZE bytes; it is always spooled to disk past UPLOAD_MEMORY_LIMIT
+#[tracing::instrument(skip_all)]
+pub async fn admin_upload_pack(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    mut multipart: Multipart,
+) -> Result<Json<PackUploadResponse>, (StatusCode, String)> {
+    let config = state.config();
+    let mut file: Option<UploadedFile> = None;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid multipart data: {}", e),
+        )
+    })? {
+        if field.name() == Some("file") {
+            file = Some(spool_upload(field, &config, config.max_pack_size).await?);
+        }
+    }
+
+    let file = file.ok_or(UploadFileError::Missing)?;
+
+    if file.size() == 0 {
+        return Err(UploadFileError::Empty.into());
+    }
+    if !is_zip(file.head()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "File must be a ZIP archive".to_string(),
+        ));
+    }
+
+    let hash = file.hash().to_string();
+    let size = file.size() as u64;
+
+    file.store_with_extension(state.storage.as_ref(), PACK_EXTENSION)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to store resource pack: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to store file".to_string(),
+            )
+        })?;
+
+    tracing::info!("Stored resource pack {} ({} bytes)", hash, size);
+
+    Ok(Json(PackUploadResponse {
+        url: pack_url(&config, &hash),
+        hash,
+        size,
+    }))
+}
+
+/// GET /metrics - Cache hit and miss counters in the Prometheus text format (admin only)
+pub async fn metrics(AuthAdmin: AuthAdmin) -> Response<Body> {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render_prometheus(),
+    )
+        .into_response()
+}
+
+/// SHA1 of resource packs by SHA256 hash
+/// Packs are content addressed by SHA256, so a cached SHA1 never goes stale
+#[derive(Default)]
+pub struct PackSha1Cache {
+    hashes: Mutex<HashMap<String, String>>,
+    /// Held while hashing a pack, so concurrent misses share a single read of it
+    computing: tokio::sync::Mutex<()>,
+}
+
+impl PackSha1Cache {
+    fn get(&self, hash: &str) -> Option<String> {
+        self.hashes.lock().unwrap().get(hash).cloned()
+    }
+}
+
+/// SHA1 of a stored resource pack, hex encoded
+/// Computed from the pack the first time it is asked for and cached after that. The pack
+/// is hashed as it is read, so even large packs are never held in memory
+async fn pack_sha1(state: &AppState, hash: &str) -> Result<String, (StatusCode, String)> {
+    let metrics = CacheMetrics::register("pack_sha1");
+    if let Some(sha1) = state.pack_sha1.get(hash) {
+        metrics.hit();
+        return Ok(sha1);
+    }
+    metrics.miss();
+
+    // Requests that missed while another one was hashing find its result here
+    let _computing = state.pack_sha1.computing.lock().await;
+    if let Some(sha1) = state.pack_sha1.get(hash) {
+        return Ok(sha1);
+    }
+
+    let read_error = |e: &dyn std::fmt::Display| {
+        tracing::error!("Failed to get resource pack: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to get file".to_string(),
+        )
+    };
+    let body = match state.storage.get_file_stream(hash, PACK_EXTENSION).await {
+        Ok(body) => body,
+        Err(StorageError::NotFound(_)) => {
+            return Err((StatusCode::NOT_FOUND, "Pack not found".to_string()))
+        }
+        Err(e) => return Err(read_error(&e)),
+    };
+    let mut hasher = Sha1Hasher::new();
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.try_next().await.map_err(|e| read_error(&e))? {
+        hasher.update(&chunk);
+    }
+    let sha1 = hasher.finalize();
+
+    state
+        .pack_sha1
+        .hashes
+        .lock()
+        .unwrap()
+        .insert(hash.to_string(), sha1.clone());
+    Ok(sha1)
+}
+
+/// Public URL of a resource pack
+fn pack_url(config: &Config, hash: &str) -> String {
+    format!(
+        "{}/pack/{}.{}",
+        config.base_url.trim_end_matches('/'),
+        hash,
+        PACK_EXTENSION
+    )
+}
+
+/// GET /pack/{hash}.zip - Serve a resource pack uploaded with /api/pack
+/// Public even with REQUIRE_AUTH_FOR_READS, since game clients download it without a token.
+/// `X-Pack-Sha1` holds the SHA1 that server.properties' resource-pack-sha1 expects
+#[tracing::instrument(skip_all, fields(%file_name))]
+pub async fn serve_pack(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let hash = file_name
+        .strip_suffix(".zip")
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Pack not found".to_string()))?;
+    let hash = normalize_texture_hash(hash)?;
+
+    // Also answers 404 for a missing pack, before any body is opened
+    let sha1 = pack_sha1(&state, &hash).await?;
+
+    let body = match state.storage.get_file_stream(&hash, PACK_EXTENSION).await {
+        Ok(body) => body,
+        Err(StorageError::NotFound(_)) => {
+            return Err((StatusCode::NOT_FOUND, "Pack not found".to_string()))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get resource pack: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get file".to_string(),
+            ));
+        }
+    };
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                content_type_for_extension(PACK_EXTENSION).to_string(),
+            ),
+            (header::CACHE_CONTROL, file_cache_control(&state)),
+            (header::ETAG, hash_etag(&hash)),
+            (HeaderName::from_static("x-pack-sha1"), sha1),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// GET /api/pack/{hash} - Describe a resource pack, including the SHA1 to copy into
+/// server.properties' resource-pack-sha1
+#[tracing::instrument(skip_all, fields(%hash))]
+pub async fn pack_info(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> Result<Json<PackInfoResponse>, (StatusCode, String)> {
+    let hash = normalize_texture_hash(&hash)?;
+    let sha1 = pack_sha1(&state, &hash).await?;
+
+    let size = state
+        .storage
+        .file_size(&hash, PACK_EXTENSION)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to get resource pack size: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get file".to_string(),
+            )
+        })?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Pack not found".to_string()))?;
+
+    Ok(Json(PackInfoResponse {
+        url: pack_url(&state.config(), &hash),
+        hash,
+        sha1,
+        size,
+    }))
+}
+
+/// POST /api/assign-cape - Give a user a cape from the gallery (admin only)
+/// Body: {"uuid": "...", "cape_name": "..."}
+/// The user's CAPE row points at the gallery file, so no bytes are uploaded or copied
+#[tracing::instrument(
+    skip_all,
+    fields(user_uuid = %request.uuid, cape_name = %request.cape_name)
+)]
+pub async fn admin_assign_cape(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    Json(request): Json<AssignCapeRequest>,
+) -> Result<Json<TextureResponse>, (StatusCode, String)> {
+    let cape = sqlx::query!(
+        r#"
+        SELECT cape_hash
+        FROM cape_gallery
+        WHERE name = $1
+        "#,
+        request.cape_name
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to look up gallery cape: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to look up gallery cape".to_string(),
+        )
+    })?
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Cape '{}' not found in gallery", request.cape_name),
+        )
+    })?;
+
+    let texture_type = TextureType::CAPE;
+    let key = state.storage.texture_key(&cape.cape_hash, texture_type);
+    let file_url = state
+        .storage
+        .generate_url(&key, texture_type.file_extension());
+    upsert_texture(
+        &state.db,
+        request.uuid,
+        texture_type,
+        &cape.cape_hash,
+        &file_url,
+        None,
+        None,
+        texture_origin(&state, ADMIN_UPLOADER.to_string(), "gallery", None).as_ref(),
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to save texture: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to save texture".to_string(),
+        )
+    })?;
+
+    tracing::info!(
+        "Assigned gallery cape {} to user {}",
+        request.cape_name,
+        request.uuid
+    );
+    invalidate_user_caches(&state, request.uuid);
+
+    Ok(Json(TextureResponse {
+        url: signed_texture_url(&state, file_url),
+        digest: cape.cape_hash,
+        metadata: None,
+        source: None,
+    }))
+}
+
+/// Load the capes a user owns, the active one first
+async fn load_owned_capes(db: &PgPool, user_uuid: Uuid) -> sqlx::Result<Vec<OwnedCape>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT file_hash, file_url, metadata, active, uploaded_by, source, original_filename,
+               updated_at
+        FROM textures
+        WHERE user_uuid = $1 AND texture_type = $2
+        ORDER BY active DESC, updated_at DESC
+        "#,
+        user_uuid,
+        TextureType::CAPE.to_string()
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| OwnedCape {
+            url: row.file_url,
+            digest: row.file_hash,
+            metadata: row.metadata.and_then(|v| serde_json::from_value(v).ok()),
+            active: row.active,
+            uploaded_by: row.uploaded_by,
+            source: row.source,
+            original_filename: row.original_filename,
+            updated_at: row.updated_at,
+        })
+        .collect())
+}
+
+/// GET /api/capes/:uuid - List the capes a user owns (admin only)
+#[tracing::instrument(skip_all, fields(%user_uuid))]
+pub async fn admin_list_user_capes(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    UuidPath(user_uuid): UuidPath<Uuid>,
+) -> Result<Json<Vec<OwnedCape>>, (StatusCode, String)> {
+    let capes = load_owned_capes(&state.db, user_uuid).await.map_err(|e| {
+        tracing::error!("Failed to load capes: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to load capes".to_string(),
+        )
+    })?;
+
+    Ok(Json(capes))
+}
+
+/// POST /api/capes/:uuid/active - Select which of a user's capes is served (admin only)
+/// Returns the user's capes after the change
+#[tracing::instrument(skip_all, fields(%user_uuid))]
+pub async fn admin_set_active_cape(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    UuidPath(user_uuid): UuidPath<Uuid>,
+    Json(request): Json<ActiveCapeRequest>,
+) -> Result<Json<Vec<OwnedCape>>, (StatusCode, String)> {
+    let db_error = |e: sqlx::Error| {
+        tracing::error!("Failed to update active cape: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update active cape".to_string(),
+        )
+    };
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE textures SET active = (file_hash = $3)
+        WHERE user_uuid = $1 AND texture_type = $2
+            AND EXISTS (
+                SELECT 1 FROM textures
+                WHERE user_uuid = $1 AND texture_type = $2 AND file_hash = $3
+            )
+        "#,
+        user_uuid,
+        TextureType::CAPE.to_string(),
+        request.hash
+    )
+    .execute(&state.db)
+    .await
+    .map_err(db_error)?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("User does not own a cape with hash {}", request.hash),
+        ));
+    }
+
+    tracing::info!("Set active cape of user {} to {}", user_uuid, request.hash);
+    invalidate_user_caches(&state, user_uuid);
+
+    let capes = load_owned_capes(&state.db, user_uuid)
+        .await
+        .map_err(db_error)?;
+    Ok(Json(capes))
+}
+
+/// PATCH /api/texture/:uuid/:type - Change the metadata of a user's texture (admin only)
+/// For fixing e.g. a skin uploaded with the wrong model without a re-upload: only the
+/// given fields of the active texture's metadata change, the file and its hash do not
+#[tracing::instrument(skip_all, fields(%user_uuid, texture_type = %texture_type_str))]
+pub async fn admin_set_texture_metadata(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    UuidPath((user_uuid, texture_type_str)): UuidPath<(Uuid, String)>,
+    Json(request): Json<SetTextureMetadataRequest>,
+) -> Result<Json<TextureResponse>, (StatusCode, String)> {
+    let texture_type: TextureType = texture_type_str.parse().map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid texture type: {}", e),
+        )
+    })?;
+
+    if request.model.is_some() && texture_type != TextureType::SKIN {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "model can only be set on skins".to_string(),
+        ));
+    }
+    if request.cape_as_elytra.is_some() && texture_type != TextureType::CAPE {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "cape_as_elytra can only be set on capes".to_string(),
+        ));
+    }
+    let changes = TextureMetadata {
+        model: request.model.map(|model| model.to_string()),
+        cape_as_elytra: request.cape_as_elytra,
+    };
+    if changes.model.is_none() && changes.cape_as_elytra.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "No metadata fields to set".to_string(),
+        ));
+    }
+    let changes = serde_json::to_value(&changes).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to encode metadata: {}", e),
+        )
+    })?;
+
+    let record = sqlx::query!(
+        r#"
+        UPDATE textures
+        SET metadata = COALESCE(metadata, '{}'::jsonb) || $3, updated_at = NOW()
+        WHERE user_uuid = $1 AND texture_type = $2 AND active
+        RETURNING file_hash, file_url, metadata
+        "#,
+        user_uuid,
+        texture_type.to_string(),
+        changes
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to update texture metadata: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to update texture metadata".to_string(),
+        )
+    })?
+    .ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("User has no {} texture", texture_type),
+        )
+    })?;
+
+    let metadata: Option<TextureMetadata> = record
+        .metadata
+        .and_then(|value| serde_json::from_value(value).ok());
+    tracing::info!(
+        "Set metadata of {} {} of user {} to {:?}",
+        texture_type,
+        record.file_hash,
+        user_uuid,
+        metadata
+    );
+    store_metadata_sidecar(&state, &record.file_hash, metadata.as_ref()).await;
+    invalidate_user_caches(&state, user_uuid);
+
+    Ok(Json(TextureResponse {
+        url: signed_texture_url(&state, record.file_url),
+        digest: record.file_hash,
+        metadata,
+        source: None,
+    }))
+}
+
+/// Number of NDJSON lines buffered between the database cursor and the response body
+const EXPORT_CHANNEL_CAPACITY: usize = 256;
+
+/// GET /api/export - Stream all textures with their username mappings as NDJSON (admin only)
+/// Rows are read from a database cursor and written as they arrive, so the whole
+/// table is never held in memory
+#[tracing::instrument(skip_all)]
+pub async fn export_textures(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+) -> Response<Body> {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<String, std::io::Error>>(
+        EXPORT_CHANNEL_CAPACITY,
+    );
+
+    tokio::spawn(async move {
+        let mut rows = sqlx::query!(
+            r#"
+            SELECT t.user_uuid, t.texture_type, t.file_hash, t.file_url, t.metadata,
+                   t.mojang_hash, t.active, t.uploaded_by, t.source, t.original_filename,
+                   t.created_at, t.updated_at,
+                   ARRAY(
+                       SELECT COALESCE(m.display_username, m.username)
+                       FROM username_mappings m
+                       WHERE m.user_uuid = t.user_uuid
+                       ORDER BY m.updated_at DESC
+                   ) AS "usernames!"
+            FROM textures t
+            "#
+        )
+        .fetch(&state.db);
+
+        let mut exported = 0usize;
+        loop {
+            let row = match rows.try_next().await {
+                Ok(Some(row)) => row,
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!("Export failed after {} rows: {}", exported, e);
+                    let _ = tx.send(Err(std::io::Error::other(e))).await;
+                    return;
+                }
+            };
+
+            let record = ExportRecord {
+                uuid: row.user_uuid,
+                usernames: row.usernames,
+                texture_type: row.texture_type,
+                hash: row.file_hash,
+                url: row.file_url,
+                metadata: row.metadata,
+                mojang_hash: row.mojang_hash,
+                active: row.active,
+                uploaded_by: row.uploaded_by,
+                source: row.source,
+                original_filename: row.original_filename,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            };
+
+            let line = match serde_json::to_string(&record) {
+                Ok(json) => json + "\n",
+                Err(e) => {
+                    tracing::error!("Failed to serialize export record: {}", e);
+                    continue;
+                }
+            };
+
+            // Receiver dropped means the client disconnected
+            if tx.send(Ok(line)).await.is_err() {
+                tracing::warn!("Export aborted by client after {} rows", exported);
+                return;
+            }
+            exported += 1;
+        }
+
+        tracing::info!("Exported {} texture rows", exported);
+    });
+
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|line| (line, rx))
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+/// Maximum length of a single /api/import NDJSON line
+const MAX_IMPORT_LINE_SIZE: usize = 65_536; // 64 KB
+
+/// POST /api/import - Restore texture records from /api/export NDJSON (admin only)
+/// Upserts `textures` and `username_mappings` rows only; files are expected to have been
+/// migrated to the storage backend separately. The body is processed line by line as it
+/// arrives. Malformed lines are skipped unless `?on_error=fail` is given.
+#[tracing::instrument(skip_all)]
+pub async fn import_textures(
+    State(state): State<AppState>,
+    AuthAdmin: AuthAdmin,
+    Query(query): Query<ImportQuery>,
+    body: Body,
+) -> Result<Json<ImportSummary>, (StatusCode, String)> {
+    let config = state.config();
+    let mut summary = ImportSummary::default();
+    let mut stream = body.into_data_stream();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut line_number = 0usize;
+
+    loop {
+        let chunk = stream.try_next().await.map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Failed to read request body: {}", e),
+            )
+        })?;
+        let finished = chunk.is_none();
+        if let Some(chunk) = chunk {
+            buffer.extend_from_slice(&chunk);
+        }
+
+        // Process every complete line; at the end of the body the remainder is the last line
+        while let Some(line) = next_import_line(&mut buffer, finished) {
+            line_number += 1;
+            if line.iter().all(|b| b.is_ascii_whitespace()) {
+                continue;
+            }
+
+            match import_record(&state.db, &config, &line).await {
+                Ok(user_uuid) => {
+                    invalidate_user_caches(&state, user_uuid);
+                    summary.imported += 1
+                }
+                Err(ImportLineError::Malformed(reason)) => {
+                    if query.on_error == ImportErrorMode::Fail {
+                        return Err((
+                            StatusCode::BAD_REQUEST,
+                            format!(
+                                "Malformed record on line {}: {} ({} imported before failure)",
+                                line_number, reason, summary.imported
+                            ),
+                        ));
+                    }
+                    tracing::warn!("Skipping malformed import line {}: {}", line_number, reason);
+                    summary.skipped += 1;
+                }
+                Err(ImportLineError::Database(e)) => {
+                    tracing::error!("Failed to import line {}: {}", line_number, e);
+                    summary.errored += 1;
+                }
+            }
+        }
+
+        if buffer.len() > MAX_IMPORT_LINE_SIZE {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Line {} exceeds maximum length of {} bytes",
+                    line_number + 1,
+                    MAX_IMPORT_LINE_SIZE
+                ),
+            ));
+        }
+
+        if finished {
+            break;
+        }
+    }
+
+    tracing::info!(
+        "Import finished: {} imported, {} skipped, {} errored",
+        summary.imported,
+        summary.skipped,
+        summary.errored
+    );
+
+    Ok(Json(summary))
+}
+
+/// Why a single import line was not imported
+enum ImportLineError {
+    Malformed(String),
+    Database(sqlx::Error),
+}
+
+/// Take the next line out of the import buffer
+/// Returns the trailing partial line only once the body is finished
+fn next_import_line(buffer: &mut Vec<u8>, finished: bool) -> Option<Vec<u8>> {
+    match buffer.iter().position(|b| *b == b'\n') {
+        Some(pos) => {
+            let mut line: Vec<u8> = buffer.drain(..=pos).collect();
+            line.pop();
+            Some(line)
+        }
+        None if finished && !buffer.is_empty() => Some(std::mem::take(buffer)),
+        None => None,
+    }
+}
+
+/// Validate one exported record and upsert it with its username mappings
+/// Returns the UUID of the imported user
+async fn import_record(
+    db: &PgPool,
+    config: &Config,
+    line: &[u8],
+) -> Result<Uuid, ImportLineError> {
+    let record: ExportRecord = serde_json::from_slice(line)
+        .map_err(|e| ImportLineError::Malformed(format!("Invalid JSON: {}", e)))?;
+
+    let texture_type: TextureType = record
+        .texture_type
+        .parse()
+        .map_err(|e| ImportLineError::Malformed(format!("{}", e)))?;
+
+    if record.hash.is_empty() || record.url.is_empty() {
+        return Err(ImportLineError::Malformed(
+            "Record must have a hash and url".to_string(),
+        ));
+    }
+
+    let mut tx = db.begin().await.map_err(ImportLineError::Database)?;
+
+    if texture_type == TextureType::CAPE {
+        // Inactive capes are added to the owned ones, an active cape replaces the active one
+        sqlx::query!(
+            r#"
+            WITH deactivated AS (
+                UPDATE textures SET active = FALSE
+                WHERE $9 AND user_uuid = $1 AND texture_type = $2 AND file_hash <> $3 AND active
+            )
+            INSERT INTO textures (user_uuid, texture_type, file_hash, file_url, metadata, mojang_hash, active,
+                uploaded_by, source, original_filename, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $9, $10, $11, $12, $7, $8)
+            ON CONFLICT (user_uuid, texture_type, file_hash)
+            DO UPDATE SET file_url = $4, metadata = $5, mojang_hash = $6, active = $9, uploaded_by = $10,
+                source = $11, original_filename = $12, updated_at = $8
+            "#,
+            record.uuid,
+            texture_type.to_string(),
+            record.hash,
+            record.url,
+            record.metadata,
+            record.mojang_hash,
+            record.created_at,
+            record.updated_at,
+            record.active,
+            record.uploaded_by,
+            record.source,
+            record.original_filename
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(ImportLineError::Database)?;
+    } else {
+        sqlx::query!(
+            r#"
+            INSERT INTO textures (user_uuid, texture_type, file_hash, file_url, metadata, mojang_hash,
+                uploaded_by, source, original_filename, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $9, $10, $11, $7, $8)
+            ON CONFLICT (user_uuid, texture_type) WHERE texture_type <> 'CAPE'
+            DO UPDATE SET file_hash = $3, file_url = $4, metadata = $5, mojang_hash = $6, uploaded_by = $9,
+                source = $10, original_filename = $11, updated_at = $8
+            "#,
+            record.uuid,
+            texture_type.to_string(),
+            record.hash,
+            record.url,
+            record.metadata,
+            record.mojang_hash,
+            record.created_at,
+            record.updated_at,
+            record.uploaded_by,
+            record.source,
+            record.original_filename
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(ImportLineError::Database)?;
+    }
+
+    for username in &record.usernames {
+        sqlx::query!(
+            r#"
+        INSERT INTO username_mappings (user_uuid, username, display_username, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (user_uuid, username)
+        DO UPDATE SET display_username = EXCLUDED.display_username, updated_at = NOW()
+        "#,
+            record.uuid,
+            config.username_key(username),
+            username
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(ImportLineError::Database)?;
+    }
+
+    tx.commit().await.map_err(ImportLineError::Database)?;
+    Ok(record.uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PNG signature followed by an IHDR chunk header for the given size
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+        bytes.extend_from_slice(&13u32.to_be_bytes());
+        bytes.extend_from_slice(b"IHDR");
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_if_match_satisfied() {
+        assert!(if_match_satisfied("\"abc\"", Some("abc")));
+        assert!(if_match_satisfied("\"def\", \"abc\"", Some("abc")));
+        assert!(if_match_satisfied("*", Some("abc")));
+        assert!(!if_match_satisfied("\"def\"", Some("abc")));
+        assert!(!if_match_satisfied("abc", Some("abc")));
+        assert!(!if_match_satisfied("*", None));
+    }
+
+    #[test]
+    fn test_mojang_textures_etag_tracks_textures_not_time() {
+        let skin = |hash: &str, model: Option<&str>| RetrievedTexture {
+            url: format!("https://example.com/files/{}", hash),
+            hash: hash.to_string(),
+            metadata: Some(TextureMetadata {
+                model: model.map(str::to_string),
+                cape_as_elytra: None,
+            }),
+            source: "storage".to_string(),
+        };
+        let textures = |hash: &str, model: Option<&str>| {
+            HashMap::from([("SKIN".to_string(), skin(hash, model))])
+        };
+
+        let etag = mojang_textures_etag("id", Some("Steve"), &textures("abc", None), true);
+        assert_eq!(
+            etag,
+            mojang_textures_etag("id", Some("Steve"), &textures("abc", None), true)
+        );
+        assert_ne!(
+            etag,
+            mojang_textures_etag("id", Some("Steve"), &textures("def", None), true)
+        );
+        assert_ne!(
+            etag,
+            mojang_textures_etag("id", Some("Steve"), &textures("abc", Some("slim")), true)
+        );
+        assert_ne!(
+            etag,
+            mojang_textures_etag("id", Some("Steve"), &textures("abc", None), false)
+        );
+        assert_ne!(
+            etag,
+            mojang_textures_etag("id", Some("Alex"), &textures("abc", None), true)
+        );
+    }
+
+    #[test]
+    fn test_with_attachment_only_for_downloads() {
+        let inline = with_attachment(Body::empty().into_response(), false, "abc.png");
+        assert!(inline.headers().get(header::CONTENT_DISPOSITION).is_none());
+
+        let attachment = with_attachment(Body::empty().into_response(), true, "abc.png");
+        assert_eq!(
+            attachment.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"abc.png\""
+        );
+    }
+
+    #[test]
+    fn test_is_zip_checks_local_file_header() {
+        assert!(is_zip(b"PK\x03\x04\x14\x00"));
+        // Empty archives have only an end of central directory record
+        assert!(!is_zip(b"PK\x05\x06"));
+        assert!(!is_zip(&png_header(64, 64)));
+        assert!(!is_zip(b""));
+    }
+
+    #[test]
+    fn test_check_png_reports_empty_and_truncated_files() {
+        let check = |bytes: Vec<u8>| {
+            let hash = crate::hashing::hash_bytes(&bytes);
+            check_png(&UploadedFile::in_memory(bytes, hash))
+        };
+
+        assert!(matches!(check(Vec::new()), Err(UploadFileError::Empty)));
+        assert!(matches!(check(png_header(64, 64)), Err(UploadFileError::TooSmall(24))));
+        assert!(matches!(check(vec![0; MIN_PNG_SIZE]), Err(UploadFileError::NotPng)));
+
+        let mut png = png_header(64, 64);
+        png.resize(MIN_PNG_SIZE, 0);
+        assert!(check(png).is_ok());
+    }
+
+    #[test]
+    fn test_png_dimensions_reads_ihdr() {
+        assert_eq!(png_dimensions(&png_header(64, 32)), Some((64, 32)));
+        assert_eq!(png_dimensions(&png_header(128, 64)), Some((128, 64)));
+
+        let mut not_ihdr = png_header(64, 32);
+        not_ihdr[12..16].copy_from_slice(b"IDAT");
+        assert_eq!(png_dimensions(&not_ihdr), None);
+        assert_eq!(png_dimensions(&png_header(64, 32)[..20]), None);
+    }
 }
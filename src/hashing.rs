@@ -0,0 +1,80 @@
+use sha2::{Digest, Sha256};
+
+/// Incremental SHA256 of texture contents, finalized to the hex hash that names stored files
+/// Lets multipart fields and streamed reads be hashed chunk by chunk instead of buffering
+/// the whole file first
+#[derive(Default)]
+pub struct Hasher(Sha256);
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next chunk of the contents
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0.update(chunk);
+    }
+
+    /// Hex encoded hash of everything passed to `update`
+    pub fn finalize(self) -> String {
+        hex::encode(self.0.finalize())
+    }
+}
+
+/// Hex encoded SHA256 of complete contents
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    let mut hasher = Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+/// Incremental SHA1, hashing resource packs as they are read
+/// Only for Minecraft's resource-pack-sha1; stored files are always named by SHA256
+#[derive(Default)]
+pub struct Sha1Hasher(sha1::Sha1);
+
+impl Sha1Hasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next chunk of the contents
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0.update(chunk);
+    }
+
+    /// Hex encoded SHA1 of everything passed to `update`
+    pub fn finalize(self) -> String {
+        hex::encode(self.0.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunked_hash_matches_whole_hash() {
+        let mut hasher = Hasher::new();
+        hasher.update(b"a");
+        hasher.update(b"");
+        hasher.update(b"bc");
+
+        let expected = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(hasher.finalize(), expected);
+        assert_eq!(hash_bytes(b"abc"), expected);
+    }
+
+    #[test]
+    fn test_sha1_hasher() {
+        let mut hasher = Sha1Hasher::new();
+        hasher.update(b"ab");
+        hasher.update(b"");
+        hasher.update(b"c");
+        assert_eq!(
+            hasher.finalize(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::cache_metrics::CacheMetrics;
+use anyhow::{anyhow, Result};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::DecodingKey;
+use reqwest::header::CACHE_CONTROL;
+
+/// How long a key set is kept when the response has no Cache-Control max-age
+const DEFAULT_JWKS_TTL: Duration = Duration::from_secs(3600);
+
+/// Least time between two fetches triggered by unknown key IDs, so tokens with
+/// made-up `kid`s cannot make every request hit the identity provider
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+const JWKS_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct CachedKeys {
+    keys: HashMap<String, DecodingKey>,
+    /// Key of a set with a single key, used for tokens without a `kid`
+    only_key: Option<DecodingKey>,
+    expires_at: Option<Instant>,
+    fetched_at: Option<Instant>,
+}
+
+/// Public keys of an identity provider's JWKS endpoint (JWT_JWKS_URL)
+/// The set is cached for its Cache-Control max-age and fetched again once that passes,
+/// or early when a token names a key that is not in it (key rotation)
+pub struct JwksKeys {
+    client: reqwest::Client,
+    url: String,
+    cached: Mutex<CachedKeys>,
+    /// Held while fetching, so concurrent misses share a single request
+    refresh: tokio::sync::Mutex<()>,
+    metrics: Arc<CacheMetrics>,
+}
+
+impl JwksKeys {
+    pub fn new(url: String) -> Self {
+        let client = crate::outbound::client_builder()
+            .timeout(JWKS_TIMEOUT)
+            .build()
+            .expect("HTTP client with a timeout");
+
+        JwksKeys {
+            client,
+            url,
+            cached: Mutex::new(CachedKeys::default()),
+            refresh: tokio::sync::Mutex::new(()),
+            metrics: CacheMetrics::register("jwks"),
+        }
+    }
+
+    /// Key for a token's `kid` header, fetching the key set if it expired or lacks the key
+    pub async fn key(&self, kid: Option<&str>) -> Result<DecodingKey> {
+        if let Some(key) = self.cached_key(kid, false) {
+            self.metrics.hit();
+            return Ok(key);
+        }
+        self.metrics.miss();
+
+        let _refresh = self.refresh.lock().await;
+        // Another request may have fetched the set while this one waited
+        if let Some(key) = self.cached_key(kid, false) {
+            return Ok(key);
+        }
+
+        let recently_fetched = self
+            .cached
+            .lock()
+            .unwrap()
+            .fetched_at
+            .is_some_and(|fetched_at| fetched_at.elapsed() < MIN_REFRESH_INTERVAL);
+        if !recently_fetched {
+            self.fetch().await?;
+        }
+
+        // A key is used past its max-age if the provider cannot be reached
+        self.cached_key(kid, true)
+            .ok_or_else(|| anyhow!("No JWKS key for token key ID {:?}", kid))
+    }
+
+    fn cached_key(&self, kid: Option<&str>, allow_expired: bool) -> Option<DecodingKey> {
+        let cached = self.cached.lock().unwrap();
+        let fresh = cached
+            .expires_at
+            .is_some_and(|expires_at| expires_at > Instant::now());
+        if !fresh && !allow_expired {
+            return None;
+        }
+
+        match kid {
+            Some(kid) => cached.keys.get(kid).cloned(),
+            None => cached.only_key.clone(),
+        }
+    }
+
+    async fn fetch(&self) -> Result<()> {
+        tracing::debug!("Fetching JWKS from {}", self.url);
+
+        let fetched_at = Instant::now();
+        let result = self.fetch_key_set().await;
+        let mut cached = self.cached.lock().unwrap();
+        cached.fetched_at = Some(fetched_at);
+        let (key_set, ttl) = result?;
+
+        let mut keys = HashMap::new();
+        let mut all_keys = Vec::new();
+        for jwk in &key_set.keys {
+            match DecodingKey::from_jwk(jwk) {
+                Ok(key) => {
+                    if let Some(kid) = &jwk.common.key_id {
+                        keys.insert(kid.clone(), key.clone());
+                    }
+                    all_keys.push(key);
+                }
+                Err(e) => tracing::warn!(
+                    "Ignoring unsupported JWKS key {:?}: {}",
+                    jwk.common.key_id,
+                    e
+                ),
+            }
+        }
+
+        cached.only_key = if all_keys.len() == 1 {
+            all_keys.pop()
+        } else {
+            None
+        };
+        cached.keys = keys;
+        cached.expires_at = Some(fetched_at + ttl);
+        Ok(())
+    }
+
+    async fn fetch_key_set(&self) -> Result<(JwkSet, Duration)> {
+        let response = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .map_err(|e| anyhow!("JWKS request failed: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("JWKS endpoint returned error: {}", response.status()));
+        }
+
+        let ttl = response
+            .headers()
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok())
+            .and_then(max_age)
+            .unwrap_or(DEFAULT_JWKS_TTL);
+
+        let key_set = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse JWKS: {}", e))?;
+
+        Ok((key_set, ttl))
+    }
+}
+
+/// The max-age directive of a Cache-Control value
+fn max_age(cache_control: &str) -> Option<Duration> {
+    cache_control
+        .split(',')
+        .filter_map(|directive| directive.trim().strip_prefix("max-age="))
+        .find_map(|seconds| seconds.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_age() {
+        assert_eq!(
+            max_age("public, max-age=600, must-revalidate"),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(max_age("max-age=0"), Some(Duration::ZERO));
+        assert_eq!(max_age("no-cache"), None);
+        assert_eq!(max_age("max-age=soon"), None);
+    }
+}
@@ -1,45 +1,133 @@
 mod auth;
+mod backfill;
+mod cache_metrics;
 mod config;
+mod download_signing;
+mod extract;
 mod handlers;
+mod hashing;
+mod jwks;
 mod models;
+mod moderation;
+mod outbound;
+mod png_chunks;
 mod retrieval;
+mod retry;
+mod skin_alpha;
+mod sprite_sheet;
 mod storage;
+mod textures_property;
+mod tls;
+mod upload_spool;
 
 use axum::{
-    extract::State,
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, Query, State},
+    http::{header, StatusCode},
     middleware,
-    routing::{get, post},
-    Router,
+    response::IntoResponse,
+    routing::{get, patch, post},
+    BoxError, Router,
 };
-use config::Config;
+use config::{Config, LogFormat};
 use handlers::AppState;
+use models::DownloadSignature;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use storage::create_storage;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
+use tower_http::decompression::RequestDecompressionLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::TraceLayer;
 use tracing::warn;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::auth::decode_key;
+use crate::auth::JwtVerifier;
+
+/// Retry-After sent with 503 responses when the server is at MAX_CONCURRENT_REQUESTS
+const OVERLOAD_RETRY_AFTER_SECONDS: u64 = 1;
+
+/// Room for the text fields and multipart framing around uploaded files
+const MULTIPART_OVERHEAD: usize = 65_536;
+
+/// Run a maintenance command given as the first argument, e.g.
+/// `texture-provider2 backfill-usernames` or `texture-provider2 normalize-usernames`
+async fn run_command(command: &str, config: &Config, db: sqlx::PgPool) -> anyhow::Result<()> {
+    match command {
+        "backfill-usernames" => {
+            let mojang = retrieval::MojangRetriever::new(config.clone(), Some(db.clone()));
+            let report = backfill::backfill_usernames(config, &db, &mojang).await?;
+            tracing::info!(
+                updated = report.updated,
+                not_found = report.not_found,
+                rate_limited = report.rate_limited,
+                failed = report.failed,
+                "Username backfill finished"
+            );
+            Ok(())
+        }
+        "normalize-usernames" => {
+            if !config.case_insensitive_usernames {
+                return Err(anyhow::anyhow!(
+                    "normalize-usernames is only needed with CASE_INSENSITIVE_USERNAMES=true"
+                ));
+            }
+            let report = backfill::normalize_usernames(&db).await?;
+            tracing::info!(
+                normalized = report.normalized,
+                merged = report.merged,
+                "Username normalization finished"
+            );
+            Ok(())
+        }
+        _ => Err(anyhow::anyhow!(
+            "Unknown command '{}', expected backfill-usernames or normalize-usernames",
+            command
+        )),
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Load .env file (ignore error if file doesn't exist, e.g., in Docker)
     let _ = dotenvy::dotenv();
 
+    // Load configuration (before tracing, which depends on LOG_FORMAT)
+    let config = Config::from_env()?;
+
     // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "texture_provider=debug,tower_http=debug,axum=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let registry = tracing_subscriber::registry().with(
+        tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| "texture_provider=debug,tower_http=debug,axum=debug".into()),
+    );
+    match config.log_format {
+        LogFormat::Pretty => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Compact => registry
+            .with(tracing_subscriber::fmt::layer().compact())
+            .init(),
+        // JSON output keeps event fields (user_uuid, texture_type, handler...) structured
+        LogFormat::Json => registry.with(tracing_subscriber::fmt::layer().json()).init(),
+    }
 
-    // Load configuration
-    let config = Config::from_env()?;
     config.validate()?;
 
+    // Load the certificate up front, so a bad TLS setup fails before anything else starts
+    let tls_config = tls::load_config(&config).await?;
+
+    // Retry policy for S3 and texture downloads
+    retry::RetryPolicy::from_config(&config).install();
+
+    // Proxy and TLS settings of outbound HTTP requests
+    outbound::OutboundSettings::from_config(&config)?.install();
+
+    // Alternative texture type names accepted in paths and form fields
+    models::TextureType::install_aliases(config.texture_type_aliases.clone());
+
     tracing::info!("Starting texture provider service");
     tracing::info!("Storage type: {:?}", config.storage_type);
 
@@ -55,55 +143,283 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::info!("Database connection verified");
 
+    // Maintenance commands run once against the database instead of starting the server
+    if let Some(command) = std::env::args().nth(1) {
+        return run_command(&command, &config, db).await;
+    }
+
     // Initialize storage
-    let storage: Arc<dyn storage::StorageBackend> = create_storage(config.clone());
+    let storage: Arc<dyn storage::StorageBackend> = create_storage(config.clone())?;
+    storage
+        .health_check()
+        .await
+        .map_err(|e| anyhow::anyhow!("Storage health check failed: {}", e))?;
+    tracing::info!("Storage backend verified");
 
     // Initialize texture retriever
     let retriever = retrieval::create_retriever(config.clone(), storage.clone(), db.clone());
     tracing::info!("Retrieval type: {:?}", config.retrieval_type);
 
+    // Mojang retriever used directly by admin operations
+    // Reuse the configured one if present, otherwise create a standalone instance
+    let mojang = retrieval::find_retriever::<retrieval::MojangRetriever>(&retriever)
+        .unwrap_or_else(|| {
+            Arc::new(retrieval::MojangRetriever::new(
+                config.clone(),
+                Some(db.clone()),
+            ))
+        });
+
+    // Default skin logic for /default/:uuid, the configured one or a standalone instance
+    let default_skin = retrieval::find_retriever::<retrieval::DefaultSkinRetriever>(&retriever)
+        .unwrap_or_else(|| Arc::new(retrieval::DefaultSkinRetriever::from_config(&config)));
+
+    // Negative cache handle, so writes can clear a user's remembered misses
+    let negative_cache =
+        retrieval::find_retriever::<retrieval::NegativeCacheRetriever>(&retriever);
+
     // Build application state
     let state = AppState {
         db,
         storage,
         retriever,
-        config: config.clone(),
-        public_key: Arc::new(decode_key(&config.jwt_public_key)?),
+        mojang,
+        default_skin,
+        miss_fallback: retrieval::MissFallback::from_config(&config).map(Arc::new),
+        config: Arc::new(RwLock::new(Arc::new(config.clone()))),
+        jwt_verifier: JwtVerifier::from_config(&config)?.map(Arc::new),
+        textures_signer: textures_property::TexturesSigner::from_config(&config)?.map(Arc::new),
+        negative_cache,
+        moderation: moderation::ModerationWebhook::from_config(&config).map(Arc::new),
+        moderation_check: moderation::ModerationCheck::from_config(&config).map(Arc::new),
+        download_signer: download_signing::DownloadSigner::from_config(&config).map(Arc::new),
+        read_only: Arc::new(AtomicBool::new(config.read_only)),
+        pack_sha1: Arc::default(),
+        sprite_sheets: Arc::default(),
     };
+    if state.jwt_verifier.is_none() {
+        tracing::warn!(
+            "JWT_PUBLIC_KEY and JWT_JWKS_URL are not set, user JWT authentication is disabled"
+        );
+    }
+    if config.read_only {
+        tracing::warn!("Starting in read-only maintenance mode");
+    }
+
+    // Warm the default texture caches so the first requests do not wait on Mojang
+    if config.preload_defaults {
+        if let Some(default_skin) =
+            retrieval::find_retriever::<retrieval::DefaultSkinRetriever>(&state.retriever)
+        {
+            default_skin.preload().await;
+        }
+        if let Some(miss_fallback) = &state.miss_fallback {
+            miss_fallback.preload().await;
+        }
+    }
 
     // Build our application with routes
-    let app = Router::new()
+    // Every handler is bounded by REQUEST_TIMEOUT_SECS, a safety net against stuck
+    // storage or upstream calls; a timed out request gets 408 Request Timeout
+    // Routes serving texture files, only for signed URLs with DOWNLOAD_SIGNING_SECRET
+    let downloads = Router::new()
+        .route(
+            "/download/:texture_type/:uuid",
+            get(handlers::download_texture).head(handlers::head_download_texture),
+        )
+        .route(
+            "/download/:hash",
+            get(handlers::download_by_hash).head(handlers::head_download_by_hash),
+        )
+        .route(
+            "/download/username/:texture_type/:username",
+            get(handlers::download_texture_by_username),
+        )
+        .route("/sheet/:uuid", get(handlers::get_sprite_sheet))
+        .route(
+            "/files/:hash",
+            get(handlers::serve_texture_file).head(handlers::head_texture_file),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_download_signature,
+        ));
+
+    // Public read endpoints, behind a JWT or the admin token with REQUIRE_AUTH_FOR_READS
+    let mut public_reads = Router::new()
         .route("/get/:uuid", get(handlers::get_textures))
         .route("/get/:uuid/:texture_type", get(handlers::get_texture))
-        .route("/upload/:texture_type", post(handlers::upload_texture))
-        .route("/api/upload/:type", post(handlers::admin_upload_texture))
+        .route(
+            "/get/:uuid/:texture_type/metadata",
+            get(handlers::get_texture_metadata),
+        )
+        .route("/default/:uuid", get(handlers::get_default_skin))
+        .route("/capes", get(handlers::list_gallery_capes))
+        .route("/files/exists", post(handlers::files_exist))
+        .merge(downloads);
+    if config.require_auth_for_reads {
+        tracing::info!("Read endpoints require authentication");
+        public_reads = public_reads.route_layer(middleware::from_fn(require_read_auth));
+    }
+
+    // Admin writes that are quick enough for REQUEST_TIMEOUT_SECS, refused in read-only mode
+    let writes = Router::new()
+        .route(
+            "/api/refresh/:uuid",
+            post(handlers::refresh_textures_from_mojang),
+        )
+        .route(
+            "/api/block/:uuid",
+            post(handlers::admin_block_uploads).delete(handlers::admin_unblock_uploads),
+        )
+        .route(
+            "/api/banned-hashes/:hash",
+            post(handlers::admin_ban_hash).delete(handlers::admin_unban_hash),
+        )
+        .route(
+            "/api/capes/:id/active",
+            post(handlers::admin_set_active_cape),
+        )
+        .route("/api/assign-cape", post(handlers::admin_assign_cape))
+        .route(
+            "/api/texture/:uuid/:texture_type",
+            patch(handlers::admin_set_texture_metadata),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            reject_writes_when_read_only,
+        ));
+
+    let reads = Router::new()
         .route(
             "/api/get/:username/:uuid",
             get(handlers::get_textures_by_username_uuid),
         )
         .route(
-            "/download/:texture_type/:uuid",
-            get(handlers::download_texture),
+            "/api/invalidate/:uuid",
+            post(handlers::admin_invalidate_user),
         )
-        .route("/download/:hash", get(handlers::download_by_hash))
+        // :id is a user UUID here and a gallery cape name in the upload route below
+        .route("/api/capes/:id", get(handlers::admin_list_user_capes))
+        .route("/api/export", get(handlers::export_textures))
+        .route("/api/sign/:hash", get(handlers::admin_sign_download_url))
         .route(
-            "/download/username/:texture_type/:username",
-            get(handlers::download_texture_by_username),
+            "/api/readonly",
+            get(handlers::admin_get_read_only).post(handlers::admin_set_read_only),
         )
-        .route("/files/:hash", get(handlers::serve_texture_file))
+        .route("/api/reload", post(handlers::admin_reload_config))
+        .route("/metrics", get(handlers::metrics))
+        // Game clients download packs without a token, so not behind REQUIRE_AUTH_FOR_READS
+        .route("/pack/:file", get(handlers::serve_pack))
+        .route("/api/pack/:hash", get(handlers::pack_info))
+        .merge(writes)
+        .merge(public_reads)
+        .layer(TimeoutLayer::new(Duration::from_secs(
+            config.request_timeout_secs,
+        )));
+
+    // Uploads legitimately take longer to receive, so they get UPLOAD_TIMEOUT_SECS instead
+    let mut uploads = Router::new()
+        .route("/upload/:texture_type", post(handlers::upload_texture))
+        .route("/api/upload/:type", post(handlers::admin_upload_texture))
+        .route("/api/upload-multi", post(handlers::admin_upload_multi))
+        .route("/api/capes/:id", post(handlers::admin_add_gallery_cape))
+        .route("/api/import", post(handlers::import_textures))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            reject_writes_when_read_only,
+        ))
+        .layer(TimeoutLayer::new(Duration::from_secs(
+            config.upload_timeout_secs,
+        )));
+
+    // Resource packs are far larger than textures, so they get their own body limit
+    let mut packs = Router::new()
+        .route("/api/pack", post(handlers::admin_upload_pack))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            reject_writes_when_read_only,
+        ))
+        .layer(DefaultBodyLimit::max(config.max_pack_size + MULTIPART_OVERHEAD))
+        .layer(TimeoutLayer::new(Duration::from_secs(
+            config.upload_timeout_secs,
+        )));
+
+    // Gzipped bodies are inflated before the multipart parser, so body limits, file size
+    // limits and format checks all see the decompressed bytes
+    if config.accept_gzip_uploads {
+        tracing::info!("Accepting gzip-compressed upload bodies");
+        uploads = uploads.layer(RequestDecompressionLayer::new());
+        packs = packs.layer(RequestDecompressionLayer::new());
+    }
+
+    let routes = reads.merge(uploads).merge(packs);
+
+    // Mount everything under ROUTE_PREFIX when running behind a path-based reverse proxy
+    let routes = match &config.route_prefix {
+        Some(prefix) => {
+            tracing::info!("Serving routes under {}", prefix);
+            Router::new().nest(prefix, routes)
+        }
+        None => routes,
+    };
+
+    let app = routes
         .layer(middleware::from_fn_with_state(
             state.clone(),
             add_public_key_to_state,
         ))
+        // Large enough for /api/upload-multi with a skin and a cape of MAX_UPLOAD_SIZE each
+        .layer(DefaultBodyLimit::max(
+            2 * config.max_upload_size + MULTIPART_OVERHEAD,
+        ))
         .layer(build_cors_layer(&config))
+        // Inside the trace span, so slow request warnings carry the request id
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            log_slow_requests,
+        ))
+        // Assign (or keep the upstream proxy's) X-Request-Id, log within a span
+        // carrying it and echo it back on the response
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        )
         .with_state(state);
+    let app = limit_concurrency(app, config.max_concurrent_requests);
 
     // Start server
     let addr = SocketAddr::from(([0, 0, 0, 0], config.server_port));
-    tracing::info!("Server listening on {}", addr);
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // Plain HTTP unless TLS_CERT_PATH and TLS_KEY_PATH are set
+    let tls_config = match tls_config {
+        Some(tls_config) => tls_config,
+        None => {
+            tracing::info!("Server listening on {}", addr);
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(listener, app).await?;
+            return Ok(());
+        }
+    };
+
+    if let Some(redirect_port) = config.http_redirect_port {
+        let redirect_addr = SocketAddr::from(([0, 0, 0, 0], redirect_port));
+        let listener = tokio::net::TcpListener::bind(redirect_addr).await?;
+        tracing::info!("Redirecting HTTP on {} to HTTPS", redirect_addr);
+        let https_port = config.server_port;
+        tokio::spawn(async move {
+            if let Err(e) = tls::serve_https_redirect(listener, https_port).await {
+                tracing::error!("HTTP to HTTPS redirect server failed: {}", e);
+            }
+        });
+    }
+
+    tracing::info!("Server listening on {} (HTTPS)", addr);
+    axum_server::bind_rustls(addr, tls_config)
+        .serve(app.into_make_service())
+        .await?;
 
     Ok(())
 }
@@ -114,19 +430,187 @@ async fn add_public_key_to_state(
     mut request: axum::http::Request<axum::body::Body>,
     next: middleware::Next,
 ) -> axum::response::Response {
-    // Add public key to request extensions so it can be accessed by AuthUser extractor
-    request.extensions_mut().insert(state.public_key.clone());
+    // Add JWT verifier to request extensions so it can be accessed by AuthUser extractor
+    if let Some(jwt_verifier) = &state.jwt_verifier {
+        request.extensions_mut().insert(jwt_verifier.clone());
+    }
 
     // Add admin token to request extensions if configured
-    if let Some(ref admin_token) = state.config.admin_token {
+    if let Some(ref admin_token) = state.config().admin_token {
         request
             .extensions_mut()
             .insert(format!("admin_token:{}", admin_token));
     }
 
+    if state.config().admin_allow_basic_auth {
+        request.extensions_mut().insert(auth::AllowAdminBasicAuth);
+    }
+
     next.run(request).await
 }
 
+/// Refuse writes with 503 while the service is in read-only maintenance mode
+async fn reject_writes_when_read_only(
+    State(state): State<AppState>,
+    request: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    if state.read_only.load(Ordering::Relaxed) {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Service is in read-only maintenance mode, writes are disabled",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// With DOWNLOAD_SIGNING_SECRET, serve texture files only for signed URLs that have not
+/// expired (403), and keep caches from serving them past the expiry
+/// The signature covers the path below ROUTE_PREFIX, which is part of BASE_URL
+async fn require_download_signature(
+    State(state): State<AppState>,
+    Query(signature): Query<DownloadSignature>,
+    request: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    let Some(signer) = state.download_signer.clone() else {
+        return next.run(request).await;
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let exp = match (signature.exp, signature.sig.as_deref()) {
+        (Some(exp), Some(sig)) if signer.verify(request.uri().path(), exp, sig, now) => exp,
+        _ => {
+            return (
+                StatusCode::FORBIDDEN,
+                "Invalid or expired download signature",
+            )
+                .into_response()
+        }
+    };
+
+    let mut response = next.run(request).await;
+    let capped = response
+        .headers()
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| cap_max_age(value, (exp - now) as u64))
+        .and_then(|value| value.parse().ok());
+    if let Some(capped) = capped {
+        response.headers_mut().insert(header::CACHE_CONTROL, capped);
+    }
+    response
+}
+
+/// Lower the max-age of a Cache-Control value to at most `limit` seconds
+fn cap_max_age(cache_control: &str, limit: u64) -> String {
+    cache_control
+        .split(',')
+        .map(str::trim)
+        .map(|directive| match directive.strip_prefix("max-age=") {
+            Some(max_age) => {
+                let max_age = max_age.parse().map_or(limit, |max_age: u64| max_age.min(limit));
+                format!("max-age={}", max_age)
+            }
+            None => directive.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Log a warning for requests that take at least SLOW_REQUEST_THRESHOLD_MS (0 disables)
+/// Times the handler up to the response headers, like the request timeouts
+async fn log_slow_requests(
+    State(state): State<AppState>,
+    request: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    let threshold_ms = state.config().slow_request_threshold_ms;
+    if threshold_ms == 0 {
+        return next.run(request).await;
+    }
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = started.elapsed();
+
+    if elapsed >= Duration::from_millis(threshold_ms) {
+        warn!(
+            %method,
+            %path,
+            status = response.status().as_u16(),
+            duration_ms = elapsed.as_millis() as u64,
+            "Slow request"
+        );
+    }
+
+    response
+}
+
+/// Gate for the public read endpoints when REQUIRE_AUTH_FOR_READS is enabled
+/// Lets through requests with a valid user JWT or the admin token, anything else gets 401
+async fn require_read_auth(
+    user: Result<auth::AuthUser, (StatusCode, String)>,
+    admin: Option<auth::AuthAdmin>,
+    request: axum::http::Request<axum::body::Body>,
+    next: middleware::Next,
+) -> axum::response::Response {
+    match (user, admin) {
+        (Ok(_), _) | (_, Some(_)) => next.run(request).await,
+        (Err(rejection), None) => rejection.into_response(),
+    }
+}
+
+/// Cap in-flight requests to protect the database pool and Mojang rate limits
+/// Requests over the cap are rejected right away rather than queued. The router layers
+/// every route separately, so the routes share one semaphore to make the cap per process
+fn limit_concurrency<S>(router: Router<S>, max_concurrent_requests: usize) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(handle_overload))
+            .load_shed()
+            .layer(GlobalConcurrencyLimitLayer::new(max_concurrent_requests)),
+    )
+}
+
+/// Turn a load-shed rejection into 503 Service Unavailable
+async fn handle_overload(error: BoxError) -> axum::response::Response {
+    if error.is::<tower::load_shed::error::Overloaded>() {
+        warn!("Rejecting request, MAX_CONCURRENT_REQUESTS reached");
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, OVERLOAD_RETRY_AFTER_SECONDS.to_string())],
+            "Server is overloaded, try again later",
+        )
+            .into_response()
+    } else {
+        tracing::error!("Unhandled middleware error: {}", error);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+    }
+}
+
+/// Create the tracing span for a request, tagged with its request ID
+fn make_request_span(request: &axum::http::Request<axum::body::Body>) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id,
+    )
+}
+
 /// Build CORS layer based on configuration
 /// If CORS_ALLOWED_ORIGINS is set, use those specific origins
 /// Otherwise, allow all origins (for development)
@@ -166,3 +650,54 @@ fn build_cors_layer(config: &Config) -> CorsLayer {
             .allow_headers(Any)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tokio::sync::{oneshot, Mutex};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_cap_max_age() {
+        assert_eq!(
+            cap_max_age("public, max-age=31536000, immutable", 60),
+            "public, max-age=60, immutable"
+        );
+        assert_eq!(cap_max_age("private, max-age=30", 60), "private, max-age=30");
+        assert_eq!(cap_max_age("no-store", 60), "no-store");
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_is_shared_by_all_routes() {
+        let (entered_tx, entered_rx) = oneshot::channel::<()>();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        let entered_tx = Arc::new(Mutex::new(Some(entered_tx)));
+        let release_rx = Arc::new(Mutex::new(Some(release_rx)));
+
+        let router = Router::new()
+            .route(
+                "/slow",
+                get(move || async move {
+                    entered_tx.lock().await.take().unwrap().send(()).unwrap();
+                    let release_rx = release_rx.lock().await.take().unwrap();
+                    release_rx.await.unwrap();
+                    "slow"
+                }),
+            )
+            .route("/fast", get(|| async { "fast" }));
+        let router = limit_concurrency(router, 1);
+
+        let request = |uri: &str| axum::http::Request::get(uri).body(Body::empty()).unwrap();
+        let slow = tokio::spawn(router.clone().oneshot(request("/slow")));
+        entered_rx.await.unwrap();
+
+        let response = router.clone().oneshot(request("/fast")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        release_tx.send(()).unwrap();
+        assert_eq!(slow.await.unwrap().unwrap().status(), StatusCode::OK);
+        let response = router.oneshot(request("/fast")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -1,8 +1,14 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::OnceLock;
 use uuid::Uuid;
 
+/// Alternative texture type names accepted by `TextureType::from_str`, keyed by
+/// uppercase alias. Installed at startup from TEXTURE_TYPE_ALIASES.
+static TEXTURE_TYPE_ALIASES: OnceLock<HashMap<String, TextureType>> = OnceLock::new();
+
 /// Enum of supported texture types
 /// To add a new texture type:
 /// 1. Add a variant here
@@ -12,8 +18,8 @@ use uuid::Uuid;
 pub enum TextureType {
     SKIN,
     CAPE,
+    ELYTRA,
     // Add new texture types here, e.g.:
-    // ELYTRA,
     // HAT,
 }
 
@@ -22,6 +28,7 @@ impl fmt::Display for TextureType {
         match self {
             TextureType::SKIN => write!(f, "SKIN"),
             TextureType::CAPE => write!(f, "CAPE"),
+            TextureType::ELYTRA => write!(f, "ELYTRA"),
             // Add display for new types here
         }
     }
@@ -34,36 +41,79 @@ impl std::str::FromStr for TextureType {
         match s.to_uppercase().as_str() {
             "SKIN" => Ok(TextureType::SKIN),
             "CAPE" => Ok(TextureType::CAPE),
+            "ELYTRA" => Ok(TextureType::ELYTRA),
             // Add parsing for new types here
-            _ => Err(anyhow::anyhow!(
-                "Invalid texture type: {}. Valid types are: {}", 
-                s, 
-                TextureType::all_types().join(", ")
-            )),
+            alias => TEXTURE_TYPE_ALIASES
+                .get()
+                .and_then(|aliases| aliases.get(alias))
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!(
+                    "Invalid texture type: {}. Valid types are: {}", 
+                    s, 
+                    TextureType::all_types().join(", ")
+                )),
         }
     }
 }
 
 impl TextureType {
+    /// Install the aliases accepted by `from_str`, keyed by uppercase alias
+    pub fn install_aliases(aliases: HashMap<String, TextureType>) {
+        if TEXTURE_TYPE_ALIASES.set(aliases).is_err() {
+            tracing::warn!("Texture type aliases already installed, ignoring");
+        }
+    }
+}
+
+/// Content type used when the texture type of a file is not known (e.g. lookups by hash)
+pub const DEFAULT_CONTENT_TYPE: &str = "image/png";
+
+/// Map a stored file extension to the content type it is served with
+pub fn content_type_for_extension(extension: &str) -> &'static str {
+    match extension {
+        "png" => "image/png",
+        "json" => "application/json",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+impl TextureType {
+    /// Every texture type, in lookup order
+    pub const ALL: [TextureType; 3] = [TextureType::SKIN, TextureType::CAPE, TextureType::ELYTRA]; // Add new types here
+
     /// Get all supported texture types
     pub fn all_types() -> Vec<&'static str> {
-        vec!["SKIN", "CAPE"] // Add new types here
+        vec!["SKIN", "CAPE", "ELYTRA"] // Add new types here
     }
 
     /// Get the file extension for this texture type
-    pub fn file_extension(&self) -> &str {
+    /// Used for storage keys, so it must stay stable for existing files
+    pub fn file_extension(&self) -> &'static str {
         match self {
             TextureType::SKIN => "png",
             TextureType::CAPE => "png",
+            TextureType::ELYTRA => "png",
             // Different types could have different extensions
         }
     }
+
+    /// Get the content type this texture type is served with
+    pub fn content_type(&self) -> &'static str {
+        content_type_for_extension(self.file_extension())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Extension of the metadata sidecar stored next to a texture file (STORE_METADATA_SIDECAR)
+pub const METADATA_SIDECAR_EXTENSION: &str = "json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TextureMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub model: Option<String>,
+    /// Capes only: the user prefers the cape to be rendered as their elytra
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cape_as_elytra: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -72,14 +122,40 @@ pub struct TextureResponse {
     pub digest: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<TextureMetadata>,
+    /// Retriever that provided the texture, only exposed in admin debug responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Response of /get/{uuid}/{type}?embed=true, for clients that cannot make a second
+/// request for the file
+#[derive(Debug, Serialize)]
+pub struct EmbeddedTextureResponse {
+    #[serde(flatten)]
+    pub texture: TextureResponse,
+    /// The texture file, base64-encoded
+    pub data: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct TexturesResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub SKIN: Option<TextureResponse>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub CAPE: Option<TextureResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ELYTRA: Option<TextureResponse>,
+}
+
+impl TexturesResponse {
+    /// Set the texture of the given type
+    pub fn set(&mut self, texture_type: TextureType, texture: TextureResponse) {
+        match texture_type {
+            TextureType::SKIN => self.SKIN = Some(texture),
+            TextureType::CAPE => self.CAPE = Some(texture),
+            TextureType::ELYTRA => self.ELYTRA = Some(texture),
+        }
+    }
 }
 
 #[derive(Debug, FromRow)]
@@ -94,16 +170,317 @@ pub struct Texture {
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Skin model (arm width) selected on upload
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SkinModel {
+    Classic,
+    Slim,
+}
+
+impl fmt::Display for SkinModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkinModel::Classic => write!(f, "classic"),
+            SkinModel::Slim => write!(f, "slim"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
 pub struct UploadOptions {
+    /// Legacy flag, kept for backward compatibility with older clients
     #[serde(default)]
     pub modelSlim: bool,
+    /// Explicit skin model, takes precedence over `modelSlim`
+    #[serde(default)]
+    pub model: Option<SkinModel>,
+    /// Render preference of a cape, ignored for other texture types
+    #[serde(default)]
+    pub cape_as_elytra: Option<bool>,
+}
+
+impl UploadOptions {
+    /// Resolve the requested skin model
+    /// Returns None if the client did not specify a model
+    pub fn skin_model(&self) -> Option<SkinModel> {
+        self.model
+            .or(if self.modelSlim { Some(SkinModel::Slim) } else { None })
+    }
+
+    /// Build the texture metadata to store for an upload of a texture type
+    /// Returns None if the client did not specify anything to store
+    pub fn texture_metadata(&self, texture_type: TextureType) -> Option<TextureMetadata> {
+        let cape_as_elytra = match texture_type {
+            TextureType::CAPE => self.cape_as_elytra,
+            _ => None,
+        };
+        let model = self.skin_model().map(|model| model.to_string());
+        if model.is_none() && cape_as_elytra.is_none() {
+            return None;
+        }
+
+        Some(TextureMetadata {
+            model,
+            cape_as_elytra,
+        })
+    }
+}
+
+/// One line of the /api/export NDJSON stream: a textures row with its username mappings
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub uuid: Uuid,
+    #[serde(default)]
+    pub usernames: Vec<String>,
+    pub texture_type: String,
+    pub hash: String,
+    pub url: String,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+    /// Hash from the Mojang texture URL, for textures pulled from Mojang
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mojang_hash: Option<String>,
+    /// Whether this is the cape the user wears; false only for other capes they own
+    #[serde(default = "default_active")]
+    pub active: bool,
+    /// Audit columns recorded with RECORD_UPLOAD_SOURCE
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploaded_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_filename: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// What /api/import does with a malformed line
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportErrorMode {
+    /// Count the line as skipped and continue
+    #[default]
+    Skip,
+    /// Abort the import with 400 Bad Request
+    Fail,
+}
+
+/// Query parameters accepted by /api/import
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub on_error: ImportErrorMode,
+}
+
+/// Result counters returned by /api/import
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    /// Records upserted into the database
+    pub imported: usize,
+    /// Malformed lines that were skipped
+    pub skipped: usize,
+    /// Valid records that failed to be written
+    pub errored: usize,
+}
+
+/// Result of /api/invalidate/:uuid
+#[derive(Debug, Serialize)]
+pub struct InvalidateResponse {
+    pub uuid: Uuid,
+    /// Names of the caches that held entries for the user
+    pub invalidated: Vec<String>,
+}
+
+/// A cape owned by a user, as listed by /api/capes/:uuid
+#[derive(Debug, Serialize)]
+pub struct OwnedCape {
+    pub url: String,
+    pub digest: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<TextureMetadata>,
+    /// The cape served for the user
+    pub active: bool,
+    /// Who added the cape and how, if RECORD_UPLOAD_SOURCE was enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uploaded_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_filename: Option<String>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Body of /api/capes/:uuid/active: select one of the user's capes by hash
+#[derive(Debug, Deserialize)]
+pub struct ActiveCapeRequest {
+    pub hash: String,
+}
+
+/// Body of PATCH /api/texture/:uuid/:type, the metadata fields to change
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SetTextureMetadataRequest {
+    /// Skins only
+    #[serde(default)]
+    pub model: Option<SkinModel>,
+    /// Capes only
+    #[serde(default)]
+    pub cape_as_elytra: Option<bool>,
+}
+
+/// Optional body of POST /api/block/:uuid
+#[derive(Debug, Deserialize)]
+pub struct BlockUploadsRequest {
+    /// Note for other admins on why the user was blocked
+    #[serde(default)]
+    pub reason: Option<String>,
 }
 
+/// Optional body of POST /api/banned-hashes/:hash
+#[derive(Debug, Deserialize)]
+pub struct BanHashRequest {
+    /// Note for other admins on why the texture was banned
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Body of POST /api/readonly and response of /api/readonly
 #[derive(Debug, Serialize, Deserialize)]
-pub struct JwtClaims {
-    pub uuid: String,
-    pub exp: usize,
+pub struct ReadOnlyState {
+    /// Writes are refused with 503 while enabled
+    pub read_only: bool,
+}
+
+/// Extension of resource packs in storage
+pub const PACK_EXTENSION: &str = "zip";
+
+/// Response of POST /api/pack
+#[derive(Debug, Serialize)]
+pub struct PackUploadResponse {
+    /// SHA256 of the pack, which names it in storage and in its URL
+    pub hash: String,
+    /// Where the pack is served, /pack/{hash}.zip
+    pub url: String,
+    pub size: u64,
+}
+
+/// Response of GET /api/pack/{hash}
+#[derive(Debug, Serialize)]
+pub struct PackInfoResponse {
+    pub hash: String,
+    pub url: String,
+    pub size: u64,
+    /// SHA1 of the pack, for server.properties' resource-pack-sha1
+    pub sha1: String,
+}
+
+/// Response of POST /api/reload
+#[derive(Debug, Serialize)]
+pub struct ReloadResponse {
+    /// Environment variables whose new values took effect
+    pub reloaded: Vec<&'static str>,
+    /// Whether settings that only apply after a restart changed too; they were ignored
+    pub restart_required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Signature query parameters of the routes serving texture files (DOWNLOAD_SIGNING_SECRET)
+#[derive(Debug, Default, Deserialize)]
+pub struct DownloadSignature {
+    /// Expiry of the URL in unix seconds
+    pub exp: Option<i64>,
+    /// Hex HMAC-SHA256 of `<path>:<exp>`
+    pub sig: Option<String>,
+}
+
+/// Query parameters of the endpoints that serve texture files
+#[derive(Debug, Default, Deserialize)]
+pub struct DownloadQuery {
+    /// Send the file as an attachment with a filename, for browser downloads
+    #[serde(default)]
+    pub download: bool,
+}
+
+/// Result of /api/sign/:hash
+#[derive(Debug, Serialize)]
+pub struct SignedUrlResponse {
+    pub url: String,
+    /// Unix seconds after which the URL is rejected
+    pub expires_at: i64,
+}
+
+/// Body of /api/assign-cape: give a user a cape from the gallery
+#[derive(Debug, Deserialize)]
+pub struct AssignCapeRequest {
+    pub uuid: Uuid,
+    pub cape_name: String,
+}
+
+/// Query parameters accepted by the /get endpoints
+#[derive(Debug, Deserialize)]
+pub struct TextureQuery {
+    /// Include the texture source in the response (requires admin token)
+    #[serde(default)]
+    pub debug: bool,
+    /// Response shape, only honored by /get/{uuid}
+    #[serde(default)]
+    pub format: TexturesFormat,
+    /// Leave the textures property unsigned, as on the session server (`?unsigned=false`
+    /// requests a signature); only honored with `format=mojang`
+    #[serde(default = "default_unsigned")]
+    pub unsigned: bool,
+    /// Include the texture file base64-encoded, only honored by /get/{uuid}/{type}
+    #[serde(default)]
+    pub embed: bool,
+}
+
+fn default_unsigned() -> bool {
+    true
+}
+
+/// Query parameters of /download/username/{type}/{username}
+#[derive(Debug, Deserialize)]
+pub struct UsernameDownloadQuery {
+    /// Resolve the username with Mojang again instead of using the stored mapping
+    /// (admin only)
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// Response shapes of /get/{uuid}
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TexturesFormat {
+    /// Our own `{"SKIN": ..., "CAPE": ...}` response
+    #[default]
+    Default,
+    /// Mojang session server profile with a base64 `textures` property
+    Mojang,
+}
+
+/// Profile returned by /get/{uuid}?format=mojang, shaped like the Mojang session server's
+#[derive(Debug, Serialize)]
+pub struct MojangTexturesResponse {
+    /// UUID without dashes
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub properties: Vec<ProfileProperty>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    /// Present when TEXTURES_SIGNING_KEY is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
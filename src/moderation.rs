@@ -0,0 +1,158 @@
+use crate::config::Config;
+use crate::models::TextureType;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Body POSTed to MODERATION_WEBHOOK_URL for every upload
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadEvent {
+    pub uuid: Uuid,
+    pub texture_type: TextureType,
+    pub hash: String,
+    pub url: String,
+}
+
+/// Why a synchronous review did not approve an upload
+#[derive(Debug, thiserror::Error)]
+pub enum ModerationError {
+    /// The webhook answered with a non-2xx status
+    #[error("Upload rejected by moderation ({0})")]
+    Rejected(reqwest::StatusCode),
+    /// The webhook could not be reached or timed out
+    #[error("Moderation webhook failed: {0}")]
+    Unavailable(#[from] reqwest::Error),
+}
+
+/// Outbound webhook notified of every upload (MODERATION_WEBHOOK_URL)
+/// By default uploads are only reported after they succeed; in synchronous mode
+/// (MODERATION_WEBHOOK_SYNC) the upload waits for the webhook and is refused unless
+/// it answers with 2xx
+pub struct ModerationWebhook {
+    client: reqwest::Client,
+    url: String,
+    timeout: Duration,
+    synchronous: bool,
+}
+
+impl ModerationWebhook {
+    /// Create from configuration, or None if no webhook URL is set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config
+            .moderation_webhook_url
+            .clone()
+            .map(|url| ModerationWebhook {
+                client: crate::outbound::client(),
+                url,
+                timeout: Duration::from_secs(config.moderation_webhook_timeout_secs),
+                synchronous: config.moderation_webhook_sync,
+            })
+    }
+
+    /// Whether uploads must be approved by the webhook before they are saved
+    pub fn is_synchronous(&self) -> bool {
+        self.synchronous
+    }
+
+    /// Send an upload to the webhook and wait for its verdict
+    pub async fn review(&self, event: &UploadEvent) -> Result<(), ModerationError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(self.timeout)
+            .json(event)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(ModerationError::Rejected(response.status()))
+        }
+    }
+
+    /// Report a completed upload in the background, without delaying the response
+    /// Failures are only logged
+    pub fn notify(self: &Arc<Self>, event: UploadEvent) {
+        let webhook = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = webhook.review(&event).await {
+                tracing::warn!(
+                    "Moderation webhook for {} {} of user {}: {}",
+                    event.texture_type,
+                    event.hash,
+                    event.uuid,
+                    e
+                );
+            }
+        });
+    }
+}
+
+/// Answer of the pre-store moderation check
+#[derive(Debug, Deserialize)]
+pub struct ModerationVerdict {
+    pub allowed: bool,
+    /// Why the texture was rejected, passed on to the uploader
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Synchronous content check run before an upload is stored (MODERATION_CHECK_URL)
+/// The PNG is POSTed as the request body, with the user, texture type and hash in
+/// `X-User-Uuid`, `X-Texture-Type` and `X-Texture-Hash` headers, and the service answers
+/// `{"allowed": bool, "reason": "..."}`
+pub struct ModerationCheck {
+    client: reqwest::Client,
+    url: String,
+    timeout: Duration,
+    // Accept uploads when the check itself fails, instead of refusing them
+    fail_open: bool,
+}
+
+impl ModerationCheck {
+    /// Create from configuration, or None if no check URL is set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config
+            .moderation_check_url
+            .clone()
+            .map(|url| ModerationCheck {
+                client: crate::outbound::client(),
+                url,
+                timeout: Duration::from_secs(config.moderation_check_timeout_secs),
+                fail_open: config.moderation_check_fail_open,
+            })
+    }
+
+    /// Whether uploads are accepted when the check cannot be completed
+    pub fn fails_open(&self) -> bool {
+        self.fail_open
+    }
+
+    /// Ask the moderation service about a texture
+    /// Fails if the service cannot be reached, answers non-2xx or without a verdict
+    pub async fn check(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+        hash: &str,
+        bytes: &[u8],
+    ) -> Result<ModerationVerdict> {
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(self.timeout)
+            .header(reqwest::header::CONTENT_TYPE, texture_type.content_type())
+            .header("X-User-Uuid", user_uuid.to_string())
+            .header("X-Texture-Type", texture_type.to_string())
+            .header("X-Texture-Hash", hash)
+            .body(bytes.to_vec())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::config::{Config, MinTlsVersion};
+use anyhow::{anyhow, Result};
+use reqwest::tls::Version;
+use std::sync::OnceLock;
+
+/// Settings installed at startup, used by code that has no access to the config
+/// (e.g. `download_file_from_url`)
+static GLOBAL_SETTINGS: OnceLock<OutboundSettings> = OnceLock::new();
+
+/// Proxy and TLS settings of the outbound HTTP clients (Mojang, texture downloads,
+/// webhooks, JWKS)
+/// Without OUTBOUND_PROXY_URL, reqwest honors HTTP_PROXY, HTTPS_PROXY and NO_PROXY itself
+#[derive(Debug, Clone, Default)]
+pub struct OutboundSettings {
+    proxy: Option<reqwest::Proxy>,
+    min_tls_version: Option<Version>,
+}
+
+impl OutboundSettings {
+    /// Read the settings and build a client with them, so unusable ones fail at startup
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let proxy = config
+            .outbound_proxy_url
+            .as_deref()
+            .map(|url| {
+                // Credentials in the URL are sent as proxy basic auth; NO_PROXY still applies
+                reqwest::Proxy::all(url)
+                    .map(|proxy| proxy.no_proxy(reqwest::NoProxy::from_env()))
+                    .map_err(|e| anyhow!("Invalid OUTBOUND_PROXY_URL: {}", e))
+            })
+            .transpose()?;
+
+        let settings = OutboundSettings {
+            proxy,
+            min_tls_version: config.outbound_min_tls_version.map(|version| match version {
+                MinTlsVersion::Tls10 => Version::TLS_1_0,
+                MinTlsVersion::Tls11 => Version::TLS_1_1,
+                MinTlsVersion::Tls12 => Version::TLS_1_2,
+            }),
+        };
+        settings
+            .apply(reqwest::Client::builder())
+            .build()
+            .map_err(|e| anyhow!("Cannot build outbound HTTP client: {}", e))?;
+
+        Ok(settings)
+    }
+
+    /// Install these settings as the process-wide ones used by `client_builder`
+    pub fn install(self) {
+        if GLOBAL_SETTINGS.set(self).is_err() {
+            tracing::warn!("Outbound HTTP settings already installed, ignoring");
+        }
+    }
+
+    fn apply(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(version) = self.min_tls_version {
+            builder = builder.min_tls_version(version);
+        }
+        builder
+    }
+}
+
+/// Builder of an outbound HTTP client with the installed settings, for clients that
+/// need more, e.g. a timeout
+pub fn client_builder() -> reqwest::ClientBuilder {
+    GLOBAL_SETTINGS
+        .get()
+        .cloned()
+        .unwrap_or_default()
+        .apply(reqwest::Client::builder())
+}
+
+/// Outbound HTTP client with the installed settings
+pub fn client() -> reqwest::Client {
+    client_builder()
+        .build()
+        .expect("Outbound HTTP client settings are checked at startup")
+}
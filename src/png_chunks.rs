@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Chunks kept by `strip_ancillary_chunks`: the image itself and its transparency
+const KEPT_CHUNKS: [&[u8; 4]; 5] = [b"IHDR", b"PLTE", b"tRNS", b"IDAT", b"IEND"];
+
+/// Drop every chunk but IHDR, PLTE, tRNS, IDAT and IEND from a PNG (STRIP_PNG_TEXT_CHUNKS)
+/// Removes tEXt/zTXt/iTXt metadata such as author names and editor software, along with
+/// other ancillary chunks. Kept chunks are copied as they are, so pixel data is not
+/// recompressed. Returns None if there was nothing to drop
+pub fn strip_ancillary_chunks(png_bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut chunks = png_bytes
+        .strip_prefix(&PNG_SIGNATURE)
+        .ok_or_else(|| anyhow!("Invalid PNG: missing signature"))?;
+    let mut stripped = PNG_SIGNATURE.to_vec();
+    let mut dropped = false;
+
+    while !chunks.is_empty() {
+        if chunks.len() < 12 {
+            return Err(anyhow!("Invalid PNG: truncated chunk"));
+        }
+        let length = u32::from_be_bytes(chunks[0..4].try_into().unwrap()) as usize;
+        // Length, type, data and CRC
+        let chunk_len = length
+            .checked_add(12)
+            .filter(|&chunk_len| chunk_len <= chunks.len())
+            .ok_or_else(|| anyhow!("Invalid PNG: truncated chunk"))?;
+        let (chunk, rest) = chunks.split_at(chunk_len);
+        let chunk_type = &chunk[4..8];
+
+        if KEPT_CHUNKS.iter().any(|kept| kept[..] == *chunk_type) {
+            stripped.extend_from_slice(chunk);
+        } else {
+            dropped = true;
+        }
+        chunks = rest;
+
+        // Anything after IEND is not part of the image
+        if chunk_type == b"IEND" {
+            dropped |= !chunks.is_empty();
+            break;
+        }
+    }
+
+    Ok(dropped.then_some(stripped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn decode_pixels(png_bytes: &[u8]) -> Vec<u8> {
+        let mut reader = png::Decoder::new(Cursor::new(png_bytes)).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer).unwrap();
+        buffer.truncate(frame.buffer_size());
+        buffer
+    }
+
+    #[test]
+    fn test_strip_ancillary_chunks_removes_text_and_keeps_pixels() {
+        let rgba: Vec<u8> = (0..64 * 32 * 4).map(|i| (i % 251) as u8).collect();
+        let mut encoded = Vec::new();
+        let mut encoder = png::Encoder::new(&mut encoded, 64, 32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .add_text_chunk("Author".to_string(), "Jane Doe".to_string())
+            .unwrap();
+        encoder
+            .add_itxt_chunk("Software".to_string(), "Skin Editor".to_string())
+            .unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&rgba).unwrap();
+        writer.finish().unwrap();
+
+        let stripped = strip_ancillary_chunks(&encoded).unwrap().unwrap();
+        assert!(!stripped.windows(4).any(|window| window == b"tEXt"));
+        assert!(!stripped.windows(4).any(|window| window == b"iTXt"));
+        assert!(!stripped.windows(8).any(|window| window == b"Jane Doe"));
+        assert_eq!(decode_pixels(&stripped), rgba);
+
+        // Nothing left to strip
+        assert_eq!(strip_ancillary_chunks(&stripped).unwrap(), None);
+        assert!(strip_ancillary_chunks(&stripped[..stripped.len() - 4]).is_err());
+    }
+}
@@ -1,18 +1,41 @@
+use std::any::Any;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::{models::{TextureMetadata, TextureType}};
+use crate::retry::{is_transient_status, RetryPolicy};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use reqwest::StatusCode;
 use uuid::Uuid;
 
 /// Utility function to download a file from a URL
-/// Returns the file bytes or None if the download fails
+/// Returns the file bytes, None if the server reports the file missing (404/410),
+/// or an error for any other failure
+/// Connection errors, timeouts and transient status codes are retried per the global retry policy
 pub async fn download_file_from_url(url: &str) -> Result<Option<Vec<u8>>> {
-    let response = reqwest::Client::new().get(url).send().await?;
+    let client = crate::outbound::client();
+    let response = RetryPolicy::global()
+        .retry("Download", is_transient_download_error, || async {
+            let response = client.get(url).send().await?;
+            if is_transient_status(response.status().as_u16()) {
+                // Turn the transient status into an error so it gets retried
+                return response.error_for_status();
+            }
+            Ok(response)
+        })
+        .await;
 
-    if !response.status().is_success() {
+    // Retries exhausted on a transient status or a network failure
+    let response = response.map_err(|e| anyhow!("Failed to download {}: {}", url, e))?;
+
+    let status = response.status();
+    if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
         return Ok(None);
     }
+    if !status.is_success() {
+        return Err(anyhow!("Failed to download {}: status {}", url, status));
+    }
 
     let bytes = response
         .bytes()
@@ -23,12 +46,25 @@ pub async fn download_file_from_url(url: &str) -> Result<Option<Vec<u8>>> {
     Ok(Some(bytes))
 }
 
+fn is_transient_download_error(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error
+            .status()
+            .is_some_and(|status| is_transient_status(status.as_u16()))
+}
+
 /// Trait defining the interface for texture retrieval strategies
 /// This separates the concern of how textures are fetched from where they are stored
+///
+/// Only `get_textures` and `supports_texture_type` must be implemented; every other
+/// lookup has a default built on top of them, so all retrievers (and `ChainRetriever`)
+/// answer the full set of calls made by the handlers
 #[async_trait]
 pub trait TextureRetriever: Send + Sync {
     /// Retrieve texture metadata for a user
     /// Returns None if the texture is not available from this retrieval source
+    /// Defaults to picking the texture out of `get_textures`
     async fn get_texture(
         &self,
         user_uuid: Uuid,
@@ -38,7 +74,8 @@ pub trait TextureRetriever: Send + Sync {
         Ok(textures.remove(&texture_type.to_string()))
     }
 
-
+    /// Retrieve all textures for a user, keyed by texture type name ("SKIN", "CAPE")
+    /// Returns an empty map if this retrieval source has no textures for the user
     async fn get_textures(
         &self,
         user_uuid: Uuid,
@@ -46,17 +83,31 @@ pub trait TextureRetriever: Send + Sync {
 
     /// Retrieve texture file bytes for a user
     /// Returns None if the texture is not available from this retrieval source
-    /// This is more efficient than downloading via URL for storage-based retrievers
+    /// Defaults to downloading the URL returned by `get_texture`; storage-based
+    /// retrievers override this to read the bytes directly
     async fn get_texture_bytes(
         &self,
         user_uuid: Uuid,
         texture_type: TextureType,
-    ) -> Result<Option<RetrievedTextureBytes>>;
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        let texture = match self.get_texture(user_uuid, texture_type).await? {
+            Some(texture) => texture,
+            None => return Ok(None),
+        };
+
+        Ok(download_file_from_url(&texture.url)
+            .await?
+            .map(|bytes| RetrievedTextureBytes {
+                hash: texture.hash,
+                bytes,
+                metadata: texture.metadata,
+            }))
+    }
 
     /// Retrieve texture file bytes by hash
     /// This allows retrievers with embedded data (like EmbeddedDefaultSkinRetriever) to provide bytes
     /// Returns None if the texture is not available from this retrieval source
-    async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
+    async fn get_texture_bytes_by_hash(&self, _hash: &str) -> Result<Option<RetrievedTextureBytes>> {
         // Default implementation returns None for backward compatibility
         Ok(None)
     }
@@ -75,6 +126,9 @@ pub trait TextureRetriever: Send + Sync {
 
     /// Check if this retriever can provide the given texture type
     fn supports_texture_type(&self, texture_type: TextureType) -> bool;
+
+    /// Convert into `Any` so callers can recover the concrete retriever type
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
 }
 
 /// Represents a successfully retrieved texture
@@ -86,6 +140,8 @@ pub struct RetrievedTexture {
     pub hash: String,
     /// Optional metadata (e.g., model type for skins)
     pub metadata: Option<TextureMetadata>,
+    /// Name of the retriever that provided the texture (e.g., "storage", "mojang")
+    pub source: String,
 }
 
 /// Represents a successfully retrieved texture with file bytes
@@ -1,21 +1,63 @@
 use super::backend::{RetrievedTexture, RetrievedTextureBytes, TextureRetriever};
+use super::circuit_breaker::RetrieverSkipped;
+use crate::config::ChainMode;
 use crate::models::TextureType;
 use anyhow::Result;
 use async_trait::async_trait;
-use std::{collections::HashMap, sync::Arc};
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::{any::Any, collections::HashMap, sync::Arc};
 use uuid::Uuid;
 
 /// Chain of texture retrievers that tries each handler in order
 /// Returns the first successfully retrieved texture
+///
+/// Handlers report a missing texture as `Ok(None)` (or an empty map) and only real
+/// failures as `Err`. A failing handler does not stop the chain, but if no handler
+/// finds the texture the first failure is returned: the texture may exist in the
+/// handler that failed, so "not found" would be wrong. Handlers skipped by an open
+/// circuit breaker are the exception: they count as misses, so an upstream outage does
+/// not turn every unknown user into an error.
+///
+/// In race mode (CHAIN_MODE=race) all eligible handlers are queried at once, so a miss
+/// in storage does not delay the Mojang lookup. The priority order still decides: a
+/// handler's texture is only used once every handler before it has missed, so fallbacks
+/// that answer right away (e.g. default skins) never beat the handlers ahead of them.
+/// Lookups of lower priority than the winner are cancelled.
 pub struct ChainRetriever {
     handlers: Vec<Arc<dyn TextureRetriever>>,
+    mode: ChainMode,
 }
 
 impl ChainRetriever {
     /// Create a new chain with the given handlers
     /// Handlers are tried in the order they are provided
     pub fn new(handlers: Vec<Arc<dyn TextureRetriever>>) -> Self {
-        ChainRetriever { handlers }
+        ChainRetriever {
+            handlers,
+            mode: ChainMode::Sequential,
+        }
+    }
+
+    /// Set how the handlers are queried
+    pub fn with_mode(mut self, mode: ChainMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    fn is_race(&self) -> bool {
+        self.mode == ChainMode::Race
+    }
+
+    /// Handlers able to provide the given texture type, with their index
+    fn eligible(
+        &self,
+        texture_type: TextureType,
+    ) -> impl Iterator<Item = (usize, &Arc<dyn TextureRetriever>)> {
+        self.handlers
+            .iter()
+            .enumerate()
+            .filter(move |(_, handler)| handler.supports_texture_type(texture_type))
     }
 
     /// Add a handler to the end of the chain
@@ -33,6 +75,72 @@ impl ChainRetriever {
     pub fn is_empty(&self) -> bool {
         self.handlers.is_empty()
     }
+
+    /// Find the first handler of the given concrete type, also inside wrapped handlers
+    /// Allows targeted operations (e.g. calling Mojang directly) on handlers built by the factory
+    pub fn find<T: TextureRetriever + 'static>(&self) -> Option<Arc<T>> {
+        self.handlers
+            .iter()
+            .find_map(|handler| super::find_retriever::<T>(handler))
+    }
+}
+
+/// Log a handler that failed and keep the first failure; the chain goes on with the next one
+/// A handler skipped by an open circuit breaker counts as a miss: it is expected, only
+/// logged at debug level, and does not turn "not found" into an error
+fn record_failure(first_error: &mut Option<anyhow::Error>, index: usize, error: anyhow::Error) {
+    if error.is::<RetrieverSkipped>() {
+        tracing::debug!(handler = index, "Handler skipped by its circuit breaker");
+    } else {
+        tracing::warn!(handler = index, error = %error, "Handler failed, trying next handler");
+        first_error.get_or_insert(error);
+    }
+}
+
+/// Outcome of a chain in which no handler found anything
+fn not_found_or_error<T>(first_error: Option<anyhow::Error>, not_found: T) -> Result<T> {
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(not_found),
+    }
+}
+
+/// Run lookups concurrently and return the result of the first one, in the given order,
+/// that finds something
+/// A result is only taken once every lookup before it has missed or failed; the
+/// remaining lookups are then dropped, which cancels them
+async fn race<T>(lookups: Vec<(usize, BoxFuture<'_, Result<Option<T>>>)>) -> Result<Option<T>> {
+    let handlers: Vec<usize> = lookups.iter().map(|(index, _)| *index).collect();
+    let mut pending: FuturesUnordered<_> = lookups
+        .into_iter()
+        .enumerate()
+        .map(|(position, (_, lookup))| async move { (position, lookup.await) })
+        .collect();
+    let mut results: Vec<Option<Result<Option<T>>>> = handlers.iter().map(|_| None).collect();
+    // Lookups before this position all missed or failed
+    let mut decided = 0;
+    let mut first_error = None;
+
+    while let Some((position, result)) = pending.next().await {
+        results[position] = Some(result);
+
+        while let Some(result) = results.get_mut(decided).and_then(Option::take) {
+            let index = handlers[decided];
+            decided += 1;
+            match result {
+                Ok(Some(found)) => {
+                    tracing::debug!(handler = index, "Handler won the race");
+                    return Ok(Some(found));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    record_failure(&mut first_error, index, e);
+                }
+            }
+        }
+    }
+
+    not_found_or_error(first_error, None)
 }
 
 #[async_trait]
@@ -42,59 +150,62 @@ impl TextureRetriever for ChainRetriever {
         user_uuid: Uuid,
         texture_type: TextureType,
     ) -> Result<Option<RetrievedTexture>> {
+        if self.is_race() {
+            return race(
+                self.eligible(texture_type)
+                    .map(|(index, handler)| (index, handler.get_texture(user_uuid, texture_type)))
+                    .collect(),
+            )
+            .await;
+        }
+
+        let mut first_error = None;
+
         // Try each handler in order
         for (index, handler) in self.handlers.iter().enumerate() {
             // Skip handlers that don't support this texture type
             if !handler.supports_texture_type(texture_type) {
                 tracing::debug!(
-                    "Handler {} does not support texture type {:?}, skipping",
-                    index,
-                    texture_type
+                    handler = index,
+                    %texture_type,
+                    "Handler does not support texture type, skipping"
                 );
                 continue;
             }
 
-            tracing::debug!(
-                "Trying handler {} for texture type {:?}",
-                index,
-                texture_type
-            );
+            tracing::debug!(handler = index, %texture_type, "Trying handler");
 
             match handler.get_texture(user_uuid, texture_type).await {
                 Ok(Some(texture)) => {
                     tracing::debug!(
-                        "Handler {} successfully retrieved texture for user {}",
-                        index,
-                        user_uuid
+                        handler = index,
+                        %user_uuid,
+                        "Handler successfully retrieved texture"
                     );
                     return Ok(Some(texture));
                 }
                 Ok(None) => {
                     tracing::debug!(
-                        "Handler {} found no texture for user {}, trying next handler",
-                        index,
-                        user_uuid
+                        handler = index,
+                        %user_uuid,
+                        "Handler found no texture, trying next handler"
                     );
                     // Continue to next handler
                 }
                 Err(e) => {
-                    tracing::warn!(
-                        "Handler {} failed with error: {}, trying next handler",
-                        index,
-                        e
-                    );
                     // Continue to next handler on error
+                    record_failure(&mut first_error, index, e);
                 }
             }
         }
 
         tracing::debug!(
-            "No handler in the chain could retrieve texture type {:?} for user {}",
-            texture_type,
-            user_uuid
+            %texture_type,
+            %user_uuid,
+            "No handler in the chain could retrieve texture"
         );
 
-        Ok(None)
+        not_found_or_error(first_error, None)
     }
 
 
@@ -102,49 +213,56 @@ impl TextureRetriever for ChainRetriever {
         &self,
         user_uuid: Uuid,
     ) -> Result<HashMap<String, RetrievedTexture>> {
+        if self.is_race() {
+            let lookups = self
+                .handlers
+                .iter()
+                .enumerate()
+                .map(|(index, handler)| {
+                    let lookup: BoxFuture<'_, _> = Box::pin(async move {
+                        // An empty map counts as not found
+                        let map = handler.get_textures(user_uuid).await?;
+                        Ok((!map.is_empty()).then_some(map))
+                    });
+                    (index, lookup)
+                })
+                .collect();
+            return Ok(race(lookups).await?.unwrap_or_default());
+        }
+
+        let mut first_error = None;
+
         // Try each handler in order
         for (index, handler) in self.handlers.iter().enumerate() {
-            // Skip handlers that don't support this texture type
-
-            tracing::debug!(
-                "Trying handler {} for all textures",
-                index
-            );
+            tracing::debug!(handler = index, "Trying handler for all textures");
 
             match handler.get_textures(user_uuid).await {
                 Ok(map) => {
                     if map.is_empty() {
                         tracing::debug!(
-                        "Handler {} found no texture for user {}, trying next handler",
-                        index,
-                        user_uuid
-                    );
+                            handler = index,
+                            %user_uuid,
+                            "Handler found no textures, trying next handler"
+                        );
                     } else {
                         tracing::debug!(
-                        "Handler {} successfully retrieved texture for user {}",
-                        index,
-                        user_uuid
-                    );
-                    return Ok(map);
+                            handler = index,
+                            %user_uuid,
+                            "Handler successfully retrieved textures"
+                        );
+                        return Ok(map);
                     }
                 }
                 Err(e) => {
-                    tracing::warn!(
-                        "Handler {} failed with error: {}, trying next handler",
-                        index,
-                        e
-                    );
                     // Continue to next handler on error
+                    record_failure(&mut first_error, index, e);
                 }
             }
         }
 
-        tracing::debug!(
-            "No handler in the chain could retrieve textures for user {}",
-            user_uuid
-        );
+        tracing::debug!(%user_uuid, "No handler in the chain could retrieve textures");
 
-        Ok(HashMap::new())
+        not_found_or_error(first_error, HashMap::new())
     }
 
     async fn get_texture_bytes(
@@ -152,6 +270,19 @@ impl TextureRetriever for ChainRetriever {
         user_uuid: Uuid,
         texture_type: TextureType,
     ) -> Result<Option<RetrievedTextureBytes>> {
+        if self.is_race() {
+            return race(
+                self.eligible(texture_type)
+                    .map(|(index, handler)| {
+                        (index, handler.get_texture_bytes(user_uuid, texture_type))
+                    })
+                    .collect(),
+            )
+            .await;
+        }
+
+        let mut first_error = None;
+
         // Try each handler in order
         for (index, handler) in self.handlers.iter().enumerate() {
             // Skip handlers that don't support this texture type
@@ -162,9 +293,9 @@ impl TextureRetriever for ChainRetriever {
             match handler.get_texture_bytes(user_uuid, texture_type).await {
                 Ok(Some(texture_bytes)) => {
                     tracing::debug!(
-                        "Handler {} successfully retrieved texture bytes for user {}",
-                        index,
-                        user_uuid
+                        handler = index,
+                        %user_uuid,
+                        "Handler successfully retrieved texture bytes"
                     );
                     return Ok(Some(texture_bytes));
                 }
@@ -172,28 +303,37 @@ impl TextureRetriever for ChainRetriever {
                     // Continue to next handler
                 }
                 Err(e) => {
-                    tracing::warn!(
-                        "Handler {} failed with error: {}, trying next handler",
-                        index,
-                        e
-                    );
                     // Continue to next handler on error
+                    record_failure(&mut first_error, index, e);
                 }
             }
         }
 
-        Ok(None)
+        not_found_or_error(first_error, None)
     }
 
     async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
+        if self.is_race() {
+            return race(
+                self.handlers
+                    .iter()
+                    .enumerate()
+                    .map(|(index, handler)| (index, handler.get_texture_bytes_by_hash(hash)))
+                    .collect(),
+            )
+            .await;
+        }
+
+        let mut first_error = None;
+
         // Try each handler in order
         for (index, handler) in self.handlers.iter().enumerate() {
             match handler.get_texture_bytes_by_hash(hash).await {
                 Ok(Some(texture_bytes)) => {
                     tracing::debug!(
-                        "Handler {} successfully retrieved texture bytes for hash {}",
-                        index,
-                        hash
+                        handler = index,
+                        hash,
+                        "Handler successfully retrieved texture bytes by hash"
                     );
                     return Ok(Some(texture_bytes));
                 }
@@ -201,17 +341,13 @@ impl TextureRetriever for ChainRetriever {
                     // Continue to next handler
                 }
                 Err(e) => {
-                    tracing::warn!(
-                        "Handler {} failed with error: {}, trying next handler",
-                        index,
-                        e
-                    );
                     // Continue to next handler on error
+                    record_failure(&mut first_error, index, e);
                 }
             }
         }
 
-        Ok(None)
+        not_found_or_error(first_error, None)
     }
 
     fn supports_texture_type(&self, texture_type: TextureType) -> bool {
@@ -221,11 +357,29 @@ impl TextureRetriever for ChainRetriever {
             .any(|handler| handler.supports_texture_type(texture_type))
     }
 
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
     async fn get_texture_bytes_by_username(
         &self,
         username: &str,
         texture_type: TextureType,
     ) -> Result<Option<RetrievedTextureBytes>> {
+        if self.is_race() {
+            return race(
+                self.eligible(texture_type)
+                    .map(|(index, handler)| {
+                        let lookup = handler.get_texture_bytes_by_username(username, texture_type);
+                        (index, lookup)
+                    })
+                    .collect(),
+            )
+            .await;
+        }
+
+        let mut first_error = None;
+
         // Try each handler in order
         for (index, handler) in self.handlers.iter().enumerate() {
             // Skip handlers that don't support this texture type
@@ -234,10 +388,10 @@ impl TextureRetriever for ChainRetriever {
             }
 
             tracing::debug!(
-                "Trying handler {} for username {} and texture type {:?}",
-                index,
+                handler = index,
                 username,
-                texture_type
+                %texture_type,
+                "Trying handler for username"
             );
 
             match handler
@@ -246,44 +400,41 @@ impl TextureRetriever for ChainRetriever {
             {
                 Ok(Some(texture_bytes)) => {
                     tracing::debug!(
-                        "Handler {} successfully retrieved texture bytes for username {}",
-                        index,
-                        username
+                        handler = index,
+                        username,
+                        "Handler successfully retrieved texture bytes for username"
                     );
                     return Ok(Some(texture_bytes));
                 }
                 Ok(None) => {
                     tracing::debug!(
-                        "Handler {} found no texture for username {}, trying next handler",
-                        index,
-                        username
+                        handler = index,
+                        username,
+                        "Handler found no texture for username, trying next handler"
                     );
                     // Continue to next handler
                 }
                 Err(e) => {
-                    tracing::warn!(
-                        "Handler {} failed with error: {}, trying next handler",
-                        index,
-                        e
-                    );
                     // Continue to next handler on error
+                    record_failure(&mut first_error, index, e);
                 }
             }
         }
 
         tracing::debug!(
-            "No handler in the chain could retrieve texture type {:?} for username {}",
-            texture_type,
-            username
+            %texture_type,
+            username,
+            "No handler in the chain could retrieve texture for username"
         );
 
-        Ok(None)
+        not_found_or_error(first_error, None)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::retrieval::CircuitBreakerRetriever;
     use std::{collections::HashMap, sync::Arc};
 
     // Mock retriever for testing
@@ -336,6 +487,10 @@ mod tests {
         fn supports_texture_type(&self, texture_type: TextureType) -> bool {
             self.supported_types.contains(&texture_type)
         }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
     }
 
     #[tokio::test]
@@ -354,6 +509,7 @@ mod tests {
                 url: "http://example.com/skin.png".to_string(),
                 hash: "abc123".to_string(),
                 metadata: None,
+                source: "mock".to_string(),
             }),
             should_fail: false,
         });
@@ -385,6 +541,7 @@ mod tests {
                 url: "http://example.com/skin.png".to_string(),
                 hash: "abc123".to_string(),
                 metadata: None,
+                source: "mock".to_string(),
             }),
             should_fail: false,
         });
@@ -427,6 +584,72 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[tokio::test]
+    async fn test_chain_returns_error_if_nothing_found_after_failure() {
+        let handler1 = Arc::new(MockRetriever {
+            name: "handler1".to_string(),
+            supported_types: vec![TextureType::SKIN],
+            should_return: None,
+            should_fail: true,
+        });
+
+        let handler2 = Arc::new(MockRetriever {
+            name: "handler2".to_string(),
+            supported_types: vec![TextureType::SKIN],
+            should_return: None,
+            should_fail: false,
+        });
+
+        let chain = ChainRetriever::new(vec![handler1, handler2]);
+
+        // handler2 found nothing, but handler1 might have had the texture
+        assert!(chain
+            .get_texture(Uuid::new_v4(), TextureType::SKIN)
+            .await
+            .is_err());
+        assert!(chain.get_textures(Uuid::new_v4()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chain_treats_open_circuit_breaker_as_miss() {
+        for mode in [ChainMode::Sequential, ChainMode::Race] {
+            let handler1 = Arc::new(MockRetriever {
+                name: "handler1".to_string(),
+                supported_types: vec![TextureType::SKIN],
+                should_return: None,
+                should_fail: false,
+            });
+
+            let failing = Arc::new(MockRetriever {
+                name: "handler2".to_string(),
+                supported_types: vec![TextureType::SKIN],
+                should_return: None,
+                should_fail: true,
+            });
+            let handler2 = Arc::new(CircuitBreakerRetriever::new(
+                failing,
+                1,
+                std::time::Duration::from_secs(60),
+            ));
+
+            let chain = ChainRetriever::new(vec![handler1, handler2]).with_mode(mode);
+            let user_uuid = Uuid::new_v4();
+
+            // The failure that opens the breaker is still reported
+            assert!(chain
+                .get_texture(user_uuid, TextureType::SKIN)
+                .await
+                .is_err());
+
+            // While it is open, the skipped handler counts as a miss
+            let result = chain.get_texture(user_uuid, TextureType::SKIN).await;
+            assert!(result.unwrap().is_none());
+            let result = chain.get_texture_bytes(user_uuid, TextureType::SKIN).await;
+            assert!(result.unwrap().is_none());
+            assert!(chain.get_textures(user_uuid).await.unwrap().is_empty());
+        }
+    }
+
     #[tokio::test]
     async fn test_chain_skips_unsupported_types() {
         let handler1 = Arc::new(MockRetriever {
@@ -443,6 +666,7 @@ mod tests {
                 url: "http://example.com/skin.png".to_string(),
                 hash: "abc123".to_string(),
                 metadata: None,
+                source: "mock".to_string(),
             }),
             should_fail: false,
         });
@@ -458,4 +682,116 @@ mod tests {
         assert!(result.is_some());
         assert_eq!(result.unwrap().url, "http://example.com/skin.png");
     }
+
+    #[tokio::test]
+    async fn test_chain_find_returns_typed_handler() {
+        let handler1 = Arc::new(MockRetriever {
+            name: "handler1".to_string(),
+            supported_types: vec![TextureType::SKIN],
+            should_return: None,
+            should_fail: false,
+        });
+
+        let chain = ChainRetriever::new(vec![handler1]);
+
+        let found = chain.find::<MockRetriever>();
+        assert!(found.is_some());
+        assert_eq!(found.unwrap().name, "handler1");
+        assert!(chain.find::<ChainRetriever>().is_none());
+    }
+
+    // Handler that answers after a delay, with a skin unless `url` is None
+    struct SlowRetriever {
+        delay: std::time::Duration,
+        url: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl TextureRetriever for SlowRetriever {
+        async fn get_textures(
+            &self,
+            _user_uuid: Uuid,
+        ) -> Result<HashMap<String, RetrievedTexture>> {
+            tokio::time::sleep(self.delay).await;
+            let Some(url) = self.url else {
+                return Ok(HashMap::new());
+            };
+            let texture = RetrievedTexture {
+                url: url.to_string(),
+                hash: "abc123".to_string(),
+                metadata: None,
+                source: "mock".to_string(),
+            };
+            Ok(HashMap::from([("SKIN".to_string(), texture)]))
+        }
+
+        fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+            texture_type == TextureType::SKIN
+        }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
+    }
+
+    #[tokio::test]
+    async fn test_race_mode_queries_handlers_concurrently() {
+        let delay = std::time::Duration::from_millis(200);
+        let miss = Arc::new(SlowRetriever { delay, url: None });
+        let found = Arc::new(SlowRetriever {
+            delay,
+            url: Some("http://example.com/found.png"),
+        });
+        let user_uuid = Uuid::new_v4();
+
+        let chain = ChainRetriever::new(vec![miss, found]).with_mode(ChainMode::Race);
+        let started = std::time::Instant::now();
+        let texture = chain
+            .get_texture(user_uuid, TextureType::SKIN)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(texture.url, "http://example.com/found.png");
+        // Both lookups ran at the same time
+        assert!(started.elapsed() < 2 * delay);
+    }
+
+    #[tokio::test]
+    async fn test_race_mode_keeps_priority_order() {
+        let storage = Arc::new(SlowRetriever {
+            delay: std::time::Duration::from_millis(50),
+            url: Some("http://example.com/uploaded.png"),
+        });
+        let default_skin = Arc::new(SlowRetriever {
+            delay: std::time::Duration::ZERO,
+            url: Some("http://example.com/steve.png"),
+        });
+        let user_uuid = Uuid::new_v4();
+
+        let chain = ChainRetriever::new(vec![storage, default_skin.clone()])
+            .with_mode(ChainMode::Race);
+        let texture = chain
+            .get_texture(user_uuid, TextureType::SKIN)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(texture.url, "http://example.com/uploaded.png");
+        assert_eq!(
+            chain.get_textures(user_uuid).await.unwrap()["SKIN"].url,
+            "http://example.com/uploaded.png"
+        );
+
+        // The fallback is used once the handlers before it missed
+        let miss = Arc::new(SlowRetriever {
+            delay: std::time::Duration::from_millis(50),
+            url: None,
+        });
+        let chain = ChainRetriever::new(vec![miss, default_skin]).with_mode(ChainMode::Race);
+        let texture = chain
+            .get_texture(user_uuid, TextureType::SKIN)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(texture.url, "http://example.com/steve.png");
+    }
 }
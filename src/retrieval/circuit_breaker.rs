@@ -0,0 +1,344 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::backend::{RetrievedTexture, RetrievedTextureBytes, TextureRetriever};
+use crate::models::TextureType;
+use anyhow::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+/// Returned for lookups skipped while the breaker is open
+/// Unlike "not found", this says nothing about the texture: chains try their next
+/// handler, and the negative cache does not remember it as a miss
+#[derive(Debug, thiserror::Error)]
+#[error("Circuit breaker open, retriever skipped")]
+pub struct RetrieverSkipped;
+
+tokio::task_local! {
+    /// Set by a breaker that skipped its retriever during a lookup run by `track_skips`
+    static SKIPPED: Cell<bool>;
+}
+
+/// Run a lookup and report whether a circuit breaker skipped its retriever in it
+/// Chains treat a skipped handler as a miss, so a lookup can end in "not found" although
+/// a handler was never asked; callers remembering misses use this to tell them apart
+pub async fn track_skips<F: Future>(lookup: F) -> (F::Output, bool) {
+    SKIPPED
+        .scope(Cell::new(false), async {
+            let output = lookup.await;
+            (output, SKIPPED.with(Cell::get))
+        })
+        .await
+}
+
+/// Whether a call may go to the inner retriever
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Admission {
+    Allowed,
+    /// The single call let through after the cooldown
+    Probe,
+    Skipped,
+}
+
+/// State shared by all calls through the breaker
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    // Set while the breaker is open: calls are skipped until this time
+    open_until: Option<Instant>,
+    // Start of the single call let through after the cooldown to probe the retriever;
+    // a probe that never reports back (e.g. cancelled) is replaced after another cooldown
+    probe_started: Option<Instant>,
+}
+
+/// Stops calling a failing retriever for a while (circuit breaker)
+/// After `failure_threshold` consecutive errors the breaker opens and every lookup
+/// fails with `RetrieverSkipped` immediately for `cooldown`, instead of waiting for the inner
+/// retriever to time out. After the cooldown one call is let through: if it succeeds
+/// the breaker closes, if it fails the breaker opens again.
+/// Only `Err` results count as failures; a texture that does not exist is a success.
+/// Meant for Mojang (MOJANG_CIRCUIT_BREAKER_THRESHOLD) but can wrap any retriever.
+pub struct CircuitBreakerRetriever {
+    inner: Arc<dyn TextureRetriever>,
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreakerRetriever {
+    pub fn new(inner: Arc<dyn TextureRetriever>, failure_threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreakerRetriever {
+            inner,
+            failure_threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// The wrapped retriever
+    pub fn inner(&self) -> &Arc<dyn TextureRetriever> {
+        &self.inner
+    }
+
+    /// Whether a call may go to the inner retriever
+    fn admit(&self) -> Admission {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            None => Admission::Allowed,
+            Some(until) if until > now => Admission::Skipped,
+            Some(_) if state.probe_started.is_some_and(|t| t + self.cooldown > now) => {
+                Admission::Skipped
+            }
+            Some(_) => {
+                // Cooldown over: let this call through as the probe
+                state.probe_started = Some(now);
+                Admission::Probe
+            }
+        }
+    }
+
+    /// Record the outcome of a call that was let through
+    /// Only the probe frees the probe slot; a slower call admitted before the breaker
+    /// opened may finish while the probe is still running
+    fn record(&self, admission: Admission, succeeded: bool) {
+        let mut state = self.state.lock().unwrap();
+        let was_probe = admission == Admission::Probe;
+        if was_probe {
+            state.probe_started = None;
+        }
+
+        if succeeded {
+            if state.open_until.take().is_some() {
+                tracing::info!("Retriever recovered, closing circuit breaker");
+            }
+            state.consecutive_failures = 0;
+            return;
+        }
+
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if was_probe || state.consecutive_failures >= self.failure_threshold {
+            if !was_probe {
+                tracing::warn!(
+                    "Retriever failed {} times in a row, skipping it for {:?}",
+                    state.consecutive_failures,
+                    self.cooldown
+                );
+            }
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+
+    /// Run a lookup through the breaker, failing with `RetrieverSkipped` while it is open
+    async fn call<T, F>(&self, lookup: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let admission = self.admit();
+        if admission == Admission::Skipped {
+            tracing::debug!("Circuit breaker open, skipping retriever");
+            // Outside of `track_skips` there is nobody to tell
+            let _ = SKIPPED.try_with(|skipped| skipped.set(true));
+            return Err(RetrieverSkipped.into());
+        }
+
+        let result = lookup.await;
+        self.record(admission, result.is_ok());
+        result
+    }
+}
+
+#[async_trait]
+impl TextureRetriever for CircuitBreakerRetriever {
+    async fn get_texture(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTexture>> {
+        self.call(self.inner.get_texture(user_uuid, texture_type))
+            .await
+    }
+
+    async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
+        self.call(self.inner.get_textures(user_uuid)).await
+    }
+
+    async fn get_texture_bytes(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        self.call(self.inner.get_texture_bytes(user_uuid, texture_type))
+            .await
+    }
+
+    async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
+        self.call(self.inner.get_texture_bytes_by_hash(hash)).await
+    }
+
+    async fn get_texture_bytes_by_username(
+        &self,
+        username: &str,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        self.call(
+            self.inner
+                .get_texture_bytes_by_username(username, texture_type),
+        )
+        .await
+    }
+
+    fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+        self.inner.supports_texture_type(texture_type)
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retrieval::{ChainRetriever, NegativeCacheRetriever};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    // Retriever that fails while `failing` is set, otherwise finds a skin, and counts the calls
+    #[derive(Default)]
+    struct FlakyRetriever {
+        failing: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TextureRetriever for FlakyRetriever {
+        async fn get_textures(
+            &self,
+            _user_uuid: Uuid,
+        ) -> Result<HashMap<String, RetrievedTexture>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                anyhow::bail!("upstream down");
+            }
+            let texture = RetrievedTexture {
+                url: "http://example.com/skin.png".to_string(),
+                hash: "abc123".to_string(),
+                metadata: None,
+                source: "flaky".to_string(),
+            };
+            Ok(HashMap::from([(TextureType::SKIN.to_string(), texture)]))
+        }
+
+        fn supports_texture_type(&self, _texture_type: TextureType) -> bool {
+            true
+        }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
+    }
+
+    fn is_skipped<T>(result: Result<T>) -> bool {
+        result.is_err_and(|e| e.is::<RetrieverSkipped>())
+    }
+
+    #[tokio::test]
+    async fn test_breaker_opens_after_threshold_and_closes_after_probe() {
+        let inner = Arc::new(FlakyRetriever::default());
+        inner.failing.store(true, Ordering::SeqCst);
+        let breaker = CircuitBreakerRetriever::new(inner.clone(), 2, Duration::from_millis(50));
+        let user_uuid = Uuid::new_v4();
+
+        assert!(breaker.get_textures(user_uuid).await.is_err());
+        assert!(breaker.get_textures(user_uuid).await.is_err());
+
+        // Open: skipped without calling the inner retriever
+        assert!(is_skipped(breaker.get_textures(user_uuid).await));
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+
+        // A failed probe reopens the breaker
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let probe = breaker.get_textures(user_uuid).await;
+        assert!(probe.is_err() && !is_skipped(probe));
+        assert!(is_skipped(breaker.get_textures(user_uuid).await));
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 3);
+
+        // A successful probe closes it
+        inner.failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        breaker.get_textures(user_uuid).await.unwrap();
+        breaker.get_textures(user_uuid).await.unwrap();
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_probe_frees_the_probe_slot() {
+        let inner = Arc::new(FlakyRetriever::default());
+        let breaker = CircuitBreakerRetriever::new(inner, 1, Duration::from_millis(50));
+
+        // A call admitted while closed, still running when the breaker opens
+        let slow_call = breaker.admit();
+        assert_eq!(slow_call, Admission::Allowed);
+        breaker.record(Admission::Allowed, false);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        assert_eq!(breaker.admit(), Admission::Probe);
+        breaker.record(slow_call, false);
+        // The probe is still running, so nothing else gets through
+        assert_eq!(breaker.admit(), Admission::Skipped);
+    }
+
+    #[tokio::test]
+    async fn test_open_breaker_is_not_cached_as_a_miss() {
+        let inner = Arc::new(FlakyRetriever::default());
+        inner.failing.store(true, Ordering::SeqCst);
+        let breaker = Arc::new(CircuitBreakerRetriever::new(
+            inner.clone(),
+            1,
+            Duration::from_millis(50),
+        ));
+        let retriever = NegativeCacheRetriever::new(breaker, Duration::from_secs(60));
+        let user_uuid = Uuid::new_v4();
+
+        assert!(retriever.get_textures(user_uuid).await.is_err());
+        assert!(is_skipped(retriever.get_textures(user_uuid).await));
+
+        // Once the upstream recovers, the user's textures are found again
+        inner.failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let textures = retriever.get_textures(user_uuid).await.unwrap();
+        assert!(textures.contains_key("SKIN"));
+    }
+
+    #[tokio::test]
+    async fn test_open_breaker_in_a_chain_is_not_cached_as_a_miss() {
+        let inner = Arc::new(FlakyRetriever::default());
+        inner.failing.store(true, Ordering::SeqCst);
+        let breaker = Arc::new(CircuitBreakerRetriever::new(
+            inner.clone(),
+            1,
+            Duration::from_millis(50),
+        ));
+        let chain = Arc::new(ChainRetriever::new(vec![breaker]));
+        let retriever = NegativeCacheRetriever::new(chain, Duration::from_secs(60));
+        let user_uuid = Uuid::new_v4();
+
+        assert!(retriever.get_textures(user_uuid).await.is_err());
+        // The chain reports the skipped handler as a miss, which is not remembered
+        assert!(retriever.get_textures(user_uuid).await.unwrap().is_empty());
+        assert!(retriever
+            .get_texture(user_uuid, TextureType::SKIN)
+            .await
+            .unwrap()
+            .is_none());
+
+        inner.failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let textures = retriever.get_textures(user_uuid).await.unwrap();
+        assert!(textures.contains_key("SKIN"));
+    }
+}
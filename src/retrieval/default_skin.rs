@@ -1,19 +1,65 @@
+use std::any::Any;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
 
 use super::backend::{
     download_file_from_url, RetrievedTexture, RetrievedTextureBytes, TextureRetriever,
 };
-use crate::models::TextureType;
+use crate::cache_metrics::CacheMetrics;
+use crate::config::Config;
+use crate::hashing::hash_bytes;
+use crate::models::{SkinModel, TextureMetadata, TextureType};
 use anyhow::Result;
 use async_trait::async_trait;
 use uuid::Uuid;
 
+/// Select the default skin model for a UUID the way vanilla does:
+/// Alex if the Java `UUID.hashCode()` is odd, Steve otherwise
+pub fn default_model_for_uuid(user_uuid: Uuid) -> SkinModel {
+    let (most, least) = user_uuid.as_u64_pair();
+    let hilo = most ^ least;
+    let hash_code = ((hilo >> 32) as u32) ^ (hilo as u32);
+
+    if hash_code & 1 == 1 {
+        SkinModel::Slim
+    } else {
+        SkinModel::Classic
+    }
+}
+
 /// Retrieves default/special textures
-/// Provides the default Minecraft Steve skin for users without custom skins
+/// Provides the default Minecraft Steve skin for users without custom skins,
+/// or the Alex skin for slim-model accounts when model selection is enabled
 pub struct DefaultSkinRetriever {
+    // Replaced as a whole by /api/reload
+    skins: RwLock<DefaultSkins>,
+    // Downloaded bytes by URL, so default textures are only fetched once
+    cache: Mutex<HashMap<String, Vec<u8>>>,
+    cache_metrics: Arc<CacheMetrics>,
+}
+
+#[derive(Clone)]
+struct DefaultSkins {
     // Pre-computed hash and URL for default Steve skin
     default_steve_url: String,
     default_steve_hash: String,
+    // Pre-computed hash and URL for default Alex skin
+    default_alex_url: String,
+    default_alex_hash: String,
+    // Pick Steve or Alex from the UUID instead of always serving Steve
+    model_by_uuid: bool,
+}
+
+impl DefaultSkins {
+    fn from_config(config: &Config) -> Self {
+        DefaultSkins {
+            default_steve_url: config.default_skin_steve_url.clone(),
+            default_steve_hash: config.default_skin_steve_hash.clone(),
+            default_alex_url: config.default_skin_alex_url.clone(),
+            default_alex_hash: config.default_skin_alex_hash.clone(),
+            model_by_uuid: config.default_skin_model_by_uuid,
+        }
+    }
 }
 
 impl DefaultSkinRetriever {
@@ -25,19 +71,122 @@ impl DefaultSkinRetriever {
         let default_steve_hash =
             "1a4af718455d58aab3011401517e43cb6f84b5f9cbd717f8df0334e0b88b8ecf".to_string();
 
+        // The official default Alex skin from Minecraft
+        let default_alex_url = "https://textures.minecraft.net/texture/46acd06e8483b176e8ea39fc12fe105eb3a2a4970f5100057e9d84d4b60bdfa7".to_string();
+
+        // Pre-computed hash of the default Alex skin
+        let default_alex_hash =
+            "46acd06e8483b176e8ea39fc12fe105eb3a2a4970f5100057e9d84d4b60bdfa7".to_string();
+
         DefaultSkinRetriever {
-            default_steve_url,
-            default_steve_hash,
+            skins: RwLock::new(DefaultSkins {
+                default_steve_url,
+                default_steve_hash,
+                default_alex_url,
+                default_alex_hash,
+                model_by_uuid: false,
+            }),
+            cache: Mutex::new(HashMap::new()),
+            cache_metrics: CacheMetrics::register("default_skin"),
+        }
+    }
+
+    /// Create from configuration (DEFAULT_SKIN_* variables)
+    pub fn from_config(config: &Config) -> Self {
+        DefaultSkinRetriever {
+            skins: RwLock::new(DefaultSkins::from_config(config)),
+            cache: Mutex::new(HashMap::new()),
+            cache_metrics: CacheMetrics::register("default_skin"),
         }
     }
 
     /// Create with custom default skin URL and hash
     pub fn with_custom_default(skin_url: String, skin_hash: String) -> Self {
-        DefaultSkinRetriever {
-            default_steve_url: skin_url,
-            default_steve_hash: skin_hash,
+        let retriever = Self::new();
+        {
+            let mut skins = retriever.skins.write().unwrap();
+            skins.default_steve_url = skin_url;
+            skins.default_steve_hash = skin_hash;
+        }
+        retriever
+    }
+
+    /// Switch to the default skins of a reloaded configuration (/api/reload)
+    /// Downloaded bytes are cached by URL, so skins at new URLs are fetched on first use
+    pub fn reload(&self, config: &Config) {
+        *self.skins.write().unwrap() = DefaultSkins::from_config(config);
+    }
+
+    fn skins(&self) -> DefaultSkins {
+        self.skins.read().unwrap().clone()
+    }
+
+    /// Get the default skin for an explicit model hint
+    pub fn default_skin(&self, model: SkinModel) -> RetrievedTexture {
+        let skins = self.skins();
+        match model {
+            SkinModel::Classic => RetrievedTexture {
+                url: skins.default_steve_url,
+                hash: skins.default_steve_hash,
+                metadata: None, // Default skin has no special metadata
+                source: "default_skin".to_string(),
+            },
+            SkinModel::Slim => RetrievedTexture {
+                url: skins.default_alex_url,
+                hash: skins.default_alex_hash,
+                metadata: Some(TextureMetadata {
+                    model: Some(SkinModel::Slim.to_string()),
+                    ..Default::default()
+                }),
+                source: "default_skin".to_string(),
+            },
+        }
+    }
+
+    /// Get the default skin for a user
+    /// Always Steve unless model selection by UUID is enabled
+    pub fn default_skin_for(&self, user_uuid: Uuid) -> RetrievedTexture {
+        if self.skins().model_by_uuid {
+            self.default_skin(default_model_for_uuid(user_uuid))
+        } else {
+            self.default_skin(SkinModel::Classic)
         }
     }
+
+    /// Download a default texture, or take it from the cache if it was downloaded before
+    async fn download_cached(&self, url: &str) -> Result<Option<Vec<u8>>> {
+        if let Some(bytes) = self.cache.lock().unwrap().get(url).cloned() {
+            self.cache_metrics.hit();
+            return Ok(Some(bytes));
+        }
+        self.cache_metrics.miss();
+
+        let bytes = download_file_from_url(url).await?;
+        if let Some(bytes) = &bytes {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), bytes.clone());
+        }
+        Ok(bytes)
+    }
+
+    /// Download a default texture into the cache, logging the outcome
+    /// A failed download is retried on first use, so it never fails startup
+    async fn preload_url(&self, url: &str) {
+        match self.download_cached(url).await {
+            Ok(Some(_)) => tracing::info!("Preloaded default texture {}", url),
+            Ok(None) => tracing::warn!("Failed to preload default texture {}: not found", url),
+            Err(e) => tracing::warn!("Failed to preload default texture {}: {}", url, e),
+        }
+    }
+
+    /// Download the default Steve and Alex skins ahead of the first request (PRELOAD_DEFAULTS)
+    pub async fn preload(&self) {
+        let skins = self.skins();
+        self.preload_url(&skins.default_steve_url).await;
+        self.preload_url(&skins.default_alex_url).await;
+    }
 }
 
 impl Default for DefaultSkinRetriever {
@@ -50,19 +199,15 @@ impl Default for DefaultSkinRetriever {
 impl TextureRetriever for DefaultSkinRetriever {
     async fn get_texture(
         &self,
-        _user_uuid: Uuid,
+        user_uuid: Uuid,
         texture_type: TextureType,
     ) -> Result<Option<RetrievedTexture>> {
         match texture_type {
             TextureType::SKIN => {
-                // Return the default Steve skin for any user requesting a skin
-                Ok(Some(RetrievedTexture {
-                    url: self.default_steve_url.clone(),
-                    hash: self.default_steve_hash.clone(),
-                    metadata: None, // Default skin has no special metadata
-                }))
+                // Return the default skin for any user requesting a skin
+                Ok(Some(self.default_skin_for(user_uuid)))
             }
-            TextureType::CAPE => {
+            TextureType::CAPE | TextureType::ELYTRA => {
                 // Default cape doesn't exist, return None
                 // Capes are optional in Minecraft
                 Ok(None)
@@ -72,21 +217,14 @@ impl TextureRetriever for DefaultSkinRetriever {
 
     async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
         let mut map = HashMap::new();
-        map.insert(
-            "SKIN".to_owned(),
-            RetrievedTexture {
-                url: self.default_steve_url.clone(),
-                hash: self.default_steve_hash.clone(),
-                metadata: None, // Default skin has no special metadata
-            },
-        );
+        map.insert("SKIN".to_owned(), self.default_skin_for(user_uuid));
         Ok(map)
     }
 
     async fn get_texture_bytes(
         &self,
         _user_uuid: Uuid,
-        texture_type: TextureType,
+        _texture_type: TextureType,
     ) -> Result<Option<RetrievedTextureBytes>> {
         // DefaultSkinRetriever doesn't have file bytes, it only has a URL
         // Return None to allow next retriever in chain to handle it
@@ -94,19 +232,26 @@ impl TextureRetriever for DefaultSkinRetriever {
     }
 
     async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
-        // Check if the requested hash matches our default skin
-        if hash == self.default_steve_hash {
-            // Download from the Mojang URL
-            match download_file_from_url(&self.default_steve_url).await? {
-                Some(bytes) => Ok(Some(RetrievedTextureBytes {
-                    hash: self.default_steve_hash.clone(),
-                    bytes,
-                    metadata: None,
-                })),
-                None => Ok(None),
-            }
+        // Check if the requested hash matches one of our default skins
+        let skins = self.skins();
+        let model = if hash == skins.default_steve_hash {
+            SkinModel::Classic
+        } else if hash == skins.default_alex_hash {
+            SkinModel::Slim
         } else {
-            Ok(None)
+            return Ok(None);
+        };
+
+        let texture = self.default_skin(model);
+
+        // Download from the Mojang URL, unless it was already downloaded
+        match self.download_cached(&texture.url).await? {
+            Some(bytes) => Ok(Some(RetrievedTextureBytes {
+                hash: texture.hash,
+                bytes,
+                metadata: texture.metadata,
+            })),
+            None => Ok(None),
         }
     }
 
@@ -114,6 +259,83 @@ impl TextureRetriever for DefaultSkinRetriever {
         // Only supports SKIN type, not CAPE
         matches!(texture_type, TextureType::SKIN)
     }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+/// Last-resort texture bytes served by the download handlers when the retrieval
+/// chain finds nothing (SERVE_DEFAULT_ON_MISS)
+/// Skins fall back to the configured default skin; capes only if DEFAULT_CAPE_URL is set
+pub struct MissFallback {
+    // Also caches the downloaded bytes of every default texture, including the cape
+    skins: DefaultSkinRetriever,
+    cape_url: RwLock<Option<String>>,
+}
+
+impl MissFallback {
+    /// Create from configuration, or None if SERVE_DEFAULT_ON_MISS is disabled
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.serve_default_on_miss.then(|| MissFallback {
+            skins: DefaultSkinRetriever::from_config(config),
+            cape_url: RwLock::new(config.default_cape_url.clone()),
+        })
+    }
+
+    /// Switch to the default textures of a reloaded configuration (/api/reload)
+    pub fn reload(&self, config: &Config) {
+        self.skins.reload(config);
+        *self.cape_url.write().unwrap() = config.default_cape_url.clone();
+    }
+
+    fn cape_url(&self) -> Option<String> {
+        self.cape_url.read().unwrap().clone()
+    }
+
+    /// Download the default skins and cape ahead of the first miss (PRELOAD_DEFAULTS)
+    pub async fn preload(&self) {
+        self.skins.preload().await;
+        if let Some(cape_url) = self.cape_url() {
+            self.skins.preload_url(&cape_url).await;
+        }
+    }
+
+    /// Get the default texture bytes for a missing texture
+    /// The user UUID, when known, selects the Steve/Alex model like DefaultSkinRetriever
+    pub async fn texture_bytes(
+        &self,
+        texture_type: TextureType,
+        user_uuid: Option<Uuid>,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        let (url, hash, metadata) = match texture_type {
+            TextureType::SKIN => {
+                let texture = match user_uuid {
+                    Some(user_uuid) => self.skins.default_skin_for(user_uuid),
+                    None => self.skins.default_skin(SkinModel::Classic),
+                };
+                (texture.url, Some(texture.hash), texture.metadata)
+            }
+            TextureType::CAPE => match self.cape_url() {
+                Some(url) => (url, None, None),
+                None => return Ok(None),
+            },
+            TextureType::ELYTRA => return Ok(None),
+        };
+
+        let bytes = match self.skins.download_cached(&url).await? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+
+        let hash = hash.unwrap_or_else(|| hash_bytes(&bytes));
+
+        Ok(Some(RetrievedTextureBytes {
+            hash,
+            bytes,
+            metadata,
+        }))
+    }
 }
 
 /// Alternative implementation that returns embedded default skin bytes
@@ -128,11 +350,7 @@ impl EmbeddedDefaultSkinRetriever {
     /// Create with embedded default skin data
     /// You would embed the default skin bytes in the binary
     pub fn new(default_skin_data: Vec<u8>, base_url: String) -> Self {
-        use sha2::{Digest, Sha256};
-
-        let mut hasher = Sha256::new();
-        hasher.update(&default_skin_data);
-        let hash = hex::encode(hasher.finalize());
+        let hash = hash_bytes(&default_skin_data);
 
         EmbeddedDefaultSkinRetriever {
             default_skin_data,
@@ -166,9 +384,10 @@ impl TextureRetriever for EmbeddedDefaultSkinRetriever {
                     url,
                     hash: self.default_skin_hash.clone(),
                     metadata: None,
+                    source: "default_skin".to_string(),
                 }))
             }
-            TextureType::CAPE => Ok(None),
+            TextureType::CAPE | TextureType::ELYTRA => Ok(None),
         }
     }
 
@@ -185,6 +404,7 @@ impl TextureRetriever for EmbeddedDefaultSkinRetriever {
                 url,
                 hash: self.default_skin_hash.clone(),
                 metadata: None,
+                source: "default_skin".to_string(),
             },
         );
         Ok(map)
@@ -201,7 +421,7 @@ impl TextureRetriever for EmbeddedDefaultSkinRetriever {
                 bytes: self.default_skin_data.clone(),
                 metadata: None,
             })),
-            TextureType::CAPE => Ok(None),
+            TextureType::CAPE | TextureType::ELYTRA => Ok(None),
         }
     }
 
@@ -221,6 +441,10 @@ impl TextureRetriever for EmbeddedDefaultSkinRetriever {
     fn supports_texture_type(&self, texture_type: TextureType) -> bool {
         matches!(texture_type, TextureType::SKIN)
     }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
 }
 
 impl EmbeddedDefaultSkinRetriever {
@@ -229,3 +453,21 @@ impl EmbeddedDefaultSkinRetriever {
         &self.default_skin_data
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_model_for_uuid_matches_vanilla_parity() {
+        // Java UUID.hashCode() of these values is 0 and 1 respectively
+        let steve = Uuid::parse_str("00000000-0000-0000-0000-000000000000").unwrap();
+        let alex = Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap();
+        // High and low halves cancel out in the XOR, hashCode() is 0
+        let steve_mixed = Uuid::parse_str("00000001-0000-0000-0000-000000000001").unwrap();
+
+        assert_eq!(default_model_for_uuid(steve), SkinModel::Classic);
+        assert_eq!(default_model_for_uuid(alex), SkinModel::Slim);
+        assert_eq!(default_model_for_uuid(steve_mixed), SkinModel::Classic);
+    }
+}
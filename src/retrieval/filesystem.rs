@@ -0,0 +1,176 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::backend::{RetrievedTexture, RetrievedTextureBytes, TextureRetriever};
+use crate::config::Config;
+use crate::hashing::hash_bytes;
+use crate::models::{TextureMetadata, TextureType};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use uuid::Uuid;
+
+/// Reads textures from a directory tree laid out as `<dir>/<uuid>/<type>.png`
+/// (FILESYSTEM_RETRIEVER_PATH), for seeding development environments and CI with known
+/// textures without a database. The UUID is hyphenated and the type lowercase, e.g.
+/// `skin.png`; an optional `<type>.json` next to a texture holds its metadata, such as
+/// `{"model": "slim"}`. Texture URLs point at /download/{type}/{uuid}, which reads the
+/// file through the retriever again.
+pub struct FilesystemRetriever {
+    base_dir: PathBuf,
+    base_url: String,
+}
+
+impl FilesystemRetriever {
+    pub fn new(config: &Config) -> Self {
+        FilesystemRetriever {
+            base_dir: config
+                .filesystem_retriever_path
+                .clone()
+                .map(PathBuf::from)
+                .expect("FILESYSTEM_RETRIEVER_PATH must be configured for the filesystem retriever"),
+            base_url: config.base_url.clone(),
+        }
+    }
+
+    /// Path of a user's file of a texture type, `<dir>/<uuid>/<type>.<extension>`
+    fn file_path(&self, user_uuid: Uuid, texture_type: TextureType, extension: &str) -> PathBuf {
+        self.base_dir.join(user_uuid.hyphenated().to_string()).join(format!(
+            "{}.{}",
+            texture_type.to_string().to_lowercase(),
+            extension
+        ))
+    }
+
+    async fn metadata(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<TextureMetadata>> {
+        let path = self.file_path(user_uuid, texture_type, "json");
+
+        match read_file(&path).await? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| anyhow!("Invalid metadata file {}: {}", path.display(), e)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Contents of a file, or None if it does not exist
+async fn read_file(path: &Path) -> Result<Option<Vec<u8>>> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+#[async_trait]
+impl TextureRetriever for FilesystemRetriever {
+    async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
+        let mut textures = HashMap::new();
+
+        for texture_type in TextureType::ALL {
+            if let Some(texture) = self.get_texture_bytes(user_uuid, texture_type).await? {
+                textures.insert(
+                    texture_type.to_string(),
+                    RetrievedTexture {
+                        url: format!(
+                            "{}/download/{}/{}",
+                            self.base_url.trim_end_matches('/'),
+                            texture_type.to_string().to_lowercase(),
+                            user_uuid
+                        ),
+                        hash: texture.hash,
+                        metadata: texture.metadata,
+                        source: "filesystem".to_string(),
+                    },
+                );
+            }
+        }
+
+        Ok(textures)
+    }
+
+    async fn get_texture_bytes(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        let path = self.file_path(user_uuid, texture_type, texture_type.file_extension());
+        let bytes = match read_file(&path).await? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+
+        Ok(Some(RetrievedTextureBytes {
+            hash: hash_bytes(&bytes),
+            metadata: self.metadata(user_uuid, texture_type).await?,
+            bytes,
+        }))
+    }
+
+    fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+        matches!(
+            texture_type,
+            TextureType::SKIN | TextureType::CAPE | TextureType::ELYTRA
+        )
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reads_textures_and_metadata_from_uuid_directory() {
+        let base_dir = std::env::temp_dir().join(format!("filesystem-{}", Uuid::new_v4()));
+        let user_uuid = Uuid::new_v4();
+        let user_dir = base_dir.join(user_uuid.hyphenated().to_string());
+        tokio::fs::create_dir_all(&user_dir).await.unwrap();
+        tokio::fs::write(user_dir.join("skin.png"), b"skin").await.unwrap();
+        tokio::fs::write(user_dir.join("skin.json"), br#"{"model": "slim"}"#)
+            .await
+            .unwrap();
+        tokio::fs::write(user_dir.join("cape.png"), b"cape").await.unwrap();
+
+        let retriever = FilesystemRetriever {
+            base_dir: base_dir.clone(),
+            base_url: "http://localhost/".to_string(),
+        };
+
+        let textures = retriever.get_textures(user_uuid).await.unwrap();
+        assert_eq!(textures.len(), 2);
+        assert_eq!(textures["SKIN"].hash, hash_bytes(b"skin"));
+        assert_eq!(
+            textures["SKIN"].metadata.as_ref().unwrap().model.as_deref(),
+            Some("slim")
+        );
+        assert_eq!(
+            textures["CAPE"].url,
+            format!("http://localhost/download/cape/{}", user_uuid)
+        );
+
+        let cape = retriever
+            .get_texture_bytes(user_uuid, TextureType::CAPE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cape.bytes, b"cape");
+        assert!(cape.metadata.is_none());
+        assert!(retriever
+            .get_texture_bytes(Uuid::new_v4(), TextureType::SKIN)
+            .await
+            .unwrap()
+            .is_none());
+
+        tokio::fs::remove_dir_all(&base_dir).await.unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::backend::{
+    download_file_from_url, RetrievedTexture, RetrievedTextureBytes, TextureRetriever,
+};
+use super::default_skin::DefaultSkinRetriever;
+use crate::config::Config;
+use crate::models::{TextureMetadata, TextureType};
+use crate::storage::StorageBackend;
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Retrieves per-permission-group default skins
+/// Users listed in `user_groups` get the skin configured for their group in
+/// `group_default_skins`, or the global default skin if their group has none.
+/// Users without a group are left to the next retriever in the chain.
+pub struct GroupDefaultSkinRetriever {
+    db: PgPool,
+    storage: Arc<dyn StorageBackend>,
+    // Global default for groups without a configured skin
+    fallback: DefaultSkinRetriever,
+}
+
+/// Default skin selected for a grouped user
+enum GroupSkin {
+    /// Skin configured for the group, stored in the storage backend
+    Group {
+        hash: String,
+        metadata: Option<TextureMetadata>,
+    },
+    /// The group has no skin configured
+    Fallback,
+}
+
+impl GroupDefaultSkinRetriever {
+    pub fn new(config: &Config, storage: Arc<dyn StorageBackend>, db: PgPool) -> Self {
+        GroupDefaultSkinRetriever {
+            db,
+            storage,
+            fallback: DefaultSkinRetriever::from_config(config),
+        }
+    }
+
+    /// Switch the global default of a reloaded configuration (/api/reload)
+    pub fn reload(&self, config: &Config) {
+        self.fallback.reload(config);
+    }
+
+    /// Look up the default skin of the user's group
+    /// Returns None if the user has no group
+    async fn group_skin(&self, user_uuid: Uuid) -> Result<Option<GroupSkin>> {
+        let row = sqlx::query!(
+            r#"
+            SELECT g.group_name, s.skin_hash AS "skin_hash?", s.metadata
+            FROM user_groups g
+            LEFT JOIN group_default_skins s ON s.group_name = g.group_name
+            WHERE g.user_uuid = $1
+            "#,
+            user_uuid
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(row.map(|row| match row.skin_hash {
+            Some(hash) => GroupSkin::Group {
+                hash,
+                metadata: row.metadata.and_then(|v| serde_json::from_value(v).ok()),
+            },
+            None => {
+                tracing::debug!(
+                    group = %row.group_name,
+                    "Group has no default skin, using the global default"
+                );
+                GroupSkin::Fallback
+            }
+        }))
+    }
+
+    fn retrieved_texture(&self, user_uuid: Uuid, skin: GroupSkin) -> RetrievedTexture {
+        match skin {
+            GroupSkin::Group { hash, metadata } => RetrievedTexture {
+                url: self.storage.generate_url(
+                    &self.storage.texture_key(&hash, TextureType::SKIN),
+                    TextureType::SKIN.file_extension(),
+                ),
+                hash,
+                metadata,
+                source: "group_default_skin".to_string(),
+            },
+            GroupSkin::Fallback => self.fallback.default_skin_for(user_uuid),
+        }
+    }
+}
+
+#[async_trait]
+impl TextureRetriever for GroupDefaultSkinRetriever {
+    async fn get_texture(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTexture>> {
+        if texture_type != TextureType::SKIN {
+            // Groups only configure skins
+            return Ok(None);
+        }
+
+        Ok(self
+            .group_skin(user_uuid)
+            .await?
+            .map(|skin| self.retrieved_texture(user_uuid, skin)))
+    }
+
+    async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
+        let mut map = HashMap::new();
+        if let Some(skin) = self.group_skin(user_uuid).await? {
+            map.insert("SKIN".to_owned(), self.retrieved_texture(user_uuid, skin));
+        }
+        Ok(map)
+    }
+
+    async fn get_texture_bytes(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        if texture_type != TextureType::SKIN {
+            return Ok(None);
+        }
+
+        match self.group_skin(user_uuid).await? {
+            Some(GroupSkin::Group { hash, metadata }) => {
+                let bytes = self.storage.get_texture(&hash, TextureType::SKIN).await?;
+                Ok(Some(RetrievedTextureBytes {
+                    hash,
+                    bytes,
+                    metadata,
+                }))
+            }
+            Some(GroupSkin::Fallback) => {
+                let texture = self.fallback.default_skin_for(user_uuid);
+                Ok(download_file_from_url(&texture.url)
+                    .await?
+                    .map(|bytes| RetrievedTextureBytes {
+                        hash: texture.hash,
+                        bytes,
+                        metadata: texture.metadata,
+                    }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
+        let group_skin = sqlx::query!(
+            r#"
+            SELECT metadata
+            FROM group_default_skins
+            WHERE skin_hash = $1
+            LIMIT 1
+            "#,
+            hash
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        match group_skin {
+            Some(group_skin) => {
+                let bytes = self.storage.get_texture(hash, TextureType::SKIN).await?;
+                Ok(Some(RetrievedTextureBytes {
+                    hash: hash.to_string(),
+                    bytes,
+                    metadata: group_skin
+                        .metadata
+                        .and_then(|v| serde_json::from_value(v).ok()),
+                }))
+            }
+            // Not a group skin, it may still be one of the global defaults
+            None => self.fallback.get_texture_bytes_by_hash(hash).await,
+        }
+    }
+
+    fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+        // Only supports SKIN type, not CAPE
+        matches!(texture_type, TextureType::SKIN)
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
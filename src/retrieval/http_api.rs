@@ -0,0 +1,378 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::backend::{
+    download_file_from_url, RetrievedTexture, RetrievedTextureBytes, TextureRetriever,
+};
+use super::mojang::extract_hash_from_url;
+use crate::config::{username_key, Config};
+use crate::models::{TextureMetadata, TextureType};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A texture in an upstream response
+#[derive(Debug, Deserialize)]
+struct ApiTexture {
+    url: String,
+    /// Taken from the last segment of the URL if the upstream does not send it
+    #[serde(default, alias = "digest")]
+    hash: Option<String>,
+    #[serde(default)]
+    metadata: Option<TextureMetadata>,
+}
+
+/// Retrieves textures from an arbitrary JSON API (HTTP_RETRIEVER_URL)
+/// The URL template may use `{uuid}`, `{username}` (from username_mappings) and `{type}`.
+/// Without `{type}`, one request returns all textures of a user as an object keyed by
+/// texture type name; with it, every texture type is requested separately and the
+/// response is a single texture. Either is looked up at HTTP_RETRIEVER_TEXTURES_POINTER
+/// (a JSON pointer, the whole body by default). A texture is `{"url": ..., "hash": ...,
+/// "metadata": ...}` where only `url` is required; bytes are downloaded from that URL.
+pub struct HttpApiRetriever {
+    client: reqwest::Client,
+    url_template: String,
+    textures_pointer: String,
+    db: PgPool,
+    case_insensitive_usernames: bool,
+}
+
+impl HttpApiRetriever {
+    pub fn new(config: &Config, db: PgPool) -> Self {
+        let client = crate::outbound::client_builder()
+            .timeout(Duration::from_secs(config.http_retriever_timeout_secs))
+            .build()
+            .expect("HTTP client with a timeout");
+
+        HttpApiRetriever {
+            client,
+            url_template: config
+                .http_retriever_url
+                .clone()
+                .expect("HTTP_RETRIEVER_URL must be configured for the http_api retriever"),
+            textures_pointer: config.http_retriever_textures_pointer.clone(),
+            db,
+            case_insensitive_usernames: config.case_insensitive_usernames,
+        }
+    }
+
+    fn uses(&self, placeholder: &str) -> bool {
+        self.url_template.contains(placeholder)
+    }
+
+    /// Latest username mapped to a UUID, for the `{username}` placeholder
+    async fn username_for(&self, user_uuid: Uuid) -> Result<Option<String>> {
+        let record = sqlx::query!(
+            r#"
+            SELECT COALESCE(display_username, username) AS "username!"
+            FROM username_mappings
+            WHERE user_uuid = $1
+            ORDER BY updated_at DESC
+            LIMIT 1
+            "#,
+            user_uuid
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(record.map(|record| record.username))
+    }
+
+    /// Latest UUID mapped to a username, for the `{uuid}` placeholder
+    async fn uuid_for(&self, username: &str) -> Result<Option<Uuid>> {
+        let record = sqlx::query!(
+            r#"
+            SELECT user_uuid
+            FROM username_mappings
+            WHERE username = $1
+            ORDER BY updated_at DESC
+            LIMIT 1
+            "#,
+            username_key(username, self.case_insensitive_usernames)
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(record.map(|record| record.user_uuid))
+    }
+
+    /// Fill in the placeholders the template uses for a user given by UUID
+    /// Returns None if the template needs a username that is not known
+    async fn user_by_uuid(&self, user_uuid: Uuid) -> Result<Option<User>> {
+        let username = if self.uses("{username}") {
+            match self.username_for(user_uuid).await? {
+                Some(username) => Some(username),
+                None => {
+                    tracing::debug!("No username mapping for UUID {}", user_uuid);
+                    return Ok(None);
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Some(User {
+            uuid: Some(user_uuid),
+            username,
+        }))
+    }
+
+    /// Fill in the placeholders the template uses for a user given by username
+    /// Returns None if the template needs a UUID that is not known
+    async fn user_by_username(&self, username: &str) -> Result<Option<User>> {
+        let uuid = if self.uses("{uuid}") {
+            match self.uuid_for(username).await? {
+                Some(uuid) => Some(uuid),
+                None => {
+                    tracing::debug!("No UUID mapping for username {}", username);
+                    return Ok(None);
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Some(User {
+            uuid,
+            username: Some(username.to_string()),
+        }))
+    }
+
+    /// Request a URL and return the part of the response at the textures pointer
+    /// Returns None if the upstream has nothing for the user (204/404 or a missing field)
+    async fn fetch(&self, url: &str) -> Result<Option<Value>> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| anyhow!("HTTP retriever request failed: {}", e))?;
+
+        if matches!(
+            response.status(),
+            StatusCode::NO_CONTENT | StatusCode::NOT_FOUND
+        ) {
+            return Ok(None);
+        }
+
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "HTTP retriever upstream returned error: {}",
+                response.status()
+            ));
+        }
+
+        let mut body: Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse HTTP retriever response: {}", e))?;
+
+        Ok(body
+            .pointer_mut(&self.textures_pointer)
+            .map(Value::take)
+            .filter(|value| !value.is_null()))
+    }
+
+    async fn textures_of(&self, user: &User) -> Result<HashMap<String, RetrievedTexture>> {
+        if self.uses("{type}") {
+            let lookups = TextureType::ALL
+                .into_iter()
+                .map(|texture_type| self.texture_of(user, texture_type));
+            let textures = futures::future::try_join_all(lookups).await?;
+
+            return Ok(TextureType::ALL
+                .into_iter()
+                .zip(textures)
+                .filter_map(|(texture_type, texture)| Some((texture_type.to_string(), texture?)))
+                .collect());
+        }
+
+        let url = fill_template(&self.url_template, user, None);
+        Ok(self
+            .fetch(&url)
+            .await?
+            .map(parse_textures)
+            .unwrap_or_default())
+    }
+
+    async fn texture_of(
+        &self,
+        user: &User,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTexture>> {
+        if !self.uses("{type}") {
+            let mut textures = self.textures_of(user).await?;
+            return Ok(textures.remove(&texture_type.to_string()));
+        }
+
+        let url = fill_template(&self.url_template, user, Some(texture_type));
+        Ok(self.fetch(&url).await?.and_then(parse_texture))
+    }
+}
+
+/// Values for the placeholders of the URL template
+struct User {
+    uuid: Option<Uuid>,
+    username: Option<String>,
+}
+
+/// Replace the placeholders of a URL template
+/// Values are percent-encoded, so a username cannot add path segments or query parameters
+fn fill_template(template: &str, user: &User, texture_type: Option<TextureType>) -> String {
+    let mut url = template.to_string();
+    if let Some(uuid) = user.uuid {
+        url = url.replace("{uuid}", &uuid.to_string());
+    }
+    if let Some(username) = &user.username {
+        url = url.replace("{username}", &urlencoding::encode(username));
+    }
+    if let Some(texture_type) = texture_type {
+        url = url.replace("{type}", &texture_type.to_string());
+    }
+    url
+}
+
+/// Read a single texture, skipping (with a warning) values that are not one
+fn parse_texture(value: Value) -> Option<RetrievedTexture> {
+    let texture: ApiTexture = match serde_json::from_value(value) {
+        Ok(texture) => texture,
+        Err(e) => {
+            tracing::warn!("Ignoring invalid texture from HTTP retriever: {}", e);
+            return None;
+        }
+    };
+
+    let hash = texture
+        .hash
+        .or_else(|| extract_hash_from_url(&texture.url).map(str::to_string))
+        .unwrap_or_default();
+
+    Some(RetrievedTexture {
+        url: texture.url,
+        hash,
+        metadata: texture.metadata,
+        source: "http_api".to_string(),
+    })
+}
+
+/// Read an object of textures keyed by texture type name
+/// Keys that are not texture types (or aliases) and null entries are ignored
+fn parse_textures(value: Value) -> HashMap<String, RetrievedTexture> {
+    let Value::Object(entries) = value else {
+        tracing::warn!("Ignoring HTTP retriever response that is not an object of textures");
+        return HashMap::new();
+    };
+
+    entries
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .filter_map(|(key, value)| {
+            let texture_type: TextureType = key.parse().ok()?;
+            Some((texture_type.to_string(), parse_texture(value)?))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl TextureRetriever for HttpApiRetriever {
+    async fn get_texture(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTexture>> {
+        match self.user_by_uuid(user_uuid).await? {
+            Some(user) => self.texture_of(&user, texture_type).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
+        match self.user_by_uuid(user_uuid).await? {
+            Some(user) => self.textures_of(&user).await,
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    async fn get_texture_bytes_by_username(
+        &self,
+        username: &str,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        let Some(user) = self.user_by_username(username).await? else {
+            return Ok(None);
+        };
+        let Some(texture) = self.texture_of(&user, texture_type).await? else {
+            return Ok(None);
+        };
+
+        Ok(download_file_from_url(&texture.url)
+            .await?
+            .map(|bytes| RetrievedTextureBytes {
+                hash: texture.hash,
+                bytes,
+                metadata: texture.metadata,
+            }))
+    }
+
+    fn supports_texture_type(&self, _texture_type: TextureType) -> bool {
+        true
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_textures_and_fill_template() {
+        let textures = parse_textures(json!({
+            "skin": {"url": "https://cdn.example.com/abc.png", "metadata": {"model": "slim"}},
+            "CAPE": {"url": "https://cdn.example.com/cape", "digest": "def"},
+            "ELYTRA": null,
+            "banner": {"url": "https://cdn.example.com/banner.png"}
+        }));
+
+        assert_eq!(textures.len(), 2);
+        assert_eq!(textures["SKIN"].hash, "abc");
+        assert!(textures["SKIN"].metadata.is_some());
+        assert_eq!(textures["CAPE"].hash, "def");
+        assert_eq!(textures["CAPE"].source, "http_api");
+
+        let user = User {
+            uuid: Some(Uuid::nil()),
+            username: Some("Notch".to_string()),
+        };
+        assert_eq!(
+            fill_template(
+                "http://players/{username}/{uuid}/{type}",
+                &user,
+                Some(TextureType::CAPE)
+            ),
+            "http://players/Notch/00000000-0000-0000-0000-000000000000/CAPE"
+        );
+
+        let user = User {
+            uuid: None,
+            username: Some("a&admin=1/../b c".to_string()),
+        };
+        assert_eq!(
+            fill_template(
+                "http://players?name={username}&type={type}",
+                &user,
+                Some(TextureType::SKIN)
+            ),
+            "http://players?name=a%26admin%3D1%2F..%2Fb%20c&type=SKIN"
+        );
+    }
+}
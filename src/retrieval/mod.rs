@@ -1,69 +1,208 @@
 pub mod backend;
 pub mod chain;
+pub mod circuit_breaker;
 pub mod default_skin;
+pub mod filesystem;
+pub mod group_default_skin;
+pub mod http_api;
 pub mod mojang;
+pub mod negative_cache;
+pub mod static_skin;
 pub mod storage_retriever;
+pub mod type_dispatch;
+pub mod type_fallback;
 
 pub use backend::{download_file_from_url, TextureRetriever};
 pub use chain::ChainRetriever;
-pub use default_skin::{DefaultSkinRetriever, EmbeddedDefaultSkinRetriever};
-pub use mojang::MojangRetriever;
+pub use circuit_breaker::CircuitBreakerRetriever;
+pub use default_skin::{DefaultSkinRetriever, EmbeddedDefaultSkinRetriever, MissFallback};
+pub use filesystem::FilesystemRetriever;
+pub use group_default_skin::GroupDefaultSkinRetriever;
+pub use http_api::HttpApiRetriever;
+pub use mojang::{MojangError, MojangRetriever};
+pub use negative_cache::NegativeCacheRetriever;
+pub use static_skin::StaticSkinRetriever;
 pub use storage_retriever::StorageRetriever;
+pub use type_dispatch::TypeDispatchRetriever;
+pub use type_fallback::TypeFallbackRetriever;
 
 use crate::config::{Config, RetrievalType};
+use crate::models::TextureType;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Find a retriever of the given concrete type, looking inside chains, the negative
+/// cache, type fallbacks, per-type retrievers and circuit breakers
+/// Returns None if the configured retriever is neither of that type nor wraps one
+pub fn find_retriever<T: TextureRetriever + 'static>(
+    retriever: &Arc<dyn TextureRetriever>,
+) -> Option<Arc<T>> {
+    let any = match retriever.clone().into_any().downcast::<T>() {
+        Ok(found) => return Some(found),
+        Err(any) => any,
+    };
+
+    let any = match any.downcast::<NegativeCacheRetriever>() {
+        Ok(cache) => return find_retriever::<T>(cache.inner()),
+        Err(any) => any,
+    };
+
+    let any = match any.downcast::<TypeFallbackRetriever>() {
+        Ok(fallback) => return find_retriever::<T>(fallback.inner()),
+        Err(any) => any,
+    };
+
+    let any = match any.downcast::<TypeDispatchRetriever>() {
+        Ok(dispatch) => return dispatch.retrievers().find_map(find_retriever::<T>),
+        Err(any) => any,
+    };
+
+    match any.downcast::<CircuitBreakerRetriever>() {
+        Ok(breaker) => find_retriever::<T>(breaker.inner()),
+        Err(any) => any
+            .downcast::<ChainRetriever>()
+            .ok()
+            .and_then(|chain| chain.find::<T>()),
+    }
+}
 
 /// Factory function to create the appropriate texture retriever based on configuration
 /// If retrieval_chain is configured, returns a ChainRetriever with all handlers in order
 /// Otherwise, returns a single retriever based on retrieval_type
+/// Skins and capes with a chain of their own are sent to it by a TypeDispatchRetriever
+/// With TYPE_FALLBACK set, it is wrapped in a TypeFallbackRetriever, and with
+/// NEGATIVE_CACHE_SECONDS set, the result is wrapped in a NegativeCacheRetriever
 pub fn create_retriever(
     config: Config,
     storage: Arc<dyn crate::storage::StorageBackend>,
     db: sqlx::PgPool,
 ) -> Arc<dyn TextureRetriever> {
-    // If retrieval_chain is configured, build a chain of retrievers
-    if let Some(chain_types) = &config.retrieval_chain {
-        if chain_types.is_empty() {
-            tracing::warn!("RETRIEVAL_CHAIN is empty, falling back to single retriever");
-            return create_single_retriever(&config, storage, db);
-        }
-
-        tracing::info!(
-            "Creating retrieval chain with {} handlers: {:?}",
-            chain_types.len(),
-            chain_types
-        );
+    let mut retriever = create_uncached_retriever(&config, storage, db);
 
-        let handlers: Vec<Arc<dyn TextureRetriever>> = chain_types
-            .iter()
-            .map(|retrieval_type| {
-                create_retriever_by_type(retrieval_type, &config, storage.clone(), db.clone())
-            })
-            .collect();
+    if !config.type_fallback.is_empty() {
+        tracing::info!("Falling back between texture types: {:?}", config.type_fallback);
+        retriever = Arc::new(TypeFallbackRetriever::new(
+            retriever,
+            config.type_fallback.clone(),
+        ));
+    }
 
+    if config.negative_cache_seconds > 0 {
         tracing::info!(
-            "Created chain retriever with {} handlers in order",
-            handlers.len()
+            "Caching missing textures for {} seconds",
+            config.negative_cache_seconds
         );
-
-        return Arc::new(ChainRetriever::new(handlers));
+        return Arc::new(NegativeCacheRetriever::new(
+            retriever,
+            Duration::from_secs(config.negative_cache_seconds),
+        ));
     }
 
-    // Fallback to single retriever based on retrieval_type
-    create_single_retriever(&config, storage, db)
+    retriever
 }
 
-/// Create a single retriever based on the retrieval_type
-fn create_single_retriever(
+/// Create the configured chain or single retriever, dispatching skins and capes to their
+/// own chains when RETRIEVAL_CHAIN_SKIN or RETRIEVAL_CHAIN_CAPE is set
+fn create_uncached_retriever(
     config: &Config,
     storage: Arc<dyn crate::storage::StorageBackend>,
     db: sqlx::PgPool,
 ) -> Arc<dyn TextureRetriever> {
+    let mut factory = RetrieverFactory {
+        config,
+        storage,
+        db,
+        created: Vec::new(),
+    };
+
+    // If retrieval_chain is configured, build a chain of retrievers
+    let default = match &config.retrieval_chain {
+        Some(chain_types) => create_chain(&mut factory, "RETRIEVAL_CHAIN", chain_types),
+        None => {
+            // Fallback to single retriever based on retrieval_type
+            tracing::info!(
+                "Creating single retriever of type: {:?}",
+                config.retrieval_type
+            );
+            factory.retriever(&config.retrieval_type)
+        }
+    };
+
+    let mut by_type = HashMap::new();
+    for texture_type in TextureType::ALL {
+        if let Some(chain_types) = config.retrieval_chain_for(texture_type) {
+            let name = format!("RETRIEVAL_CHAIN_{}", texture_type);
+            by_type.insert(
+                texture_type,
+                create_chain(&mut factory, &name, chain_types),
+            );
+        }
+    }
+
+    if by_type.is_empty() {
+        return default;
+    }
+    Arc::new(TypeDispatchRetriever::new(default, by_type))
+}
+
+/// Create a chain of retrievers of the given types, in order
+fn create_chain(
+    factory: &mut RetrieverFactory,
+    name: &str,
+    chain_types: &[RetrievalType],
+) -> Arc<dyn TextureRetriever> {
+    let config = factory.config;
+    if chain_types.is_empty() {
+        tracing::warn!("{} is empty, falling back to single retriever", name);
+        return factory.retriever(&config.retrieval_type);
+    }
+
+    tracing::info!(
+        "Creating {} with {} handlers: {:?}",
+        name,
+        chain_types.len(),
+        chain_types
+    );
+
+    let handlers: Vec<Arc<dyn TextureRetriever>> = chain_types
+        .iter()
+        .map(|retrieval_type| factory.retriever(retrieval_type))
+        .collect();
+
     tracing::info!(
-        "Creating single retriever of type: {:?}",
-        config.retrieval_type
+        "Created chain retriever with {} handlers in {:?} mode",
+        handlers.len(),
+        config.chain_mode
     );
-    create_retriever_by_type(&config.retrieval_type, config, storage, db)
+
+    Arc::new(ChainRetriever::new(handlers).with_mode(config.chain_mode))
+}
+
+/// Creates each retrieval type once, so chains listing the same type share one retriever
+/// (and with it Mojang's rate limit pause and circuit breaker)
+struct RetrieverFactory<'a> {
+    config: &'a Config,
+    storage: Arc<dyn crate::storage::StorageBackend>,
+    db: sqlx::PgPool,
+    created: Vec<(RetrievalType, Arc<dyn TextureRetriever>)>,
+}
+
+impl RetrieverFactory<'_> {
+    fn retriever(&mut self, retrieval_type: &RetrievalType) -> Arc<dyn TextureRetriever> {
+        if let Some((_, retriever)) = self.created.iter().find(|(t, _)| t == retrieval_type) {
+            return retriever.clone();
+        }
+
+        let retriever = create_retriever_by_type(
+            retrieval_type,
+            self.config,
+            self.storage.clone(),
+            self.db.clone(),
+        );
+        self.created.push((retrieval_type.clone(), retriever.clone()));
+        retriever
+    }
 }
 
 /// Create a retriever for a specific retrieval type
@@ -76,15 +215,45 @@ fn create_retriever_by_type(
     match retrieval_type {
         RetrievalType::Storage => {
             tracing::debug!("Creating StorageRetriever");
-            Arc::new(StorageRetriever::new(storage, db))
+            Arc::new(
+                StorageRetriever::new(storage, db)
+                    .with_hash_verification(config.verify_hash_on_read)
+                    .with_elytra_from_cape(config.derive_elytra_from_cape)
+                    .with_metadata_sidecar(config.store_metadata_sidecar)
+                    .with_case_insensitive_usernames(config.case_insensitive_usernames),
+            )
         }
         RetrievalType::Mojang => {
             tracing::debug!("Creating MojangRetriever");
-            Arc::new(MojangRetriever::new(config.clone(), Some(db)))
+            let mojang = Arc::new(MojangRetriever::new(config.clone(), Some(db)));
+            if config.mojang_circuit_breaker_threshold == 0 {
+                return mojang;
+            }
+            Arc::new(CircuitBreakerRetriever::new(
+                mojang,
+                config.mojang_circuit_breaker_threshold,
+                Duration::from_secs(config.mojang_circuit_breaker_cooldown_seconds),
+            ))
         }
         RetrievalType::DefaultSkin => {
             tracing::debug!("Creating DefaultSkinRetriever");
-            Arc::new(DefaultSkinRetriever::new())
+            Arc::new(DefaultSkinRetriever::from_config(config))
+        }
+        RetrievalType::GroupDefaultSkin => {
+            tracing::debug!("Creating GroupDefaultSkinRetriever");
+            Arc::new(GroupDefaultSkinRetriever::new(config, storage, db))
+        }
+        RetrievalType::HttpApi => {
+            tracing::debug!("Creating HttpApiRetriever");
+            Arc::new(HttpApiRetriever::new(config, db))
+        }
+        RetrievalType::Filesystem => {
+            tracing::debug!("Creating FilesystemRetriever");
+            Arc::new(FilesystemRetriever::new(config))
+        }
+        RetrievalType::Static => {
+            tracing::debug!("Creating StaticSkinRetriever");
+            Arc::new(StaticSkinRetriever::new(config))
         }
     }
 }
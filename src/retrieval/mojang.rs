@@ -1,4 +1,7 @@
+use std::any::Any;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use super::backend::{
     download_file_from_url, RetrievedTexture, RetrievedTextureBytes, TextureRetriever,
@@ -12,6 +15,17 @@ use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use uuid::Uuid;
 
+/// How long to pause Mojang requests after a 429 without a usable Retry-After header
+const DEFAULT_RATE_LIMIT_PAUSE: Duration = Duration::from_secs(60);
+
+/// Errors from the Mojang API that callers may want to tell apart
+#[derive(Debug, thiserror::Error)]
+pub enum MojangError {
+    /// Mojang answered 429, or we are still pausing after one
+    #[error("Mojang API rate limited, retry after {}s", .0.as_secs())]
+    RateLimited(Duration),
+}
+
 /// Retrieves textures from the Mojang API
 /// This allows fetching official Minecraft skins and capes
 pub struct MojangRetriever {
@@ -20,6 +34,8 @@ pub struct MojangRetriever {
     session_server_url: String,
     use_database_username_in_mojang_requests: bool,
     db: Option<PgPool>,
+    // Requests are not sent before this instant, set from Retry-After on a 429
+    rate_limited_until: Mutex<Option<Instant>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -68,14 +84,53 @@ pub fn extract_hash_from_url(url: &str) -> Option<&str> {
 impl MojangRetriever {
     pub fn new(config: Config, db: Option<PgPool>) -> Self {
         MojangRetriever {
-            client: reqwest::Client::new(),
+            client: crate::outbound::client(),
             api_base_url: "https://api.mojang.com".to_string(),
             session_server_url: "https://sessionserver.mojang.com/session/minecraft/profile"
                 .to_string(),
             use_database_username_in_mojang_requests: config
                 .use_database_username_in_mojang_requests,
             db: db,
+            rate_limited_until: Mutex::new(None),
+        }
+    }
+
+    /// Send a GET request to a Mojang API, honoring the rate limiter
+    /// While paused after a 429, fails with `MojangError::RateLimited` without sending anything
+    async fn mojang_get(&self, url: &str) -> Result<reqwest::Response> {
+        if let Some(until) = *self.rate_limited_until.lock().unwrap() {
+            let remaining = until.saturating_duration_since(Instant::now());
+            if !remaining.is_zero() {
+                return Err(MojangError::RateLimited(remaining).into());
+            }
+        }
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| anyhow!("Mojang request failed: {}", e))?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let pause = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_RATE_LIMIT_PAUSE);
+
+            tracing::warn!(
+                pause_secs = pause.as_secs(),
+                "Mojang API rate limited, pausing requests"
+            );
+            *self.rate_limited_until.lock().unwrap() = Some(Instant::now() + pause);
+
+            return Err(MojangError::RateLimited(pause).into());
         }
+
+        Ok(response)
     }
 
     /// Resolve a username to UUID using Mojang API
@@ -86,15 +141,13 @@ impl MojangRetriever {
             self.api_base_url, username
         );
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to resolve username from Mojang: {}", e))?;
+        let response = self.mojang_get(&url).await?;
 
-        // 204 No Content means user doesn't exist
-        if response.status() == reqwest::StatusCode::NO_CONTENT {
+        // 204 No Content or 404 Not Found means user doesn't exist
+        if matches!(
+            response.status(),
+            StatusCode::NO_CONTENT | StatusCode::NOT_FOUND
+        ) {
             return Ok(None);
         }
 
@@ -119,15 +172,18 @@ impl MojangRetriever {
     }
 
     /// Fetch the full profile from Mojang session server
-    async fn fetch_profile(&self, uuid: Uuid) -> Result<ProfileResponse> {
+    /// Returns None if Mojang has no profile for this UUID (204/404)
+    async fn fetch_profile(&self, uuid: Uuid) -> Result<Option<ProfileResponse>> {
         let url = format!("{}/{}", self.session_server_url, uuid);
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to fetch profile from Mojang: {}", e))?;
+        let response = self.mojang_get(&url).await?;
+
+        if matches!(
+            response.status(),
+            StatusCode::NO_CONTENT | StatusCode::NOT_FOUND
+        ) {
+            return Ok(None);
+        }
 
         if !response.status().is_success() {
             return Err(anyhow!("Mojang API returned error: {}", response.status()));
@@ -136,9 +192,16 @@ impl MojangRetriever {
         response
             .json::<ProfileResponse>()
             .await
+            .map(Some)
             .map_err(|e| anyhow!("Failed to parse profile response: {}", e))
     }
 
+    /// Current username of a UUID, from its session server profile
+    /// Returns None if Mojang has no profile for this UUID
+    pub async fn fetch_username(&self, uuid: Uuid) -> Result<Option<String>> {
+        Ok(self.fetch_profile(uuid).await?.map(|profile| profile.name))
+    }
+
     /// Decode Base64 texture payload
     fn decode_textures_payload(encoded: &str) -> Result<TexturesPayload> {
         use base64::Engine;
@@ -157,14 +220,22 @@ impl MojangRetriever {
         fetch_uuid: Uuid,
     ) -> Result<HashMap<String, RetrievedTexture>> {
         // Fetch profile from Mojang
-        let profile = self.fetch_profile(fetch_uuid).await?;
+        let profile = match self.fetch_profile(fetch_uuid).await? {
+            Some(profile) => profile,
+            None => {
+                tracing::debug!("No Mojang profile for UUID {}", fetch_uuid);
+                return Ok(HashMap::new());
+            }
+        };
 
         // Find textures property
-        let textures_property = profile
-            .properties
-            .iter()
-            .find(|p| p.name == "textures")
-            .ok_or_else(|| anyhow!("Profile does not have textures property"))?;
+        let textures_property = match profile.properties.iter().find(|p| p.name == "textures") {
+            Some(property) => property,
+            None => {
+                tracing::debug!("Mojang profile {} has no textures property", fetch_uuid);
+                return Ok(HashMap::new());
+            }
+        };
 
         // Decode the base64-encoded textures
         let payload = Self::decode_textures_payload(&textures_property.value)?;
@@ -177,6 +248,7 @@ impl MojangRetriever {
                     url: v.url.to_owned(),
                     hash: extract_hash_from_url(&v.url).map_or("", |e| e).to_owned(),
                     metadata: v.metadata.to_owned(),
+                    source: "mojang".to_string(),
                 };
                 (k.to_owned(), e)
             })
@@ -205,28 +277,19 @@ impl MojangRetriever {
         Ok(textures.remove(key))
     }
 
-        async fn get_texture_bytes_from_mojang(
+    /// Download the texture bytes
+    /// Returns None if the texture server no longer has the file
+    async fn get_texture_bytes_from_mojang(
         &self,
         texture: &RetrievedTexture,
-    ) -> Result<RetrievedTextureBytes> {
-                // Download the texture bytes
-                let response = self
-                    .client
-                    .get(&texture.url)
-                    .send()
-                    .await
-                    .map_err(|e| anyhow!("Failed to download texture: {}", e))?;
-
-                let bytes = response
-                    .bytes()
-                    .await
-                    .map_err(|e| anyhow!("Failed to read texture bytes: {}", e))?
-                    .to_vec();
-        Ok(RetrievedTextureBytes {
-                    hash: texture.hash.to_owned(),
-                    bytes,
-                    metadata: texture.metadata.to_owned(),
-                })
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        Ok(download_file_from_url(&texture.url)
+            .await?
+            .map(|bytes| RetrievedTextureBytes {
+                hash: texture.hash.to_owned(),
+                bytes,
+                metadata: texture.metadata.to_owned(),
+            }))
     }
 }
 
@@ -335,9 +398,7 @@ impl TextureRetriever for MojangRetriever {
         let texture = self.get_texture(user_uuid, texture_type).await?;
 
         match texture {
-            Some(texture) => {
-                Ok(Some(self.get_texture_bytes_from_mojang(&texture).await?))
-            }
+            Some(texture) => self.get_texture_bytes_from_mojang(&texture).await,
             None => Ok(None),
         }
     }
@@ -360,6 +421,10 @@ impl TextureRetriever for MojangRetriever {
         matches!(texture_type, TextureType::SKIN | TextureType::CAPE)
     }
 
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
     async fn get_texture_bytes_by_username(
         &self,
         username: &str,
@@ -378,10 +443,65 @@ impl TextureRetriever for MojangRetriever {
 
         let texture = self.get_texture_from_mojang(uuid, texture_type).await?;
         match texture {
-            Some(e) => {
-                Ok(Some(self.get_texture_bytes_from_mojang(&e).await?))
-            },
+            Some(e) => self.get_texture_bytes_from_mojang(&e).await,
             None => Ok(None),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode as AxumStatusCode, routing::get, Router};
+
+    /// Serve every request with the given status and point a retriever at it
+    async fn retriever_with_status(status: AxumStatusCode) -> MojangRetriever {
+        let app = Router::new().fallback(get(move || async move { status }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        MojangRetriever {
+            client: reqwest::Client::new(),
+            api_base_url: base_url.clone(),
+            session_server_url: format!("{}/session/minecraft/profile", base_url),
+            use_database_username_in_mojang_requests: false,
+            db: None,
+            rate_limited_until: Mutex::new(None),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mojang_404_is_not_found_not_error() {
+        let retriever = retriever_with_status(AxumStatusCode::NOT_FOUND).await;
+        let uuid = Uuid::new_v4();
+
+        assert!(retriever.get_textures(uuid).await.unwrap().is_empty());
+        assert!(retriever
+            .get_texture(uuid, TextureType::SKIN)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(retriever
+            .resolve_username_to_uuid("missing")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(retriever.fetch_username(uuid).await.unwrap().is_none());
+        assert!(retriever
+            .get_texture_bytes_by_username("missing", TextureType::SKIN)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mojang_server_error_is_error() {
+        let retriever = retriever_with_status(AxumStatusCode::INTERNAL_SERVER_ERROR).await;
+
+        assert!(retriever
+            .get_texture(Uuid::new_v4(), TextureType::SKIN)
+            .await
+            .is_err());
+    }
+}
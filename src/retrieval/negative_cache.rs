@@ -0,0 +1,243 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::backend::{RetrievedTexture, RetrievedTextureBytes, TextureRetriever};
+use super::circuit_breaker::track_skips;
+use crate::cache_metrics::CacheMetrics;
+use crate::models::TextureType;
+use anyhow::Result;
+use async_trait::async_trait;
+use lru::LruCache;
+use uuid::Uuid;
+
+/// Number of users whose misses are remembered; the least recently used are evicted
+const CAPACITY: usize = 10_000;
+
+/// Misses are keyed by user and texture type; `None` stands for a `get_textures` call
+type MissKey = (Uuid, Option<TextureType>);
+
+/// Expiry time of every recorded miss of a user, by texture type
+type UserMisses = HashMap<Option<TextureType>, Instant>;
+
+/// Remembers lookups that found nothing for a short time (NEGATIVE_CACHE_SECONDS)
+/// Wraps the configured retriever (usually the chain), so repeated requests for a user
+/// without a texture skip the whole chain, including Mojang.
+/// Only `Ok(None)`/empty results are cached, never failures, nor misses of a lookup in
+/// which a circuit breaker skipped its retriever (the texture may be there).
+/// Uploads call `invalidate` so a new texture is visible immediately.
+/// At most `CAPACITY` users are remembered, so lookups of random UUIDs cannot grow it
+/// without bound.
+pub struct NegativeCacheRetriever {
+    inner: Arc<dyn TextureRetriever>,
+    ttl: Duration,
+    misses: Mutex<LruCache<Uuid, UserMisses>>,
+    metrics: Arc<CacheMetrics>,
+}
+
+impl NegativeCacheRetriever {
+    pub fn new(inner: Arc<dyn TextureRetriever>, ttl: Duration) -> Self {
+        NegativeCacheRetriever {
+            inner,
+            ttl,
+            misses: Mutex::new(LruCache::new(NonZeroUsize::new(CAPACITY).unwrap())),
+            metrics: CacheMetrics::register("negative"),
+        }
+    }
+
+    /// The wrapped retriever
+    pub fn inner(&self) -> &Arc<dyn TextureRetriever> {
+        &self.inner
+    }
+
+    /// Forget every recorded miss of a user
+    /// Returns whether anything was removed
+    pub fn invalidate(&self, user_uuid: Uuid) -> bool {
+        self.misses.lock().unwrap().pop(&user_uuid).is_some()
+    }
+
+    /// Whether a lookup is known to find nothing
+    fn is_cached_miss(&self, (user_uuid, texture_type): MissKey) -> bool {
+        let mut misses = self.misses.lock().unwrap();
+        let cached = match misses.get_mut(&user_uuid) {
+            Some(user_misses) => match user_misses.get(&texture_type) {
+                Some(expires_at) if *expires_at > Instant::now() => true,
+                Some(_) => {
+                    user_misses.remove(&texture_type);
+                    if user_misses.is_empty() {
+                        misses.pop(&user_uuid);
+                    }
+                    false
+                }
+                None => false,
+            },
+            None => false,
+        };
+        self.metrics.record(cached);
+        cached
+    }
+
+    /// Record a lookup that found nothing
+    fn record_miss(&self, (user_uuid, texture_type): MissKey) {
+        let expires_at = Instant::now() + self.ttl;
+        self.misses
+            .lock()
+            .unwrap()
+            .get_or_insert_mut(user_uuid, UserMisses::new)
+            .insert(texture_type, expires_at);
+    }
+}
+
+#[async_trait]
+impl TextureRetriever for NegativeCacheRetriever {
+    async fn get_texture(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTexture>> {
+        let key = (user_uuid, Some(texture_type));
+        if self.is_cached_miss(key) {
+            tracing::debug!(%user_uuid, %texture_type, "Negative cache hit");
+            return Ok(None);
+        }
+
+        let (texture, skipped) = track_skips(self.inner.get_texture(user_uuid, texture_type)).await;
+        let texture = texture?;
+        if texture.is_none() && !skipped {
+            self.record_miss(key);
+        }
+        Ok(texture)
+    }
+
+    async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
+        let key = (user_uuid, None);
+        if self.is_cached_miss(key) {
+            tracing::debug!(%user_uuid, "Negative cache hit for all textures");
+            return Ok(HashMap::new());
+        }
+
+        let (textures, skipped) = track_skips(self.inner.get_textures(user_uuid)).await;
+        let textures = textures?;
+        if textures.is_empty() && !skipped {
+            self.record_miss(key);
+        }
+        Ok(textures)
+    }
+
+    async fn get_texture_bytes(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        // Shares the entry of get_texture: both look up the same texture
+        let key = (user_uuid, Some(texture_type));
+        if self.is_cached_miss(key) {
+            tracing::debug!(%user_uuid, %texture_type, "Negative cache hit");
+            return Ok(None);
+        }
+
+        let (texture, skipped) =
+            track_skips(self.inner.get_texture_bytes(user_uuid, texture_type)).await;
+        let texture = texture?;
+        if texture.is_none() && !skipped {
+            self.record_miss(key);
+        }
+        Ok(texture)
+    }
+
+    async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
+        self.inner.get_texture_bytes_by_hash(hash).await
+    }
+
+    async fn get_texture_bytes_by_username(
+        &self,
+        username: &str,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        self.inner
+            .get_texture_bytes_by_username(username, texture_type)
+            .await
+    }
+
+    fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+        self.inner.supports_texture_type(texture_type)
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Retriever that never finds anything and counts the lookups
+    #[derive(Default)]
+    struct EmptyRetriever {
+        lookups: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TextureRetriever for EmptyRetriever {
+        async fn get_textures(
+            &self,
+            _user_uuid: Uuid,
+        ) -> Result<HashMap<String, RetrievedTexture>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            Ok(HashMap::new())
+        }
+
+        fn supports_texture_type(&self, _texture_type: TextureType) -> bool {
+            true
+        }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
+    }
+
+    #[tokio::test]
+    async fn test_negative_cache_skips_repeated_misses_until_invalidated() {
+        let inner = Arc::new(EmptyRetriever::default());
+        let retriever = NegativeCacheRetriever::new(inner.clone(), Duration::from_secs(60));
+        let user_uuid = Uuid::new_v4();
+
+        for _ in 0..3 {
+            assert!(retriever
+                .get_texture(user_uuid, TextureType::SKIN)
+                .await
+                .unwrap()
+                .is_none());
+        }
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+
+        assert!(retriever.invalidate(user_uuid));
+        assert!(!retriever.invalidate(user_uuid));
+        retriever
+            .get_texture(user_uuid, TextureType::SKIN)
+            .await
+            .unwrap();
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_negative_cache_evicts_least_recently_used_users() {
+        let inner = Arc::new(EmptyRetriever::default());
+        let retriever = NegativeCacheRetriever::new(inner.clone(), Duration::from_secs(60));
+        let first_user = Uuid::new_v4();
+
+        retriever.get_textures(first_user).await.unwrap();
+        for _ in 0..CAPACITY {
+            retriever.record_miss((Uuid::new_v4(), None));
+        }
+        assert_eq!(retriever.misses.lock().unwrap().len(), CAPACITY);
+
+        // Evicted, so looked up again
+        retriever.get_textures(first_user).await.unwrap();
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 2);
+    }
+}
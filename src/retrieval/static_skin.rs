@@ -0,0 +1,200 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::backend::{
+    download_file_from_url, RetrievedTexture, RetrievedTextureBytes, TextureRetriever,
+};
+use crate::config::Config;
+use crate::hashing::hash_bytes;
+use crate::models::{TextureMetadata, TextureType};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+/// Where the static skin is read from
+enum StaticSkinSource {
+    /// STATIC_SKIN_PATH, served through /download/skin/{uuid}
+    File(PathBuf),
+    /// STATIC_SKIN_URL, which texture URLs point at directly
+    Url(String),
+}
+
+/// The static skin once loaded
+struct StaticSkin {
+    hash: String,
+    bytes: Vec<u8>,
+}
+
+/// Serves one configured skin to every user, whatever their UUID (dev/demo mode)
+/// Unlike `DefaultSkinRetriever` there is no Steve/Alex choice: at the front of a chain
+/// it overrides every skin, at the back it is a universal default. Capes and elytras are
+/// never provided. The skin is loaded on first use and kept; a failed load is retried
+/// on the next request.
+pub struct StaticSkinRetriever {
+    source: StaticSkinSource,
+    metadata: Option<TextureMetadata>,
+    base_url: String,
+    skin: OnceCell<StaticSkin>,
+}
+
+impl StaticSkinRetriever {
+    pub fn new(config: &Config) -> Self {
+        let source = match (&config.static_skin_path, &config.static_skin_url) {
+            (Some(path), _) => StaticSkinSource::File(PathBuf::from(path)),
+            (None, Some(url)) => StaticSkinSource::Url(url.clone()),
+            (None, None) => panic!(
+                "STATIC_SKIN_PATH or STATIC_SKIN_URL must be configured for the static retriever"
+            ),
+        };
+
+        StaticSkinRetriever {
+            source,
+            metadata: config.static_skin_model.map(|model| TextureMetadata {
+                model: Some(model.to_string()),
+                ..Default::default()
+            }),
+            base_url: config.base_url.clone(),
+            skin: OnceCell::new(),
+        }
+    }
+
+    async fn skin(&self) -> Result<&StaticSkin> {
+        self.skin
+            .get_or_try_init(|| async {
+                let bytes = match &self.source {
+                    StaticSkinSource::File(path) => tokio::fs::read(path).await.map_err(|e| {
+                        anyhow!("Failed to read static skin {}: {}", path.display(), e)
+                    })?,
+                    StaticSkinSource::Url(url) => download_file_from_url(url)
+                        .await?
+                        .ok_or_else(|| anyhow!("Static skin not found at {}", url))?,
+                };
+
+                Ok(StaticSkin {
+                    hash: hash_bytes(&bytes),
+                    bytes,
+                })
+            })
+            .await
+    }
+
+    fn texture_bytes(&self, skin: &StaticSkin) -> RetrievedTextureBytes {
+        RetrievedTextureBytes {
+            hash: skin.hash.clone(),
+            bytes: skin.bytes.clone(),
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl TextureRetriever for StaticSkinRetriever {
+    async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
+        let skin = self.skin().await?;
+        let url = match &self.source {
+            StaticSkinSource::File(_) => format!(
+                "{}/download/skin/{}",
+                self.base_url.trim_end_matches('/'),
+                user_uuid
+            ),
+            StaticSkinSource::Url(url) => url.clone(),
+        };
+
+        Ok(HashMap::from([(
+            TextureType::SKIN.to_string(),
+            RetrievedTexture {
+                url,
+                hash: skin.hash.clone(),
+                metadata: self.metadata.clone(),
+                source: "static".to_string(),
+            },
+        )]))
+    }
+
+    async fn get_texture_bytes(
+        &self,
+        _user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        match texture_type {
+            TextureType::SKIN => Ok(Some(self.texture_bytes(self.skin().await?))),
+            TextureType::CAPE | TextureType::ELYTRA => Ok(None),
+        }
+    }
+
+    async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
+        let skin = self.skin().await?;
+        Ok((skin.hash == hash).then(|| self.texture_bytes(skin)))
+    }
+
+    async fn get_texture_bytes_by_username(
+        &self,
+        _username: &str,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        self.get_texture_bytes(Uuid::nil(), texture_type).await
+    }
+
+    fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+        matches!(texture_type, TextureType::SKIN)
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SkinModel;
+
+    #[tokio::test]
+    async fn test_same_skin_for_every_user() {
+        let path = std::env::temp_dir().join(format!("static-skin-{}.png", Uuid::new_v4()));
+        tokio::fs::write(&path, b"branded skin").await.unwrap();
+
+        let retriever = StaticSkinRetriever {
+            source: StaticSkinSource::File(path.clone()),
+            metadata: Some(TextureMetadata {
+                model: Some(SkinModel::Slim.to_string()),
+                ..Default::default()
+            }),
+            base_url: "http://localhost".to_string(),
+            skin: OnceCell::new(),
+        };
+
+        let first_user = Uuid::new_v4();
+        let first = retriever
+            .get_texture(first_user, TextureType::SKIN)
+            .await
+            .unwrap()
+            .unwrap();
+        let second = retriever
+            .get_texture(Uuid::new_v4(), TextureType::SKIN)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.hash, hash_bytes(b"branded skin"));
+        assert_eq!(first.hash, second.hash);
+        assert_eq!(first.url, format!("http://localhost/download/skin/{}", first_user));
+        assert_eq!(first.metadata.unwrap().model.as_deref(), Some("slim"));
+
+        assert!(retriever
+            .get_texture(first_user, TextureType::CAPE)
+            .await
+            .unwrap()
+            .is_none());
+        let by_hash = retriever
+            .get_texture_bytes_by_hash(&first.hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(by_hash.bytes, b"branded skin");
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -1,9 +1,11 @@
 use super::backend::{RetrievedTexture, RetrievedTextureBytes, TextureRetriever};
-use crate::models::{TextureMetadata, TextureType};
-use crate::storage::StorageBackend;
+use crate::config::username_key;
+use crate::models::{TextureMetadata, TextureType, METADATA_SIDECAR_EXTENSION};
+use crate::storage::{StorageBackend, StorageError};
 use anyhow::Result;
 use async_trait::async_trait;
 use sqlx::PgPool;
+use std::any::Any;
 use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -13,11 +15,104 @@ use uuid::Uuid;
 pub struct StorageRetriever {
     db: PgPool,
     storage: Arc<dyn StorageBackend>,
+    // Recompute the hash of every file read and refuse to serve mismatches
+    verify_hash_on_read: bool,
+    // Serve the cape as the elytra for users without an elytra texture
+    derive_elytra_from_cape: bool,
+    // Fall back to the metadata sidecar in storage for hashes the database does not know
+    read_metadata_sidecar: bool,
+    // Match usernames regardless of case
+    case_insensitive_usernames: bool,
 }
 
 impl StorageRetriever {
     pub fn new(storage: Arc<dyn StorageBackend>, db: PgPool) -> Self {
-        StorageRetriever { db, storage }
+        StorageRetriever {
+            db,
+            storage,
+            verify_hash_on_read: false,
+            derive_elytra_from_cape: false,
+            read_metadata_sidecar: false,
+            case_insensitive_usernames: false,
+        }
+    }
+
+    /// Check that files read from storage actually hash to their name (VERIFY_HASH_ON_READ)
+    pub fn with_hash_verification(mut self, verify_hash_on_read: bool) -> Self {
+        self.verify_hash_on_read = verify_hash_on_read;
+        self
+    }
+
+    /// Serve a user's cape when their elytra is requested but absent (DERIVE_ELYTRA_FROM_CAPE)
+    /// The game draws the elytra from a cape-layout texture, so the cape is a usable stand-in
+    pub fn with_elytra_from_cape(mut self, derive_elytra_from_cape: bool) -> Self {
+        self.derive_elytra_from_cape = derive_elytra_from_cape;
+        self
+    }
+
+    /// Read metadata from the `<hash>.json` sidecar when the database has no texture
+    /// with the hash (STORE_METADATA_SIDECAR)
+    pub fn with_metadata_sidecar(mut self, read_metadata_sidecar: bool) -> Self {
+        self.read_metadata_sidecar = read_metadata_sidecar;
+        self
+    }
+
+    /// Resolve usernames case-insensitively in `get_texture_bytes_by_username`
+    /// (CASE_INSENSITIVE_USERNAMES)
+    pub fn with_case_insensitive_usernames(mut self, case_insensitive_usernames: bool) -> Self {
+        self.case_insensitive_usernames = case_insensitive_usernames;
+        self
+    }
+
+    /// Load the metadata sidecar of a file, if enabled and present
+    /// The sidecar is only a backup of the database, so failures are logged and ignored
+    async fn metadata_from_sidecar(&self, hash: &str) -> Option<TextureMetadata> {
+        if !self.read_metadata_sidecar {
+            return None;
+        }
+
+        let bytes = match self.storage.get_file(hash, METADATA_SIDECAR_EXTENSION).await {
+            Ok(bytes) => bytes,
+            Err(StorageError::NotFound(_)) => return None,
+            Err(e) => {
+                tracing::warn!("Failed to read metadata sidecar of {}: {}", hash, e);
+                return None;
+            }
+        };
+
+        serde_json::from_slice(&bytes)
+            .map_err(|e| tracing::warn!("Invalid metadata sidecar of {}: {}", hash, e))
+            .ok()
+    }
+
+    /// Texture type to fall back to when the user has no texture of `texture_type`
+    fn derived_from(&self, texture_type: TextureType) -> Option<TextureType> {
+        (self.derive_elytra_from_cape && texture_type == TextureType::ELYTRA)
+            .then_some(TextureType::CAPE)
+    }
+
+    /// Fail if hash verification is enabled and the bytes do not hash to `hash`
+    /// Keys are content hashes, so a mismatch means the stored file is corrupted or was tampered with
+    fn verify_hash(&self, hash: &str, bytes: &[u8]) -> Result<()> {
+        if !self.verify_hash_on_read {
+            return Ok(());
+        }
+
+        let actual = self.storage.calculate_hash(bytes);
+        if actual != hash {
+            tracing::error!(
+                hash,
+                actual_hash = %actual,
+                "Stored file does not match its hash, refusing to serve it"
+            );
+            return Err(anyhow::anyhow!(
+                "Stored file {} is corrupted (content hashes to {})",
+                hash,
+                actual
+            ));
+        }
+
+        Ok(())
     }
 }
 
@@ -32,7 +127,7 @@ impl TextureRetriever for StorageRetriever {
             r#"
             SELECT file_hash, file_url, metadata
             FROM textures
-            WHERE user_uuid = $1 AND texture_type = $2
+            WHERE user_uuid = $1 AND texture_type = $2 AND active
             "#,
             user_uuid,
             texture_type.to_string()
@@ -50,9 +145,13 @@ impl TextureRetriever for StorageRetriever {
                     url: texture.file_url,
                     hash: texture.file_hash,
                     metadata,
+                    source: "storage".to_string(),
                 }))
             }
-            None => Ok(None),
+            None => match self.derived_from(texture_type) {
+                Some(derived_from) => self.get_texture(user_uuid, derived_from).await,
+                None => Ok(None),
+            },
         }
     }
 
@@ -61,14 +160,14 @@ impl TextureRetriever for StorageRetriever {
             r#"
             SELECT file_hash, file_url, metadata, texture_type
             FROM textures
-            WHERE user_uuid = $1
+            WHERE user_uuid = $1 AND active
             "#,
             user_uuid
         )
         .fetch_all(&self.db)
         .await?;
 
-        Ok(texture
+        let mut textures: HashMap<String, RetrievedTexture> = texture
             .into_iter()
             .map(|e| {
                 let metadata: Option<TextureMetadata> =
@@ -80,10 +179,22 @@ impl TextureRetriever for StorageRetriever {
                         url: e.file_url,
                         hash: e.file_hash,
                         metadata,
+                        source: "storage".to_string(),
                     },
                 )
             })
-            .collect())
+            .collect();
+
+        if let Some(derived_from) = self.derived_from(TextureType::ELYTRA) {
+            let elytra = TextureType::ELYTRA.to_string();
+            if !textures.contains_key(&elytra) {
+                if let Some(texture) = textures.get(&derived_from.to_string()).cloned() {
+                    textures.insert(elytra, texture);
+                }
+            }
+        }
+
+        Ok(textures)
     }
 
     async fn get_texture_bytes(
@@ -95,7 +206,7 @@ impl TextureRetriever for StorageRetriever {
             r#"
             SELECT file_hash, metadata
             FROM textures
-            WHERE user_uuid = $1 AND texture_type = $2
+            WHERE user_uuid = $1 AND texture_type = $2 AND active
             "#,
             user_uuid,
             texture_type.to_string()
@@ -112,8 +223,9 @@ impl TextureRetriever for StorageRetriever {
                 // Get file bytes from storage
                 let bytes = self
                     .storage
-                    .get_file(&texture.file_hash, texture_type.file_extension())
+                    .get_texture(&texture.file_hash, texture_type)
                     .await?;
+                self.verify_hash(&texture.file_hash, &bytes)?;
 
                 Ok(Some(RetrievedTextureBytes {
                     hash: texture.file_hash,
@@ -121,20 +233,28 @@ impl TextureRetriever for StorageRetriever {
                     metadata,
                 }))
             }
-            None => Ok(None),
+            None => match self.derived_from(texture_type) {
+                Some(derived_from) => self.get_texture_bytes(user_uuid, derived_from).await,
+                None => Ok(None),
+            },
         }
     }
 
     async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
         // Try to get from storage (works for both S3 and local storage)
-        match self.storage.get_file(hash, "png").await {
-            Ok(bytes) => {
+        match self.storage.get_file_any_type(hash).await {
+            Ok((bytes, _)) => {
+                self.verify_hash(hash, &bytes)?;
+
                 // Look up metadata from database if available
+                // A hash stored by several users or as several types has the metadata of
+                // its first upload, so the answer does not change from one request to the next
                 let texture = sqlx::query!(
                     r#"
                     SELECT metadata
                     FROM textures
                     WHERE file_hash = $1
+                    ORDER BY created_at, user_uuid, texture_type
                     LIMIT 1
                     "#,
                     hash
@@ -142,9 +262,12 @@ impl TextureRetriever for StorageRetriever {
                 .fetch_optional(&self.db)
                 .await?;
 
-                let metadata: Option<TextureMetadata> = texture
-                    .and_then(|t| t.metadata)
-                    .and_then(|v| serde_json::from_value(v).ok());
+                let metadata: Option<TextureMetadata> = match texture {
+                    Some(texture) => texture
+                        .metadata
+                        .and_then(|v| serde_json::from_value(v).ok()),
+                    None => self.metadata_from_sidecar(hash).await,
+                };
 
                 Ok(Some(RetrievedTextureBytes {
                     hash: hash.to_string(),
@@ -152,12 +275,382 @@ impl TextureRetriever for StorageRetriever {
                     metadata,
                 }))
             }
-            Err(_) => Ok(None), // File not in storage
+            // Not stored here, the next retriever in the chain may have it
+            Err(StorageError::NotFound(_)) => Ok(None),
+            Err(e) => {
+                tracing::error!("Failed to read {} from storage: {}", hash, e);
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn get_texture_bytes_by_username(
+        &self,
+        username: &str,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        // Resolve the username through the local mappings, preferring the most
+        // recently updated mapping that actually has a texture of this type
+        let mapping = sqlx::query!(
+            r#"
+            SELECT m.user_uuid
+            FROM username_mappings m
+            JOIN textures t ON t.user_uuid = m.user_uuid
+            WHERE m.username = $1 AND t.texture_type = $2 AND t.active
+            ORDER BY m.updated_at DESC
+            LIMIT 1
+            "#,
+            username_key(username, self.case_insensitive_usernames),
+            texture_type.to_string()
+        )
+        .fetch_optional(&self.db)
+        .await?;
+
+        match mapping {
+            Some(mapping) => self.get_texture_bytes(mapping.user_uuid, texture_type).await,
+            None => match self.derived_from(texture_type) {
+                Some(derived_from) => self.get_texture_bytes_by_username(username, derived_from).await,
+                None => Ok(None),
+            },
         }
     }
 
     fn supports_texture_type(&self, texture_type: TextureType) -> bool {
         // Storage retriever supports all texture types
-        matches!(texture_type, TextureType::SKIN | TextureType::CAPE)
+        matches!(
+            texture_type,
+            TextureType::SKIN | TextureType::CAPE | TextureType::ELYTRA
+        )
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // In-memory storage backend for testing
+    #[derive(Default)]
+    struct MemoryStorage {
+        files: Mutex<HashMap<String, Vec<u8>>>,
+        // Fail every read, like a backend that cannot be reached
+        unavailable: bool,
+    }
+
+    #[async_trait]
+    impl StorageBackend for MemoryStorage {
+        async fn store_file(
+            &self,
+            bytes: Vec<u8>,
+            hash: &str,
+            extension: &str,
+        ) -> Result<String, StorageError> {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(format!("{}.{}", hash, extension), bytes);
+            Ok(self.generate_url(hash, extension))
+        }
+
+        async fn delete_file(&self, hash: &str, extension: &str) -> Result<(), StorageError> {
+            self.files
+                .lock()
+                .unwrap()
+                .remove(&format!("{}.{}", hash, extension));
+            Ok(())
+        }
+
+        async fn health_check(&self) -> Result<(), StorageError> {
+            Ok(())
+        }
+
+        async fn exists(&self, hash: &str, extension: &str) -> Result<bool, StorageError> {
+            Ok(self
+                .files
+                .lock()
+                .unwrap()
+                .contains_key(&format!("{}.{}", hash, extension)))
+        }
+
+        async fn get_file(&self, hash: &str, extension: &str) -> Result<Vec<u8>, StorageError> {
+            if self.unavailable {
+                return Err(anyhow::anyhow!("storage is down").into());
+            }
+            self.files
+                .lock()
+                .unwrap()
+                .get(&format!("{}.{}", hash, extension))
+                .cloned()
+                .ok_or_else(|| StorageError::NotFound(format!("{}.{}", hash, extension)))
+        }
+
+        fn generate_url(&self, hash: &str, _extension: &str) -> String {
+            format!("http://localhost/files/{}", hash)
+        }
+    }
+
+    async fn insert_texture(
+        db: &PgPool,
+        storage: &MemoryStorage,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+        bytes: &[u8],
+    ) -> String {
+        let hash = storage.calculate_hash(bytes);
+        let url = storage
+            .store_file(bytes.to_vec(), &hash, texture_type.file_extension())
+            .await
+            .unwrap();
+
+        sqlx::query(
+            "INSERT INTO textures (user_uuid, texture_type, file_hash, file_url) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(user_uuid)
+        .bind(texture_type.to_string())
+        .bind(&hash)
+        .bind(url)
+        .execute(db)
+        .await
+        .unwrap();
+
+        hash
+    }
+
+    #[tokio::test]
+    async fn test_hash_verification_rejects_corrupted_files() {
+        let storage = Arc::new(MemoryStorage::default());
+        let hash = storage.calculate_hash(b"skin");
+        storage
+            .store_file(b"tampered".to_vec(), &hash, "png")
+            .await
+            .unwrap();
+
+        // The corrupted file is rejected before the database is ever queried
+        let db = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let retriever = StorageRetriever::new(storage, db).with_hash_verification(true);
+
+        assert!(retriever.get_texture_bytes_by_hash(&hash).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_is_none_but_storage_failure_is_an_error() {
+        let db = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let retriever = StorageRetriever::new(Arc::new(MemoryStorage::default()), db.clone());
+        assert!(retriever
+            .get_texture_bytes_by_hash("missing")
+            .await
+            .unwrap()
+            .is_none());
+
+        let storage = MemoryStorage {
+            unavailable: true,
+            ..Default::default()
+        };
+        let retriever = StorageRetriever::new(Arc::new(storage), db);
+        assert!(retriever.get_texture_bytes_by_hash("missing").await.is_err());
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "requires a PostgreSQL database (DATABASE_URL)"]
+    async fn test_get_textures_returns_all_types(db: PgPool) {
+        let storage = Arc::new(MemoryStorage::default());
+        let user_uuid = Uuid::new_v4();
+        let skin_hash = insert_texture(&db, &storage, user_uuid, TextureType::SKIN, b"skin").await;
+        let cape_hash = insert_texture(&db, &storage, user_uuid, TextureType::CAPE, b"cape").await;
+
+        let retriever = StorageRetriever::new(storage, db);
+        let textures = retriever.get_textures(user_uuid).await.unwrap();
+
+        assert_eq!(textures.len(), 2);
+        assert_eq!(textures["SKIN"].hash, skin_hash);
+        assert_eq!(textures["CAPE"].hash, cape_hash);
+        assert!(retriever.get_textures(Uuid::new_v4()).await.unwrap().is_empty());
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "requires a PostgreSQL database (DATABASE_URL)"]
+    async fn test_only_the_active_cape_is_served(db: PgPool) {
+        let storage = Arc::new(MemoryStorage::default());
+        let user_uuid = Uuid::new_v4();
+        let old_hash = insert_texture(&db, &storage, user_uuid, TextureType::CAPE, b"old").await;
+        let cape_hash = insert_texture(&db, &storage, user_uuid, TextureType::CAPE, b"cape").await;
+
+        sqlx::query("UPDATE textures SET active = FALSE WHERE file_hash = $1")
+            .bind(&old_hash)
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let retriever = StorageRetriever::new(storage, db);
+        let cape = retriever
+            .get_texture(user_uuid, TextureType::CAPE)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cape.hash, cape_hash);
+
+        let textures = retriever.get_textures(user_uuid).await.unwrap();
+        assert_eq!(textures["CAPE"].hash, cape_hash);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "requires a PostgreSQL database (DATABASE_URL)"]
+    async fn test_get_texture_bytes_by_username(db: PgPool) {
+        let storage = Arc::new(MemoryStorage::default());
+        let user_uuid = Uuid::new_v4();
+        let skin_hash = insert_texture(&db, &storage, user_uuid, TextureType::SKIN, b"skin").await;
+
+        sqlx::query("INSERT INTO username_mappings (user_uuid, username) VALUES ($1, $2)")
+            .bind(user_uuid)
+            .bind("Notch")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let retriever = StorageRetriever::new(storage, db);
+
+        let skin = retriever
+            .get_texture_bytes_by_username("Notch", TextureType::SKIN)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(skin.hash, skin_hash);
+        assert_eq!(skin.bytes, b"skin");
+
+        let cape = retriever
+            .get_texture_bytes_by_username("Notch", TextureType::CAPE)
+            .await
+            .unwrap();
+        assert!(cape.is_none());
+
+        let unknown = retriever
+            .get_texture_bytes_by_username("jeb_", TextureType::SKIN)
+            .await
+            .unwrap();
+        assert!(unknown.is_none());
+
+        // Another spelling only matches with case-insensitive usernames
+        assert!(retriever
+            .get_texture_bytes_by_username("notch", TextureType::SKIN)
+            .await
+            .unwrap()
+            .is_none());
+        let retriever = retriever.with_case_insensitive_usernames(true);
+
+        // Mappings stored before are found once normalized; of names differing only in
+        // case, the most recently updated mapping wins
+        sqlx::query(
+            "INSERT INTO username_mappings (user_uuid, username, updated_at)
+             VALUES ($1, 'NOTCH', NOW() - INTERVAL '1 day')",
+        )
+        .bind(Uuid::new_v4())
+        .execute(&retriever.db)
+        .await
+        .unwrap();
+        let report = crate::backfill::normalize_usernames(&retriever.db)
+            .await
+            .unwrap();
+        assert_eq!(report.normalized, 1);
+        assert_eq!(report.merged, 1);
+
+        let skin = retriever
+            .get_texture_bytes_by_username("nOtCh", TextureType::SKIN)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(skin.hash, skin_hash);
+        let display_username: Option<String> = sqlx::query_scalar(
+            "SELECT display_username FROM username_mappings WHERE username = 'notch'",
+        )
+        .fetch_one(&retriever.db)
+        .await
+        .unwrap();
+        assert_eq!(display_username.as_deref(), Some("Notch"));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "requires a PostgreSQL database (DATABASE_URL)"]
+    async fn test_shared_hash_has_metadata_of_first_upload(db: PgPool) {
+        let storage = Arc::new(MemoryStorage::default());
+        let hash = insert_texture(&db, &storage, Uuid::new_v4(), TextureType::SKIN, b"shared").await;
+        sqlx::query(
+            "UPDATE textures SET metadata = '{\"model\": \"slim\"}', created_at = NOW() - INTERVAL '1 day'",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        // The same bytes uploaded later as a cape, without metadata
+        insert_texture(&db, &storage, Uuid::new_v4(), TextureType::CAPE, b"shared").await;
+
+        let retriever = StorageRetriever::new(storage, db);
+        for _ in 0..3 {
+            let texture = retriever.get_texture_bytes_by_hash(&hash).await.unwrap().unwrap();
+            assert_eq!(texture.metadata.unwrap().model.as_deref(), Some("slim"));
+        }
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "requires a PostgreSQL database (DATABASE_URL)"]
+    async fn test_elytra_derived_from_cape(db: PgPool) {
+        let storage = Arc::new(MemoryStorage::default());
+        let user_uuid = Uuid::new_v4();
+        let cape_hash = insert_texture(&db, &storage, user_uuid, TextureType::CAPE, b"cape").await;
+
+        let plain = StorageRetriever::new(storage.clone(), db.clone());
+        assert!(plain
+            .get_texture(user_uuid, TextureType::ELYTRA)
+            .await
+            .unwrap()
+            .is_none());
+
+        let retriever =
+            StorageRetriever::new(storage.clone(), db.clone()).with_elytra_from_cape(true);
+        let elytra = retriever
+            .get_texture_bytes(user_uuid, TextureType::ELYTRA)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(elytra.hash, cape_hash);
+        assert_eq!(
+            retriever.get_textures(user_uuid).await.unwrap()["ELYTRA"].hash,
+            cape_hash
+        );
+
+        // An uploaded elytra wins over the cape
+        let elytra_hash =
+            insert_texture(&db, &storage, user_uuid, TextureType::ELYTRA, b"elytra").await;
+        let elytra = retriever
+            .get_texture(user_uuid, TextureType::ELYTRA)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(elytra.hash, elytra_hash);
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    #[ignore = "requires a PostgreSQL database (DATABASE_URL)"]
+    async fn test_metadata_sidecar_used_without_database_row(db: PgPool) {
+        let storage = Arc::new(MemoryStorage::default());
+        let hash = storage.calculate_hash(b"skin");
+        storage
+            .store_file(b"skin".to_vec(), &hash, "png")
+            .await
+            .unwrap();
+        storage
+            .store_file(br#"{"model":"slim"}"#.to_vec(), &hash, METADATA_SIDECAR_EXTENSION)
+            .await
+            .unwrap();
+
+        let plain = StorageRetriever::new(storage.clone(), db.clone());
+        let texture = plain.get_texture_bytes_by_hash(&hash).await.unwrap().unwrap();
+        assert!(texture.metadata.is_none());
+
+        let retriever = StorageRetriever::new(storage, db).with_metadata_sidecar(true);
+        let texture = retriever.get_texture_bytes_by_hash(&hash).await.unwrap().unwrap();
+        assert_eq!(texture.metadata.unwrap().model.as_deref(), Some("slim"));
     }
 }
@@ -0,0 +1,184 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::backend::{RetrievedTexture, RetrievedTextureBytes, TextureRetriever};
+use crate::models::TextureType;
+use anyhow::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+/// Sends each texture type to its own retriever (RETRIEVAL_CHAIN_SKIN, RETRIEVAL_CHAIN_CAPE)
+/// Types without one of their own go to the default retriever, built from RETRIEVAL_CHAIN
+/// or RETRIEVAL_TYPE. Lookups by hash do not know the type, so they try the default
+/// retriever and then the per-type ones.
+pub struct TypeDispatchRetriever {
+    default: Arc<dyn TextureRetriever>,
+    by_type: HashMap<TextureType, Arc<dyn TextureRetriever>>,
+}
+
+impl TypeDispatchRetriever {
+    pub fn new(
+        default: Arc<dyn TextureRetriever>,
+        by_type: HashMap<TextureType, Arc<dyn TextureRetriever>>,
+    ) -> Self {
+        TypeDispatchRetriever { default, by_type }
+    }
+
+    /// The default retriever followed by the per-type ones
+    pub fn retrievers(&self) -> impl Iterator<Item = &Arc<dyn TextureRetriever>> {
+        std::iter::once(&self.default).chain(self.by_type.values())
+    }
+
+    fn retriever_for(&self, texture_type: TextureType) -> &Arc<dyn TextureRetriever> {
+        self.by_type.get(&texture_type).unwrap_or(&self.default)
+    }
+}
+
+#[async_trait]
+impl TextureRetriever for TypeDispatchRetriever {
+    async fn get_texture(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTexture>> {
+        self.retriever_for(texture_type)
+            .get_texture(user_uuid, texture_type)
+            .await
+    }
+
+    /// Textures of the default retriever, with the types that have a retriever of their
+    /// own looked up there instead
+    async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
+        let mut textures = if TextureType::ALL
+            .iter()
+            .all(|texture_type| self.by_type.contains_key(texture_type))
+        {
+            HashMap::new()
+        } else {
+            self.default.get_textures(user_uuid).await?
+        };
+
+        for (&texture_type, retriever) in &self.by_type {
+            match retriever.get_texture(user_uuid, texture_type).await? {
+                Some(texture) => textures.insert(texture_type.to_string(), texture),
+                None => textures.remove(&texture_type.to_string()),
+            };
+        }
+
+        Ok(textures)
+    }
+
+    async fn get_texture_bytes(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        self.retriever_for(texture_type)
+            .get_texture_bytes(user_uuid, texture_type)
+            .await
+    }
+
+    async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
+        let mut first_error = None;
+
+        for retriever in self.retrievers() {
+            match retriever.get_texture_bytes_by_hash(hash).await {
+                Ok(Some(texture)) => return Ok(Some(texture)),
+                Ok(None) => {}
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_texture_bytes_by_username(
+        &self,
+        username: &str,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        self.retriever_for(texture_type)
+            .get_texture_bytes_by_username(username, texture_type)
+            .await
+    }
+
+    fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+        self.retriever_for(texture_type)
+            .supports_texture_type(texture_type)
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Retriever answering every type with a texture whose hash is its name
+    struct NamedRetriever(&'static str);
+
+    #[async_trait]
+    impl TextureRetriever for NamedRetriever {
+        async fn get_textures(
+            &self,
+            _user_uuid: Uuid,
+        ) -> Result<HashMap<String, RetrievedTexture>> {
+            Ok(TextureType::ALL
+                .into_iter()
+                .map(|texture_type| {
+                    let texture = RetrievedTexture {
+                        url: format!("http://example.com/{}.png", self.0),
+                        hash: self.0.to_string(),
+                        metadata: None,
+                        source: self.0.to_string(),
+                    };
+                    (texture_type.to_string(), texture)
+                })
+                .collect())
+        }
+
+        fn supports_texture_type(&self, _texture_type: TextureType) -> bool {
+            true
+        }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
+    }
+
+    #[tokio::test]
+    async fn test_types_with_their_own_retriever_bypass_the_default() {
+        let retriever = TypeDispatchRetriever::new(
+            Arc::new(NamedRetriever("default")),
+            HashMap::from([(
+                TextureType::CAPE,
+                Arc::new(NamedRetriever("capes")) as Arc<dyn TextureRetriever>,
+            )]),
+        );
+        let user_uuid = Uuid::new_v4();
+
+        let skin = retriever
+            .get_texture(user_uuid, TextureType::SKIN)
+            .await
+            .unwrap();
+        assert_eq!(skin.unwrap().hash, "default");
+        let cape = retriever
+            .get_texture(user_uuid, TextureType::CAPE)
+            .await
+            .unwrap();
+        assert_eq!(cape.unwrap().hash, "capes");
+
+        let textures = retriever.get_textures(user_uuid).await.unwrap();
+        assert_eq!(textures["SKIN"].hash, "default");
+        assert_eq!(textures["CAPE"].hash, "capes");
+        assert_eq!(textures["ELYTRA"].hash, "default");
+    }
+}
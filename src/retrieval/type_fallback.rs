@@ -0,0 +1,189 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::backend::{RetrievedTexture, RetrievedTextureBytes, TextureRetriever};
+use crate::models::TextureType;
+use anyhow::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+/// Serves another type's texture when a user has none of the requested type (TYPE_FALLBACK)
+/// Wraps the configured retriever (usually the chain): a lookup that finds nothing is
+/// repeated with the fallback type, and with its fallback in turn. `Config::validate`
+/// rejects cyclic fallbacks, so the lookups always end.
+pub struct TypeFallbackRetriever {
+    inner: Arc<dyn TextureRetriever>,
+    fallbacks: HashMap<TextureType, TextureType>,
+}
+
+impl TypeFallbackRetriever {
+    pub fn new(
+        inner: Arc<dyn TextureRetriever>,
+        fallbacks: HashMap<TextureType, TextureType>,
+    ) -> Self {
+        TypeFallbackRetriever { inner, fallbacks }
+    }
+
+    /// The wrapped retriever
+    pub fn inner(&self) -> &Arc<dyn TextureRetriever> {
+        &self.inner
+    }
+
+    /// The requested type followed by its fallbacks, in the order they are tried
+    fn lookup_order(&self, texture_type: TextureType) -> Vec<TextureType> {
+        let mut order = vec![texture_type];
+        while let Some(&fallback) = self.fallbacks.get(order.last().unwrap()) {
+            // Cycles are rejected at startup; this only keeps a bad map from looping
+            if order.contains(&fallback) {
+                break;
+            }
+            order.push(fallback);
+        }
+        order
+    }
+}
+
+#[async_trait]
+impl TextureRetriever for TypeFallbackRetriever {
+    async fn get_texture(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTexture>> {
+        for lookup_type in self.lookup_order(texture_type) {
+            if let Some(texture) = self.inner.get_texture(user_uuid, lookup_type).await? {
+                return Ok(Some(texture));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Types missing from the inner result are filled in from their fallbacks in it,
+    /// without further lookups
+    async fn get_textures(&self, user_uuid: Uuid) -> Result<HashMap<String, RetrievedTexture>> {
+        let mut textures = self.inner.get_textures(user_uuid).await?;
+
+        for &texture_type in self.fallbacks.keys() {
+            if textures.contains_key(&texture_type.to_string()) {
+                continue;
+            }
+            let fallback = self
+                .lookup_order(texture_type)
+                .into_iter()
+                .skip(1)
+                .find_map(|lookup_type| textures.get(&lookup_type.to_string()).cloned());
+            if let Some(fallback) = fallback {
+                textures.insert(texture_type.to_string(), fallback);
+            }
+        }
+
+        Ok(textures)
+    }
+
+    async fn get_texture_bytes(
+        &self,
+        user_uuid: Uuid,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        for lookup_type in self.lookup_order(texture_type) {
+            if let Some(texture) = self.inner.get_texture_bytes(user_uuid, lookup_type).await? {
+                return Ok(Some(texture));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn get_texture_bytes_by_hash(&self, hash: &str) -> Result<Option<RetrievedTextureBytes>> {
+        self.inner.get_texture_bytes_by_hash(hash).await
+    }
+
+    async fn get_texture_bytes_by_username(
+        &self,
+        username: &str,
+        texture_type: TextureType,
+    ) -> Result<Option<RetrievedTextureBytes>> {
+        for lookup_type in self.lookup_order(texture_type) {
+            if let Some(texture) = self
+                .inner
+                .get_texture_bytes_by_username(username, lookup_type)
+                .await?
+            {
+                return Ok(Some(texture));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+        self.lookup_order(texture_type)
+            .into_iter()
+            .any(|lookup_type| self.inner.supports_texture_type(lookup_type))
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Retriever holding a cape and nothing else
+    struct CapeOnlyRetriever;
+
+    #[async_trait]
+    impl TextureRetriever for CapeOnlyRetriever {
+        async fn get_textures(
+            &self,
+            _user_uuid: Uuid,
+        ) -> Result<HashMap<String, RetrievedTexture>> {
+            let cape = RetrievedTexture {
+                url: "http://example.com/cape.png".to_string(),
+                hash: "cape".to_string(),
+                metadata: None,
+                source: "test".to_string(),
+            };
+            Ok(HashMap::from([(TextureType::CAPE.to_string(), cape)]))
+        }
+
+        fn supports_texture_type(&self, texture_type: TextureType) -> bool {
+            texture_type == TextureType::CAPE
+        }
+
+        fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+            self
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_type_falls_back_but_not_the_other_way() {
+        let retriever = TypeFallbackRetriever::new(
+            Arc::new(CapeOnlyRetriever),
+            HashMap::from([(TextureType::ELYTRA, TextureType::CAPE)]),
+        );
+        let user_uuid = Uuid::new_v4();
+
+        let elytra = retriever
+            .get_texture(user_uuid, TextureType::ELYTRA)
+            .await
+            .unwrap();
+        assert_eq!(elytra.unwrap().hash, "cape");
+        assert!(retriever
+            .get_texture(user_uuid, TextureType::SKIN)
+            .await
+            .unwrap()
+            .is_none());
+
+        let textures = retriever.get_textures(user_uuid).await.unwrap();
+        assert_eq!(textures["ELYTRA"].hash, "cape");
+        assert!(!textures.contains_key("SKIN"));
+
+        assert!(retriever.supports_texture_type(TextureType::ELYTRA));
+        assert!(!retriever.supports_texture_type(TextureType::SKIN));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::config::Config;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Policy installed at startup, used by code that has no access to the config
+/// (e.g. `download_file_from_url`)
+static GLOBAL_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// Retry with exponential backoff for transient storage and network failures
+/// Only wrap idempotent operations: a failed attempt may still have been applied
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every further retry
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        RetryPolicy {
+            max_retries: config.storage_max_retries,
+            base_delay: Duration::from_millis(config.storage_retry_base_delay_ms),
+        }
+    }
+
+    /// Install this policy as the process-wide one returned by `RetryPolicy::global`
+    pub fn install(self) {
+        if GLOBAL_POLICY.set(self).is_err() {
+            tracing::warn!("Retry policy already installed, ignoring");
+        }
+    }
+
+    /// Get the process-wide policy, or the default one if none was installed
+    pub fn global() -> Self {
+        GLOBAL_POLICY.get().copied().unwrap_or_default()
+    }
+
+    /// Delay before the given retry (0-based)
+    fn delay_for(&self, retry: u32) -> Duration {
+        self.base_delay.saturating_mul(2u32.saturating_pow(retry))
+    }
+
+    /// Run `operation` until it succeeds, fails with an error `is_transient` rejects,
+    /// or `max_retries` retries have been made
+    pub async fn retry<T, E, F, Fut>(
+        &self,
+        description: &str,
+        is_transient: impl Fn(&E) -> bool,
+        mut operation: F,
+    ) -> Result<T, E>
+    where
+        E: std::fmt::Display,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) if retry < self.max_retries && is_transient(&e) => {
+                    let delay = self.delay_for(retry);
+                    tracing::warn!(
+                        attempt = retry + 1,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %e,
+                        "{} failed with a transient error, retrying",
+                        description
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// HTTP status codes worth retrying: throttling and temporary server-side failures
+/// Other 4xx codes are the client's fault and never retried
+pub fn is_transient_status(status: u16) -> bool {
+    matches!(status, 429 | 500 | 502 | 503 | 504)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_recovers_from_transient_errors() {
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<u32, String> = policy()
+            .retry("test", |_| true, || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err("unavailable".to_string()),
+                    n => Ok(n),
+                }
+            })
+            .await;
+
+        assert_eq!(result, Ok(2));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_on_permanent_errors_and_exhaustion() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), String> = policy()
+            .retry("test", |_| false, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("forbidden".to_string())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), String> = policy()
+            .retry("test", |_| true, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err("unavailable".to_string())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+}
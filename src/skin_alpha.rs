@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Result};
+use std::io::Cursor;
+
+/// Regions of a 64x64 skin that vanilla renders without transparency, as (x0, y0, x1, y1)
+/// They hold the base layer of the head, body, arms and legs; the hat and the other
+/// overlay layers around them may be transparent
+const OPAQUE_REGIONS: [(u32, u32, u32, u32); 3] = [
+    (0, 0, 32, 16),
+    (0, 16, 64, 32),
+    (16, 48, 48, 64),
+];
+
+/// Force the base layer of a skin to full alpha, like vanilla does when loading it,
+/// keeping the transparency of the overlay layers (NORMALIZE_SKIN_ALPHA)
+/// Works on 64x64, legacy 64x32 and HD skins. Returns the re-encoded PNG, or None if
+/// the skin has no transparent pixels there and can be kept as uploaded
+pub fn normalize_skin_alpha(png_bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut decoder = png::Decoder::new(Cursor::new(png_bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| anyhow!("Invalid PNG: {}", e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| anyhow!("Invalid PNG: {}", e))?;
+    let (width, height) = (frame.width, frame.height);
+
+    let mut rgba = match frame.color_type {
+        // Without an alpha channel every pixel is opaque already
+        png::ColorType::Rgb | png::ColorType::Grayscale => return Ok(None),
+        png::ColorType::Rgba => buffer[..frame.buffer_size()].to_vec(),
+        png::ColorType::GrayscaleAlpha => buffer[..frame.buffer_size()]
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        png::ColorType::Indexed => return Err(anyhow!("Invalid PNG: palette was not expanded")),
+    };
+
+    if !force_opaque(&mut rgba, width, height) {
+        return Ok(None);
+    }
+
+    let mut encoded = Vec::new();
+    let mut encoder = png::Encoder::new(&mut encoded, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgba)?;
+    writer.finish()?;
+
+    Ok(Some(encoded))
+}
+
+/// Set the alpha of every pixel in the opaque regions of an RGBA skin to 255, scaled to
+/// its resolution and cut off at its height for legacy skins
+/// Returns whether any pixel changed
+fn force_opaque(rgba: &mut [u8], width: u32, height: u32) -> bool {
+    let scale = width / 64;
+    let mut changed = false;
+
+    for (x0, y0, x1, y1) in OPAQUE_REGIONS {
+        for y in (y0 * scale)..(y1 * scale).min(height) {
+            for x in (x0 * scale)..(x1 * scale) {
+                let alpha = &mut rgba[((y * width + x) * 4 + 3) as usize];
+                if *alpha != 255 {
+                    *alpha = 255;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_rgba(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let mut encoder = png::Encoder::new(&mut encoded, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(rgba)
+            .unwrap();
+        encoded
+    }
+
+    fn alpha_at(png_bytes: &[u8], x: u32, y: u32) -> u8 {
+        let mut reader = png::Decoder::new(Cursor::new(png_bytes)).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer).unwrap();
+        buffer[((y * frame.width + x) * 4 + 3) as usize]
+    }
+
+    #[test]
+    fn test_normalize_skin_alpha_keeps_overlay_transparency() {
+        // Half-transparent everywhere
+        let mut rgba = [200u8, 100, 50, 128].repeat(64 * 64);
+        // A fully transparent pixel in the hat layer
+        rgba[((4 * 64 + 40) * 4 + 3) as usize] = 0;
+        let skin = encode_rgba(&rgba, 64, 64);
+
+        let normalized = normalize_skin_alpha(&skin).unwrap().unwrap();
+
+        // Head, body and left leg base layer
+        assert_eq!(alpha_at(&normalized, 8, 8), 255);
+        assert_eq!(alpha_at(&normalized, 20, 20), 255);
+        assert_eq!(alpha_at(&normalized, 20, 52), 255);
+        // Hat, jacket and left leg overlay
+        assert_eq!(alpha_at(&normalized, 40, 4), 0);
+        assert_eq!(alpha_at(&normalized, 40, 8), 128);
+        assert_eq!(alpha_at(&normalized, 20, 36), 128);
+        assert_eq!(alpha_at(&normalized, 4, 52), 128);
+
+        // Already normalized skins are kept as they are
+        assert_eq!(normalize_skin_alpha(&normalized).unwrap(), None);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::cache_metrics::CacheMetrics;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+/// Number of sheets kept by `SheetCache` before it is emptied
+const CACHE_CAPACITY: usize = 1_000;
+
+/// Sheets are keyed by the hashes of the skin and cape they were built from
+type SheetKey = (String, Option<String>);
+
+/// An RGBA image
+struct Image {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+/// Composite a skin and an optional cape into one PNG for GET /sheet/{uuid}
+///
+/// At scale 1 the sheet is 128x64: the skin fills (0, 0)-(64, 64) and the cape
+/// (64, 0)-(128, 32). Legacy 64x32 skins leave (0, 32)-(64, 64) transparent, as does a
+/// missing cape its region; (64, 32)-(128, 64) is always transparent.
+/// HD textures scale the whole layout by the largest of their multiples of the vanilla
+/// size, and the other texture is enlarged to match with nearest-neighbour sampling
+pub fn compose_sheet(skin: &[u8], cape: Option<&[u8]>) -> Result<Vec<u8>> {
+    let skin = decode_rgba(skin)?;
+    let cape = cape.map(decode_rgba).transpose()?;
+
+    let scale = [Some(&skin), cape.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|image| (image.width / 64).max(1))
+        .max()
+        .unwrap_or(1);
+
+    let mut sheet = Image {
+        width: 128 * scale,
+        height: 64 * scale,
+        rgba: vec![0; (128 * scale * 64 * scale * 4) as usize],
+    };
+    blit(&mut sheet, &skin, 0, scale);
+    if let Some(cape) = &cape {
+        blit(&mut sheet, cape, 64 * scale, scale);
+    }
+
+    let mut encoded = Vec::new();
+    let mut encoder = png::Encoder::new(&mut encoded, sheet.width, sheet.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&sheet.rgba)?;
+    writer.finish()?;
+
+    Ok(encoded)
+}
+
+/// Decode a PNG of any color type into 8-bit RGBA
+fn decode_rgba(png_bytes: &[u8]) -> Result<Image> {
+    let mut decoder = png::Decoder::new(Cursor::new(png_bytes));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| anyhow!("Invalid PNG: {}", e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| anyhow!("Invalid PNG: {}", e))?;
+    let pixels = &buffer[..frame.buffer_size()];
+
+    let rgba = match frame.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels
+            .iter()
+            .flat_map(|&gray| [gray, gray, gray, 255])
+            .collect(),
+        png::ColorType::Indexed => return Err(anyhow!("Invalid PNG: palette was not expanded")),
+    };
+
+    Ok(Image {
+        width: frame.width,
+        height: frame.height,
+        rgba,
+    })
+}
+
+/// Copy an image into the sheet with its left edge at `x_offset`, enlarged from its own
+/// scale to the sheet's
+fn blit(sheet: &mut Image, image: &Image, x_offset: u32, scale: u32) {
+    let factor = (scale / (image.width / 64).max(1)).max(1);
+    let width = (image.width * factor).min(sheet.width - x_offset);
+    let height = (image.height * factor).min(sheet.height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let from = (((y / factor) * image.width + x / factor) * 4) as usize;
+            let to = ((y * sheet.width + x_offset + x) * 4) as usize;
+            sheet.rgba[to..to + 4].copy_from_slice(&image.rgba[from..from + 4]);
+        }
+    }
+}
+
+/// Sprite sheets already built, by skin and cape hash
+/// Textures are content addressed, so a cached sheet never goes stale; once
+/// `CACHE_CAPACITY` sheets are held the cache is emptied and fills up again
+pub struct SheetCache {
+    sheets: Mutex<HashMap<SheetKey, Arc<Vec<u8>>>>,
+    metrics: Arc<CacheMetrics>,
+}
+
+impl Default for SheetCache {
+    fn default() -> Self {
+        SheetCache {
+            sheets: Mutex::default(),
+            metrics: CacheMetrics::register("sprite_sheet"),
+        }
+    }
+}
+
+impl SheetCache {
+    pub fn get(&self, skin_hash: &str, cape_hash: Option<&str>) -> Option<Arc<Vec<u8>>> {
+        let key = (skin_hash.to_string(), cape_hash.map(str::to_string));
+        let sheet = self.sheets.lock().unwrap().get(&key).cloned();
+        self.metrics.record(sheet.is_some());
+        sheet
+    }
+
+    pub fn insert(&self, skin_hash: &str, cape_hash: Option<&str>, sheet: Arc<Vec<u8>>) {
+        let key = (skin_hash.to_string(), cape_hash.map(str::to_string));
+        let mut sheets = self.sheets.lock().unwrap();
+        if sheets.len() >= CACHE_CAPACITY {
+            sheets.clear();
+        }
+        sheets.insert(key, sheet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_rgba(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let mut encoder = png::Encoder::new(&mut encoded, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(rgba)
+            .unwrap();
+        encoded
+    }
+
+    fn pixel_at(sheet: &Image, x: u32, y: u32) -> [u8; 4] {
+        let index = ((y * sheet.width + x) * 4) as usize;
+        sheet.rgba[index..index + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_compose_sheet_places_skin_and_cape_side_by_side() {
+        let skin = encode_rgba(&[255, 0, 0, 255].repeat(64 * 64), 64, 64);
+        let cape = encode_rgba(&[0, 0, 255, 255].repeat(64 * 32), 64, 32);
+
+        let sheet = decode_rgba(&compose_sheet(&skin, Some(&cape)).unwrap()).unwrap();
+        assert_eq!((sheet.width, sheet.height), (128, 64));
+        assert_eq!(pixel_at(&sheet, 63, 63), [255, 0, 0, 255]);
+        assert_eq!(pixel_at(&sheet, 64, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel_at(&sheet, 127, 31), [0, 0, 255, 255]);
+        assert_eq!(pixel_at(&sheet, 64, 32), [0, 0, 0, 0]);
+
+        // Without a cape its region stays transparent
+        let sheet = decode_rgba(&compose_sheet(&skin, None).unwrap()).unwrap();
+        assert_eq!(pixel_at(&sheet, 64, 0), [0, 0, 0, 0]);
+
+        // An HD cape doubles the layout and the skin is enlarged to match
+        let hd_cape = encode_rgba(&[0, 0, 255, 255].repeat(128 * 64), 128, 64);
+        let sheet = decode_rgba(&compose_sheet(&skin, Some(&hd_cape)).unwrap()).unwrap();
+        assert_eq!((sheet.width, sheet.height), (256, 128));
+        assert_eq!(pixel_at(&sheet, 127, 127), [255, 0, 0, 255]);
+        assert_eq!(pixel_at(&sheet, 128, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel_at(&sheet, 255, 63), [0, 0, 255, 255]);
+    }
+}
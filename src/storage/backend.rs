@@ -1,5 +1,99 @@
-use anyhow::Result;
+use crate::models::TextureType;
 use async_trait::async_trait;
+use axum::body::Body;
+use std::path::Path;
+
+/// Error returned by storage backends
+/// Lets callers tell a missing file apart from a backend that is down or misconfigured
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    /// There is no such file
+    #[error("File not found: {0}")]
+    NotFound(String),
+
+    /// The local filesystem failed
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The backend failed or could not be reached
+    #[error(transparent)]
+    Backend(#[from] anyhow::Error),
+
+    /// A setting the backend needs is missing
+    #[error("{0} must be configured for this storage type")]
+    NotConfigured(&'static str),
+
+    /// The backend was compiled out
+    #[cfg_attr(feature = "s3", allow(dead_code))]
+    #[error("{0} feature not enabled")]
+    FeatureDisabled(&'static str),
+}
+
+impl StorageError {
+    /// An IO error with what was being done when it happened
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        StorageError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(source: std::io::Error) -> Self {
+        StorageError::io("IO error", source)
+    }
+}
+
+pub type Result<T, E = StorageError> = std::result::Result<T, E>;
+
+/// URL of a file under STORAGE_PUBLIC_BASE_URL
+pub fn public_file_url(public_base_url: &str, hash: &str, extension: &str) -> String {
+    format!("{}/{}.{}", public_base_url.trim_end_matches('/'), hash, extension)
+}
+
+/// One texture type per distinct file extension, in lookup order
+/// Used to find a file by hash when its texture type is not known
+fn texture_types_by_extension() -> Vec<TextureType> {
+    let mut types: Vec<TextureType> = Vec::new();
+
+    for texture_type in TextureType::ALL {
+        if !types
+            .iter()
+            .any(|t| t.file_extension() == texture_type.file_extension())
+        {
+            types.push(texture_type);
+        }
+    }
+
+    types
+}
+
+/// Keys (without extension) and texture types to try, in order, to find a file by hash
+/// alone. With type prefixes every type has a key of its own; files written before
+/// STORAGE_TYPE_PREFIX was enabled are still found under the bare hash
+fn lookup_keys(hash: &str, type_prefix: bool) -> Vec<(String, TextureType)> {
+    let mut keys = Vec::new();
+
+    if type_prefix {
+        for texture_type in TextureType::ALL {
+            keys.push((prefixed_key(hash, texture_type), texture_type));
+        }
+    }
+    for texture_type in texture_types_by_extension() {
+        keys.push((hash.to_string(), texture_type));
+    }
+
+    keys
+}
+
+fn prefixed_key(hash: &str, texture_type: TextureType) -> String {
+    format!("{}/{}", texture_type.to_string().to_lowercase(), hash)
+}
 
 /// Trait defining the interface for storage backends
 #[async_trait]
@@ -7,17 +101,155 @@ pub trait StorageBackend: Send + Sync {
     /// Store a file and return its URL
     async fn store_file(&self, bytes: Vec<u8>, hash: &str, extension: &str) -> Result<String>;
 
+    /// Store the contents of a local file (e.g. an upload spilled to disk) and return its URL
+    /// Backends that can stream override this; the default reads the whole file into memory
+    async fn store_file_from_path(&self, path: &Path, hash: &str, extension: &str) -> Result<String> {
+        let bytes = tokio::fs::read(path).await?;
+        self.store_file(bytes, hash, extension).await
+    }
+
+    /// Delete a stored file by hash
+    /// Deleting a file that does not exist is not an error
+    async fn delete_file(&self, hash: &str, extension: &str) -> Result<()>;
+
+    /// Check that the backend is reachable and writable
+    /// Called at startup so misconfiguration fails fast instead of on the first upload
+    async fn health_check(&self) -> Result<()>;
+
+    /// Check whether a file exists without reading it
+    async fn exists(&self, hash: &str, extension: &str) -> Result<bool>;
+
+    /// Whether texture files are kept under a prefix of their type (STORAGE_TYPE_PREFIX)
+    fn type_prefix(&self) -> bool {
+        false
+    }
+
+    /// Key a texture file is stored under, passed as the hash to the other methods
+    /// `<type>/<hash>` with type prefixes, otherwise the hash itself
+    fn texture_key(&self, hash: &str, texture_type: TextureType) -> String {
+        if self.type_prefix() {
+            prefixed_key(hash, texture_type)
+        } else {
+            hash.to_string()
+        }
+    }
+
+    /// Check whether a file exists under the key of any texture type
+    async fn exists_any_type(&self, hash: &str) -> Result<bool> {
+        for (key, texture_type) in lookup_keys(hash, self.type_prefix()) {
+            if self.exists(&key, texture_type.file_extension()).await? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
     /// Get file bytes by hash
+    /// Fails with `StorageError::NotFound` if there is no such file
     async fn get_file(&self, hash: &str, extension: &str) -> Result<Vec<u8>>;
 
+    /// Get the bytes of a texture file of a known type
+    /// Falls back to the bare hash for files stored before STORAGE_TYPE_PREFIX was enabled
+    async fn get_texture(&self, hash: &str, texture_type: TextureType) -> Result<Vec<u8>> {
+        let extension = texture_type.file_extension();
+        let key = self.texture_key(hash, texture_type);
+
+        match self.get_file(&key, extension).await {
+            Err(StorageError::NotFound(_)) if key != hash => self.get_file(hash, extension).await,
+            result => result,
+        }
+    }
+
+    /// Get file bytes by hash when the texture type is not known
+    /// Tries the key of each texture type in turn and returns the first match with its type
+    /// Fails with `StorageError::NotFound` only if no key exists and no lookup failed otherwise
+    async fn get_file_any_type(&self, hash: &str) -> Result<(Vec<u8>, TextureType)> {
+        let mut failure = None;
+
+        for (key, texture_type) in lookup_keys(hash, self.type_prefix()) {
+            match self.get_file(&key, texture_type.file_extension()).await {
+                Ok(bytes) => return Ok((bytes, texture_type)),
+                Err(StorageError::NotFound(_)) => {}
+                Err(e) => failure = Some(e),
+            }
+        }
+
+        Err(failure.unwrap_or_else(|| StorageError::NotFound(hash.to_string())))
+    }
+
+    /// Size in bytes of a stored file as served (i.e. uncompressed), or None if it does not exist
+    /// Backends that can read it from metadata override this; the default reads the file
+    async fn file_size(&self, hash: &str, extension: &str) -> Result<Option<u64>> {
+        match self.get_file(hash, extension).await {
+            Ok(bytes) => Ok(Some(bytes.len() as u64)),
+            Err(StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Size of a file when the texture type is not known, with the type it was found as
+    /// Tries the key of each texture type in turn, like `get_file_any_type`
+    async fn file_size_any_type(&self, hash: &str) -> Result<Option<(u64, TextureType)>> {
+        for (key, texture_type) in lookup_keys(hash, self.type_prefix()) {
+            if let Some(size) = self.file_size(&key, texture_type.file_extension()).await? {
+                return Ok(Some((size, texture_type)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Get file contents by hash as a response body
+    /// Backends that can stream override this; the default reads the whole file into memory
+    /// Fails with `StorageError::NotFound` if there is no such file
+    async fn get_file_stream(&self, hash: &str, extension: &str) -> Result<Body> {
+        Ok(Body::from(self.get_file(hash, extension).await?))
+    }
+
+    /// Get file contents as a response body when the texture type is not known
+    /// Same lookup rules as `get_file_any_type`
+    async fn get_file_stream_any_type(&self, hash: &str) -> Result<(Body, TextureType)> {
+        let mut failure = None;
+
+        for (key, texture_type) in lookup_keys(hash, self.type_prefix()) {
+            match self.get_file_stream(&key, texture_type.file_extension()).await {
+                Ok(body) => return Ok((body, texture_type)),
+                Err(StorageError::NotFound(_)) => {}
+                Err(e) => failure = Some(e),
+            }
+        }
+
+        Err(failure.unwrap_or_else(|| StorageError::NotFound(hash.to_string())))
+    }
+
     /// Generate URL for a file by hash
     fn generate_url(&self, hash: &str, extension: &str) -> String;
 
     /// Calculate SHA256 hash of file bytes
     fn calculate_hash(&self, bytes: &[u8]) -> String {
-        use sha2::{Digest, Sha256};
-        let mut hasher = Sha256::new();
-        hasher.update(bytes);
-        hex::encode(hasher.finalize())
+        crate::hashing::hash_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_keys_fall_back_to_unprefixed_files() {
+        assert_eq!(
+            lookup_keys("abc", false),
+            vec![("abc".to_string(), TextureType::SKIN)]
+        );
+        assert_eq!(
+            lookup_keys("abc", true),
+            vec![
+                ("skin/abc".to_string(), TextureType::SKIN),
+                ("cape/abc".to_string(), TextureType::CAPE),
+                ("elytra/abc".to_string(), TextureType::ELYTRA),
+                ("abc".to_string(), TextureType::SKIN),
+            ]
+        );
     }
 }
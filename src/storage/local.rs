@@ -1,51 +1,378 @@
-use super::backend::StorageBackend;
-use crate::config::Config;
-use anyhow::Result;
+use super::backend::{public_file_url, Result, StorageBackend, StorageError};
+use crate::config::{Config, LocalCompression};
 use async_trait::async_trait;
-use std::path::PathBuf;
+use axum::body::Body;
+use std::path::{Path, PathBuf};
+use tokio_util::io::ReaderStream;
+
+/// Extension appended to files compressed with zstd
+const ZSTD_EXTENSION: &str = "zst";
 
 pub struct LocalStorage {
     storage_path: PathBuf,
     base_url: String,
+    public_base_url: Option<String>,
+    // Compression of newly written files; hashes are always of the uncompressed bytes
+    compression: LocalCompression,
+    type_prefix: bool,
 }
 
 impl LocalStorage {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config) -> Result<Self> {
         let storage_path = config
             .local_storage_path
-            .expect("Local storage path must be configured for Local storage");
+            .ok_or(StorageError::NotConfigured("LOCAL_STORAGE_PATH"))?;
 
-        LocalStorage {
+        Ok(LocalStorage {
             storage_path: PathBuf::from(storage_path),
             base_url: config.base_url,
+            public_base_url: config.storage_public_base_url,
+            compression: config.local_compress,
+            type_prefix: config.storage_type_prefix,
+        })
+    }
+
+    /// Paths a file may be stored at, with whether they are compressed
+    /// The configured form comes first; the other keeps files written before
+    /// LOCAL_COMPRESS was changed readable
+    fn candidate_paths(&self, file_name: &str) -> [(PathBuf, bool); 2] {
+        let raw = (self.storage_path.join(file_name), false);
+        let compressed = (
+            self.storage_path
+                .join(format!("{}.{}", file_name, ZSTD_EXTENSION)),
+            true,
+        );
+
+        match self.compression {
+            LocalCompression::None => [raw, compressed],
+            LocalCompression::Zstd => [compressed, raw],
         }
     }
+
+    /// Create the directory a file is written to, including its type prefix directory
+    async fn create_parent_dir(&self, file_name: &str) -> Result<()> {
+        let file_path = self.storage_path.join(file_name);
+        let dir = file_path.parent().unwrap_or(&self.storage_path);
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| {
+                StorageError::io(format!("Failed to create directory {}", dir.display()), e)
+            })
+    }
+}
+
+/// Decompress a file written with LOCAL_COMPRESS=zstd
+fn decompress(file_path: &Path, bytes: &[u8]) -> Result<Vec<u8>> {
+    zstd::decode_all(bytes).map_err(|e| {
+        StorageError::io(format!("Failed to decompress file {}", file_path.display()), e)
+    })
+}
+
+/// Map an IO error writing a stored file
+fn write_error(file_path: &Path, e: std::io::Error) -> StorageError {
+    StorageError::io(format!("Failed to write file {}", file_path.display()), e)
+}
+
+/// Map an IO error on a stored file, keeping missing files distinguishable
+fn read_error(file_name: String, file_path: &Path, e: std::io::Error) -> StorageError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        StorageError::NotFound(file_name)
+    } else {
+        StorageError::io(format!("Failed to read file {}", file_path.display()), e)
+    }
 }
 
 #[async_trait]
 impl StorageBackend for LocalStorage {
     async fn store_file(&self, bytes: Vec<u8>, hash: &str, extension: &str) -> Result<String> {
+        let file_name = format!("{}.{}", hash, extension);
         // Create directory if it doesn't exist
-        tokio::fs::create_dir_all(&self.storage_path).await?;
+        self.create_parent_dir(&file_name).await?;
+
+        match self.compression {
+            LocalCompression::None => {
+                let file_path = self.storage_path.join(&file_name);
+                tokio::fs::write(&file_path, bytes)
+                    .await
+                    .map_err(|e| write_error(&file_path, e))?
+            }
+            LocalCompression::Zstd => {
+                let file_path = self
+                    .storage_path
+                    .join(format!("{}.{}", file_name, ZSTD_EXTENSION));
+                let compressed =
+                    zstd::encode_all(bytes.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)
+                        .map_err(|e| write_error(&file_path, e))?;
+                tokio::fs::write(&file_path, compressed)
+                    .await
+                    .map_err(|e| write_error(&file_path, e))?
+            }
+        }
+
+        Ok(self.generate_url(hash, extension))
+    }
 
+    async fn store_file_from_path(&self, path: &Path, hash: &str, extension: &str) -> Result<String> {
         let file_name = format!("{}.{}", hash, extension);
-        let file_path = self.storage_path.join(&file_name);
+        self.create_parent_dir(&file_name).await?;
 
-        tokio::fs::write(&file_path, bytes).await?;
+        match self.compression {
+            LocalCompression::None => {
+                let file_path = self.storage_path.join(&file_name);
+                tokio::fs::copy(path, &file_path)
+                    .await
+                    .map_err(|e| write_error(&file_path, e))?;
+            }
+            LocalCompression::Zstd => {
+                // Compress from file to file, without holding the texture in memory
+                let source = path.to_path_buf();
+                let file_path = self
+                    .storage_path
+                    .join(format!("{}.{}", file_name, ZSTD_EXTENSION));
+                tokio::task::spawn_blocking(move || {
+                    let mut reader = std::fs::File::open(source)?;
+                    let writer = std::fs::File::create(&file_path)
+                        .map_err(|e| write_error(&file_path, e))?;
+                    zstd::stream::copy_encode(
+                        &mut reader,
+                        writer,
+                        zstd::DEFAULT_COMPRESSION_LEVEL,
+                    )
+                    .map_err(|e| write_error(&file_path, e))
+                })
+                .await
+                .map_err(anyhow::Error::from)??;
+            }
+        }
 
         Ok(self.generate_url(hash, extension))
     }
 
-    async fn get_file(&self, hash: &str, extension: &str) -> Result<Vec<u8>> {
+    async fn delete_file(&self, hash: &str, extension: &str) -> Result<()> {
         let file_name = format!("{}.{}", hash, extension);
-        let file_path = self.storage_path.join(&file_name);
 
-        tokio::fs::read(&file_path)
+        // Remove both forms, the file may have been written under another LOCAL_COMPRESS
+        for (file_path, _) in self.candidate_paths(&file_name) {
+            match tokio::fs::remove_file(&file_path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(StorageError::io(
+                        format!("Failed to delete file {}", file_path.display()),
+                        e,
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        tokio::fs::create_dir_all(&self.storage_path)
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to read file {}: {}", file_path.display(), e))
+            .map_err(|e| {
+                StorageError::io(
+                    format!("Cannot create storage directory {}", self.storage_path.display()),
+                    e,
+                )
+            })?;
+
+        // Write and remove a probe file to make sure the directory is writable
+        let probe_path = self.storage_path.join(".health_check");
+        tokio::fs::write(&probe_path, b"ok").await.map_err(|e| {
+            StorageError::io(
+                format!("Storage directory {} is not writable", self.storage_path.display()),
+                e,
+            )
+        })?;
+        tokio::fs::remove_file(&probe_path).await?;
+
+        Ok(())
     }
 
-    fn generate_url(&self, hash: &str, _extension: &str) -> String {
-        format!("{}/{}", self.base_url.trim_end_matches('/'), hash)
+    async fn exists(&self, hash: &str, extension: &str) -> Result<bool> {
+        for (file_path, _) in self.candidate_paths(&format!("{}.{}", hash, extension)) {
+            let exists = tokio::fs::try_exists(&file_path).await.map_err(|e| {
+                StorageError::io(format!("Failed to check file {}", file_path.display()), e)
+            })?;
+            if exists {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn get_file(&self, hash: &str, extension: &str) -> Result<Vec<u8>> {
+        let file_name = format!("{}.{}", hash, extension);
+
+        for (file_path, compressed) in self.candidate_paths(&file_name) {
+            match tokio::fs::read(&file_path).await {
+                Ok(bytes) if compressed => return decompress(&file_path, &bytes),
+                Ok(bytes) => return Ok(bytes),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(read_error(file_name, &file_path, e)),
+            }
+        }
+
+        Err(StorageError::NotFound(file_name))
+    }
+
+    async fn file_size(&self, hash: &str, extension: &str) -> Result<Option<u64>> {
+        let file_name = format!("{}.{}", hash, extension);
+
+        for (file_path, compressed) in self.candidate_paths(&file_name) {
+            if compressed {
+                // The size on disk is not the size served, decompress to find out
+                match tokio::fs::read(&file_path).await {
+                    Ok(bytes) => return Ok(Some(decompress(&file_path, &bytes)?.len() as u64)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(read_error(file_name, &file_path, e)),
+                }
+            }
+
+            match tokio::fs::metadata(&file_path).await {
+                Ok(metadata) => return Ok(Some(metadata.len())),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(read_error(file_name, &file_path, e)),
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn get_file_stream(&self, hash: &str, extension: &str) -> Result<Body> {
+        let file_name = format!("{}.{}", hash, extension);
+
+        for (file_path, compressed) in self.candidate_paths(&file_name) {
+            if compressed {
+                // Compressed files are small, decompress them in memory
+                match tokio::fs::read(&file_path).await {
+                    Ok(bytes) => return Ok(Body::from(decompress(&file_path, &bytes)?)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(read_error(file_name, &file_path, e)),
+                }
+            }
+
+            // Stream from disk instead of buffering the whole file
+            match tokio::fs::File::open(&file_path).await {
+                Ok(file) => return Ok(Body::from_stream(ReaderStream::new(file))),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(read_error(file_name, &file_path, e)),
+            }
+        }
+
+        Err(StorageError::NotFound(file_name))
+    }
+
+    fn type_prefix(&self) -> bool {
+        self.type_prefix
+    }
+
+    fn generate_url(&self, hash: &str, extension: &str) -> String {
+        match &self.public_base_url {
+            Some(public_base_url) => public_file_url(public_base_url, hash, extension),
+            // /files/{hash} looks the file up by hash alone, without its type prefix
+            None => {
+                let hash = hash.rsplit('/').next().unwrap_or(hash);
+                format!("{}/{}", self.base_url.trim_end_matches('/'), hash)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TextureType;
+
+    fn storage_in(storage_path: &Path, compression: LocalCompression) -> LocalStorage {
+        LocalStorage {
+            storage_path: storage_path.to_path_buf(),
+            base_url: "http://localhost".to_string(),
+            public_base_url: None,
+            compression,
+            type_prefix: false,
+        }
+    }
+
+    #[test]
+    fn test_public_base_url_overrides_file_urls() {
+        let mut storage = storage_in(Path::new("/tmp"), LocalCompression::None);
+        assert_eq!(storage.generate_url("abc", "png"), "http://localhost/abc");
+
+        storage.public_base_url = Some("https://cdn.example.com/skins/".to_string());
+        assert_eq!(
+            storage.generate_url("abc", "png"),
+            "https://cdn.example.com/skins/abc.png"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zstd_compression_round_trips_bytes() {
+        let storage_path =
+            std::env::temp_dir().join(format!("local-zstd-{}", uuid::Uuid::new_v4()));
+        let storage = storage_in(&storage_path, LocalCompression::Zstd);
+        let bytes: Vec<u8> = (0..4096u32).map(|i| (i % 7) as u8).collect();
+
+        storage.store_file(bytes.clone(), "abc", "png").await.unwrap();
+
+        assert!(storage_path.join("abc.png.zst").exists());
+        assert!(!storage_path.join("abc.png").exists());
+        assert_eq!(storage.get_file("abc", "png").await.unwrap(), bytes);
+        let body = storage.get_file_stream("abc", "png").await.unwrap();
+        let streamed = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(streamed.to_vec(), bytes);
+        // Reported size is of the uncompressed bytes
+        assert_eq!(storage.file_size("abc", "png").await.unwrap(), Some(4096));
+
+        // Still readable with compression turned off
+        let raw_storage = storage_in(&storage_path, LocalCompression::None);
+        assert!(raw_storage.exists("abc", "png").await.unwrap());
+        assert_eq!(raw_storage.get_file("abc", "png").await.unwrap(), bytes);
+
+        raw_storage.delete_file("abc", "png").await.unwrap();
+        assert!(!storage.exists("abc", "png").await.unwrap());
+        assert_eq!(storage.file_size("abc", "png").await.unwrap(), None);
+
+        tokio::fs::remove_dir_all(&storage_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_type_prefix_keeps_types_apart() {
+        let storage_path =
+            std::env::temp_dir().join(format!("local-prefix-{}", uuid::Uuid::new_v4()));
+        let mut storage = storage_in(&storage_path, LocalCompression::None);
+        storage.type_prefix = true;
+
+        let key = storage.texture_key("abc", TextureType::CAPE);
+        let url = storage.store_file(b"cape".to_vec(), &key, "png").await.unwrap();
+
+        assert!(storage_path.join("cape/abc.png").exists());
+        assert_eq!(url, "http://localhost/abc");
+        let (bytes, texture_type) = storage.get_file_any_type("abc").await.unwrap();
+        assert_eq!((bytes.as_slice(), texture_type), (&b"cape"[..], TextureType::CAPE));
+
+        tokio::fs::remove_dir_all(&storage_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_files_are_told_apart_from_read_failures() {
+        let storage_path =
+            std::env::temp_dir().join(format!("local-missing-{}", uuid::Uuid::new_v4()));
+        let storage = storage_in(&storage_path, LocalCompression::None);
+
+        let missing = storage.get_file("abc", "png").await.unwrap_err();
+        assert!(matches!(missing, StorageError::NotFound(_)));
+        let missing = storage.get_file_any_type("abc").await.unwrap_err();
+        assert!(matches!(missing, StorageError::NotFound(_)));
+
+        // A directory in place of the file cannot be read, but is not missing
+        tokio::fs::create_dir_all(storage_path.join("abc.png")).await.unwrap();
+        let unreadable = storage.get_file("abc", "png").await.unwrap_err();
+        assert!(matches!(unreadable, StorageError::Io { .. }));
+
+        tokio::fs::remove_dir_all(&storage_path).await.unwrap();
     }
 }
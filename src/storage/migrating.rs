@@ -0,0 +1,235 @@
+use super::backend::{Result, StorageBackend, StorageError};
+use async_trait::async_trait;
+use axum::body::Body;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Storage used while migrating between two backends (e.g. local disk to S3)
+/// Writes go to the primary backend; reads try the primary first and fall back
+/// to the secondary for files that have not been migrated yet.
+/// With `migrate_on_read`, files found only in the secondary are copied to the primary.
+pub struct MigratingStorage {
+    primary: Arc<dyn StorageBackend>,
+    secondary: Arc<dyn StorageBackend>,
+    migrate_on_read: bool,
+}
+
+impl MigratingStorage {
+    pub fn new(
+        primary: Arc<dyn StorageBackend>,
+        secondary: Arc<dyn StorageBackend>,
+        migrate_on_read: bool,
+    ) -> Self {
+        MigratingStorage {
+            primary,
+            secondary,
+            migrate_on_read,
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MigratingStorage {
+    async fn store_file(&self, bytes: Vec<u8>, hash: &str, extension: &str) -> Result<String> {
+        self.primary.store_file(bytes, hash, extension).await
+    }
+
+    async fn store_file_from_path(&self, path: &Path, hash: &str, extension: &str) -> Result<String> {
+        self.primary.store_file_from_path(path, hash, extension).await
+    }
+
+    async fn delete_file(&self, hash: &str, extension: &str) -> Result<()> {
+        // The file may still exist in either backend
+        let primary = self.primary.delete_file(hash, extension).await;
+        let secondary = self.secondary.delete_file(hash, extension).await;
+        primary.and(secondary)
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        self.primary
+            .health_check()
+            .await
+            .map_err(|e| anyhow::anyhow!("Primary storage: {}", e))?;
+        self.secondary
+            .health_check()
+            .await
+            .map_err(|e| anyhow::anyhow!("Secondary storage: {}", e).into())
+    }
+
+    async fn exists(&self, hash: &str, extension: &str) -> Result<bool> {
+        // Unmigrated files are still served from the secondary
+        Ok(self.primary.exists(hash, extension).await?
+            || self.secondary.exists(hash, extension).await?)
+    }
+
+    async fn get_file(&self, hash: &str, extension: &str) -> Result<Vec<u8>> {
+        let primary_error = match self.primary.get_file(hash, extension).await {
+            Ok(bytes) => return Ok(bytes),
+            Err(e) => e,
+        };
+
+        let bytes = match self.secondary.get_file(hash, extension).await {
+            Ok(bytes) => bytes,
+            Err(StorageError::NotFound(_))
+                if matches!(primary_error, StorageError::NotFound(_)) =>
+            {
+                return Err(StorageError::NotFound(format!("{}.{}", hash, extension)))
+            }
+            Err(e) => {
+                return Err(StorageError::Backend(anyhow::anyhow!(
+                    "Failed to read {}.{} from primary ({}) and secondary ({}) storage",
+                    hash,
+                    extension,
+                    primary_error,
+                    e
+                )))
+            }
+        };
+
+        tracing::debug!("Read {}.{} from secondary storage", hash, extension);
+
+        if self.migrate_on_read {
+            // Best effort: the read succeeds even if the copy fails
+            match self.primary.store_file(bytes.clone(), hash, extension).await {
+                Ok(_) => tracing::info!("Migrated {}.{} to primary storage", hash, extension),
+                Err(e) => tracing::warn!(
+                    "Failed to migrate {}.{} to primary storage: {}",
+                    hash,
+                    extension,
+                    e
+                ),
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    async fn file_size(&self, hash: &str, extension: &str) -> Result<Option<u64>> {
+        // Only metadata is read, so nothing is migrated here
+        match self.primary.file_size(hash, extension).await? {
+            Some(size) => Ok(Some(size)),
+            None => self.secondary.file_size(hash, extension).await,
+        }
+    }
+
+    async fn get_file_stream(&self, hash: &str, extension: &str) -> Result<Body> {
+        match self.primary.get_file_stream(hash, extension).await {
+            Ok(body) => Ok(body),
+            // Files still in the secondary are read whole, so they can be migrated
+            Err(StorageError::NotFound(_)) => {
+                Ok(Body::from(self.get_file(hash, extension).await?))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn type_prefix(&self) -> bool {
+        // Keys are the primary's, where every file ends up
+        self.primary.type_prefix()
+    }
+
+    fn generate_url(&self, hash: &str, extension: &str) -> String {
+        // New URLs point at the primary, where every file ends up
+        self.primary.generate_url(hash, extension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryStorage {
+        files: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl MemoryStorage {
+        fn contains(&self, key: &str) -> bool {
+            self.files.lock().unwrap().contains_key(key)
+        }
+    }
+
+    #[async_trait]
+    impl StorageBackend for MemoryStorage {
+        async fn store_file(&self, bytes: Vec<u8>, hash: &str, extension: &str) -> Result<String> {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(format!("{}.{}", hash, extension), bytes);
+            Ok(self.generate_url(hash, extension))
+        }
+
+        async fn delete_file(&self, hash: &str, extension: &str) -> Result<()> {
+            self.files
+                .lock()
+                .unwrap()
+                .remove(&format!("{}.{}", hash, extension));
+            Ok(())
+        }
+
+        async fn health_check(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn exists(&self, hash: &str, extension: &str) -> Result<bool> {
+            Ok(self.contains(&format!("{}.{}", hash, extension)))
+        }
+
+        async fn get_file(&self, hash: &str, extension: &str) -> Result<Vec<u8>> {
+            self.files
+                .lock()
+                .unwrap()
+                .get(&format!("{}.{}", hash, extension))
+                .cloned()
+                .ok_or_else(|| StorageError::NotFound(format!("{}.{}", hash, extension)))
+        }
+
+        fn generate_url(&self, hash: &str, _extension: &str) -> String {
+            format!("http://localhost/files/{}", hash)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrating_storage_falls_back_and_migrates_on_read() {
+        let primary = Arc::new(MemoryStorage::default());
+        let secondary = Arc::new(MemoryStorage::default());
+        secondary
+            .store_file(b"old".to_vec(), "old", "png")
+            .await
+            .unwrap();
+
+        let storage = MigratingStorage::new(primary.clone(), secondary.clone(), true);
+
+        storage.store_file(b"new".to_vec(), "new", "png").await.unwrap();
+        assert!(primary.contains("new.png"));
+        assert!(!secondary.contains("new.png"));
+
+        assert_eq!(storage.get_file("old", "png").await.unwrap(), b"old");
+        assert!(primary.contains("old.png"));
+
+        let missing = storage.get_file("missing", "png").await.unwrap_err();
+        assert!(matches!(missing, StorageError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn test_migrating_storage_exists_in_either_backend() {
+        let primary = Arc::new(MemoryStorage::default());
+        let secondary = Arc::new(MemoryStorage::default());
+        primary.store_file(b"new".to_vec(), "new", "png").await.unwrap();
+        secondary
+            .store_file(b"old".to_vec(), "old", "png")
+            .await
+            .unwrap();
+
+        let storage = MigratingStorage::new(primary, secondary, false);
+
+        assert!(storage.exists("new", "png").await.unwrap());
+        assert!(storage.exists_any_type("old").await.unwrap());
+        assert!(!storage.exists_any_type("missing").await.unwrap());
+
+        assert_eq!(storage.file_size("old", "png").await.unwrap(), Some(3));
+        assert_eq!(storage.file_size("missing", "png").await.unwrap(), None);
+    }
+}
@@ -1,18 +1,49 @@
 pub mod backend;
 pub mod local;
+pub mod migrating;
 pub mod s3;
 
-pub use backend::StorageBackend;
+pub use backend::{StorageBackend, StorageError};
 pub use local::LocalStorage;
+pub use migrating::MigratingStorage;
 pub use s3::S3Storage;
 
-use crate::config::Config;
+use crate::config::{Config, StorageType};
 use std::sync::Arc;
 
 /// Factory function to create the appropriate storage backend
-pub fn create_storage(config: Config) -> Arc<dyn StorageBackend> {
-    match config.storage_type {
-        crate::config::StorageType::Local => Arc::new(LocalStorage::new(config)),
-        crate::config::StorageType::S3 => Arc::new(S3Storage::new(config)),
-    }
+pub fn create_storage(config: Config) -> Result<Arc<dyn StorageBackend>, StorageError> {
+    create_storage_by_type(&config.storage_type, &config)
+}
+
+/// Create a storage backend for a specific storage type
+fn create_storage_by_type(
+    storage_type: &StorageType,
+    config: &Config,
+) -> Result<Arc<dyn StorageBackend>, StorageError> {
+    Ok(match storage_type {
+        StorageType::Local => Arc::new(LocalStorage::new(config.clone())?),
+        StorageType::S3 => Arc::new(S3Storage::new(config.clone())?),
+        StorageType::Migrating => {
+            let primary = config
+                .storage_primary
+                .as_ref()
+                .ok_or(StorageError::NotConfigured("STORAGE_PRIMARY"))?;
+            let secondary = config
+                .storage_secondary
+                .as_ref()
+                .ok_or(StorageError::NotConfigured("STORAGE_SECONDARY"))?;
+            tracing::info!(
+                "Migrating storage: writing to {:?}, falling back to {:?}",
+                primary,
+                secondary
+            );
+
+            Arc::new(MigratingStorage::new(
+                create_storage_by_type(primary, config)?,
+                create_storage_by_type(secondary, config)?,
+                config.storage_migrate_on_read,
+            ))
+        }
+    })
 }
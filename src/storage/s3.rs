@@ -1,13 +1,21 @@
-use super::backend::StorageBackend;
+use super::backend::{public_file_url, Result, StorageBackend, StorageError};
 use crate::config::Config;
-use anyhow::Result;
+use crate::models::content_type_for_extension;
+use crate::retry::RetryPolicy;
 use async_trait::async_trait;
+use std::path::Path;
 
 pub struct S3Storage {
     bucket: String,
     region: String,
     endpoint: Option<String>,
     credentials: Option<S3Credentials>,
+    object_acl: Option<String>,
+    cache_control: Option<String>,
+    storage_class: Option<String>,
+    public_base_url: Option<String>,
+    type_prefix: bool,
+    retry: RetryPolicy,
 }
 
 struct S3Credentials {
@@ -16,13 +24,19 @@ struct S3Credentials {
 }
 
 impl S3Storage {
-    pub fn new(config: Config) -> Self {
-        S3Storage {
+    pub fn new(config: Config) -> Result<Self> {
+        Ok(S3Storage {
+            retry: RetryPolicy::from_config(&config),
             bucket: config
                 .s3_bucket
-                .expect("S3 bucket must be configured for S3 storage"),
+                .ok_or(StorageError::NotConfigured("S3_BUCKET"))?,
             region: config.s3_region.unwrap_or_else(|| "us-east-1".to_string()),
             endpoint: config.s3_endpoint,
+            object_acl: config.s3_object_acl,
+            cache_control: config.s3_cache_control,
+            storage_class: config.s3_storage_class,
+            public_base_url: config.storage_public_base_url,
+            type_prefix: config.storage_type_prefix,
             credentials: match (config.s3_access_key, config.s3_secret_key) {
                 (Some(access), Some(secret)) => Some(S3Credentials {
                     access_key: access,
@@ -30,7 +44,7 @@ impl S3Storage {
                 }),
                 _ => None,
             },
-        }
+        })
     }
 
     /// Get or create AWS S3 client
@@ -42,7 +56,9 @@ impl S3Storage {
             use aws_sdk_s3::Config;
 
             let region = Region::new(self.region.clone());
-            let mut builder = Builder::new().region(region.clone());
+            let mut builder = Builder::new()
+                .behavior_version(BehaviorVersion::latest())
+                .region(region.clone());
 
             // Add credentials if provided
             if let Some(creds) = &self.credentials {
@@ -73,11 +89,12 @@ impl S3Storage {
 
         #[cfg(not(feature = "s3"))]
         {
-            Err(anyhow::anyhow!("S3 feature not enabled"))
+            Err(StorageError::FeatureDisabled("S3"))
         }
     }
 
     /// Get file path in S3 bucket
+    /// Texture files have their type prefix in the hash, see `texture_key`
     fn get_file_path(&self, hash: &str, extension: &str) -> String {
         format!("{}.{}", hash, extension)
     }
@@ -97,31 +114,172 @@ impl S3Storage {
     }
 }
 
+/// Whether an S3 request failed in a way worth retrying
+/// (network/timeout failures, throttling and 5xx responses, but not other 4xx)
+#[cfg(feature = "s3")]
+fn is_transient_s3_error<E>(
+    error: &aws_sdk_s3::error::SdkError<E, aws_sdk_s3::config::http::HttpResponse>,
+) -> bool {
+    use aws_sdk_s3::error::SdkError;
+
+    match error {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            true
+        }
+        SdkError::ServiceError(e) => crate::retry::is_transient_status(e.raw().status().as_u16()),
+        _ => false,
+    }
+}
+
 #[async_trait]
 impl StorageBackend for S3Storage {
     async fn store_file(&self, bytes: Vec<u8>, hash: &str, extension: &str) -> Result<String> {
         #[cfg(feature = "s3")]
         {
             use aws_sdk_s3::primitives::ByteStream;
+            use aws_sdk_s3::types::{ObjectCannedAcl, StorageClass};
+
+            let client = self.get_client().await?;
+            let path = self.get_file_path(hash, extension);
+
+            // Keys are content-addressed, so repeating a put is idempotent
+            self.retry
+                .retry("S3 put", is_transient_s3_error, || {
+                    client
+                        .put_object()
+                        .bucket(&self.bucket)
+                        .key(&path)
+                        .body(ByteStream::from(bytes.clone()))
+                        .content_type(content_type_for_extension(extension))
+                        .set_acl(self.object_acl.as_deref().map(ObjectCannedAcl::from))
+                        .set_cache_control(self.cache_control.clone())
+                        .set_storage_class(self.storage_class.as_deref().map(StorageClass::from))
+                        .send()
+                })
+                .await
+                .map_err(anyhow::Error::from)?;
+
+            Ok(self.generate_url(hash, extension))
+        }
+
+        #[cfg(not(feature = "s3"))]
+        {
+            Err(StorageError::FeatureDisabled("S3"))
+        }
+    }
+
+    async fn store_file_from_path(&self, path: &Path, hash: &str, extension: &str) -> Result<String> {
+        #[cfg(feature = "s3")]
+        {
+            use aws_sdk_s3::error::SdkError;
+            use aws_sdk_s3::primitives::ByteStream;
+            use aws_sdk_s3::types::{ObjectCannedAcl, StorageClass};
 
+            let client = self.get_client().await?;
+            let key = self.get_file_path(hash, extension);
+
+            // The body is streamed from the file, reopened for every attempt
+            self.retry
+                .retry("S3 put", is_transient_s3_error, || async {
+                    let body = ByteStream::from_path(path)
+                        .await
+                        .map_err(SdkError::construction_failure)?;
+                    client
+                        .put_object()
+                        .bucket(&self.bucket)
+                        .key(&key)
+                        .body(body)
+                        .content_type(content_type_for_extension(extension))
+                        .set_acl(self.object_acl.as_deref().map(ObjectCannedAcl::from))
+                        .set_cache_control(self.cache_control.clone())
+                        .set_storage_class(self.storage_class.as_deref().map(StorageClass::from))
+                        .send()
+                        .await
+                })
+                .await
+                .map_err(anyhow::Error::from)?;
+
+            Ok(self.generate_url(hash, extension))
+        }
+
+        #[cfg(not(feature = "s3"))]
+        {
+            Err(StorageError::FeatureDisabled("S3"))
+        }
+    }
+
+    async fn delete_file(&self, hash: &str, extension: &str) -> Result<()> {
+        #[cfg(feature = "s3")]
+        {
             let client = self.get_client().await?;
             let path = self.get_file_path(hash, extension);
 
-            client
-                .put_object()
-                .bucket(&self.bucket)
-                .key(&path)
-                .body(ByteStream::from(bytes))
-                .content_type("image/png")
-                .send()
-                .await?;
+            // S3 reports success for keys that do not exist
+            self.retry
+                .retry("S3 delete", is_transient_s3_error, || {
+                    client.delete_object().bucket(&self.bucket).key(&path).send()
+                })
+                .await
+                .map_err(anyhow::Error::from)?;
 
-            Ok(self.generate_s3_url(&path))
+            Ok(())
         }
 
         #[cfg(not(feature = "s3"))]
         {
-            Err(anyhow::anyhow!("S3 feature not enabled"))
+            Err(StorageError::FeatureDisabled("S3"))
+        }
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        #[cfg(feature = "s3")]
+        {
+            let client = self.get_client().await?;
+
+            self.retry
+                .retry("S3 head bucket", is_transient_s3_error, || {
+                    client.head_bucket().bucket(&self.bucket).send()
+                })
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Cannot access S3 bucket {}: {}",
+                        self.bucket,
+                        aws_sdk_s3::error::DisplayErrorContext(e)
+                    )
+                })?;
+
+            Ok(())
+        }
+
+        #[cfg(not(feature = "s3"))]
+        {
+            Err(StorageError::FeatureDisabled("S3"))
+        }
+    }
+
+    async fn exists(&self, hash: &str, extension: &str) -> Result<bool> {
+        #[cfg(feature = "s3")]
+        {
+            let client = self.get_client().await?;
+            let path = self.get_file_path(hash, extension);
+
+            match self
+                .retry
+                .retry("S3 head", is_transient_s3_error, || {
+                    client.head_object().bucket(&self.bucket).key(&path).send()
+                })
+                .await
+            {
+                Ok(_) => Ok(true),
+                Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(false),
+                Err(e) => Err(anyhow::Error::from(e).into()),
+            }
+        }
+
+        #[cfg(not(feature = "s3"))]
+        {
+            Err(StorageError::FeatureDisabled("S3"))
         }
     }
 
@@ -131,24 +289,75 @@ impl StorageBackend for S3Storage {
             let client = self.get_client().await?;
             let path = self.get_file_path(hash, extension);
 
-            let response = client
-                .get_object()
-                .bucket(&self.bucket)
-                .key(&path)
-                .send()
-                .await?;
+            let response = self
+                .retry
+                .retry("S3 get", is_transient_s3_error, || {
+                    client.get_object().bucket(&self.bucket).key(&path).send()
+                })
+                .await
+                .map_err(|e| {
+                    if e.as_service_error().is_some_and(|e| e.is_no_such_key()) {
+                        StorageError::NotFound(path.clone())
+                    } else {
+                        anyhow::Error::from(e).into()
+                    }
+                })?;
 
-            let bytes = response.body.collect().await?.into_bytes();
+            let bytes = response
+                .body
+                .collect()
+                .await
+                .map_err(anyhow::Error::from)?
+                .into_bytes();
             Ok(bytes.to_vec())
         }
 
         #[cfg(not(feature = "s3"))]
         {
-            Err(anyhow::anyhow!("S3 feature not enabled"))
+            Err(StorageError::FeatureDisabled("S3"))
+        }
+    }
+
+    async fn file_size(&self, hash: &str, extension: &str) -> Result<Option<u64>> {
+        #[cfg(feature = "s3")]
+        {
+            let client = self.get_client().await?;
+            let path = self.get_file_path(hash, extension);
+
+            let response = match self
+                .retry
+                .retry("S3 head", is_transient_s3_error, || {
+                    client.head_object().bucket(&self.bucket).key(&path).send()
+                })
+                .await
+            {
+                Ok(response) => response,
+                Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(anyhow::Error::from(e).into()),
+            };
+
+            let size = response
+                .content_length()
+                .ok_or_else(|| anyhow::anyhow!("S3 did not report the size of {}", path))?;
+            Ok(Some(size as u64))
+        }
+
+        #[cfg(not(feature = "s3"))]
+        {
+            Err(StorageError::FeatureDisabled("S3"))
         }
     }
 
+    fn type_prefix(&self) -> bool {
+        self.type_prefix
+    }
+
     fn generate_url(&self, hash: &str, extension: &str) -> String {
+        if let Some(public_base_url) = &self.public_base_url {
+            return public_file_url(public_base_url, hash, extension);
+        }
         let path = self.get_file_path(hash, extension);
         self.generate_s3_url(&path)
     }
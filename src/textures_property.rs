@@ -0,0 +1,170 @@
+use crate::config::Config;
+use crate::retrieval::backend::RetrievedTexture;
+use anyhow::Result;
+use base64::Engine;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// Decoded `textures` property value, in the format of the Mojang session server
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TexturesPayload<'a> {
+    timestamp: i64,
+    profile_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile_name: Option<&'a str>,
+    textures: BTreeMap<&'a str, TexturePayload<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct TexturePayload<'a> {
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<MetadataPayload<'a>>,
+}
+
+/// Texture metadata as the game client reads it: string values, the skin model only
+#[derive(Debug, Serialize)]
+struct MetadataPayload<'a> {
+    model: &'a str,
+}
+
+/// Encode textures as the base64 value of a Mojang `textures` property
+pub fn encode_textures_property(
+    user_uuid: Uuid,
+    username: Option<&str>,
+    textures: &HashMap<String, RetrievedTexture>,
+) -> Result<String> {
+    let payload = TexturesPayload {
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        profile_id: user_uuid.simple().to_string(),
+        profile_name: username,
+        textures: textures
+            .iter()
+            .map(|(texture_type, texture)| {
+                (
+                    texture_type.as_str(),
+                    TexturePayload {
+                        url: &texture.url,
+                        metadata: texture
+                            .metadata
+                            .as_ref()
+                            .and_then(|metadata| metadata.model.as_deref())
+                            .map(|model| MetadataPayload { model }),
+                    },
+                )
+            })
+            .collect(),
+    };
+
+    let json = serde_json::to_vec(&payload)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(json))
+}
+
+/// Signs `textures` property values the way the Mojang session server does
+/// (SHA1withRSA, base64-encoded), so clients can verify them with our public key
+pub struct TexturesSigner {
+    key: RsaPrivateKey,
+}
+
+impl TexturesSigner {
+    /// Load the signing key from TEXTURES_SIGNING_KEY
+    /// Returns None if no key is configured (properties are then sent unsigned)
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        config
+            .textures_signing_key
+            .as_deref()
+            .map(Self::from_base64_pkcs8)
+            .transpose()
+    }
+
+    /// Parse a base64-encoded PKCS#8 RSA private key (a PEM body without the armor)
+    fn from_base64_pkcs8(private_key: &str) -> Result<Self> {
+        let body: String = private_key.split_whitespace().collect();
+        let der = base64::engine::general_purpose::STANDARD
+            .decode(body)
+            .map_err(|e| anyhow::anyhow!("Failed to decode TEXTURES_SIGNING_KEY: {}", e))?;
+        let key = RsaPrivateKey::from_pkcs8_der(&der)
+            .map_err(|e| anyhow::anyhow!("Failed to parse TEXTURES_SIGNING_KEY: {}", e))?;
+        Ok(TexturesSigner { key })
+    }
+
+    /// Sign a property value, returning the base64 signature
+    pub fn sign(&self, value: &str) -> Result<String> {
+        let digest = Sha1::digest(value.as_bytes());
+        let signature = self
+            .key
+            .sign(Pkcs1v15Sign::new::<Sha1>(), &digest)
+            .map_err(|e| anyhow::anyhow!("Failed to sign textures property: {}", e))?;
+        Ok(base64::engine::general_purpose::STANDARD.encode(signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TextureMetadata;
+
+    #[test]
+    fn test_encode_textures_property_matches_session_server_shape() {
+        let user_uuid = Uuid::new_v4();
+        let mut textures = HashMap::new();
+        textures.insert(
+            "SKIN".to_string(),
+            RetrievedTexture {
+                url: "http://localhost/files/abc".to_string(),
+                hash: "abc".to_string(),
+                metadata: Some(TextureMetadata {
+                    model: Some("slim".to_string()),
+                    ..Default::default()
+                }),
+                source: "storage".to_string(),
+            },
+        );
+
+        let value = encode_textures_property(user_uuid, Some("Steve"), &textures).unwrap();
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(value)
+            .unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(&decoded).unwrap();
+
+        assert_eq!(payload["profileId"], user_uuid.simple().to_string());
+        assert_eq!(payload["profileName"], "Steve");
+        assert!(payload["timestamp"].is_i64());
+        assert_eq!(
+            payload["textures"]["SKIN"]["url"],
+            "http://localhost/files/abc"
+        );
+        assert_eq!(payload["textures"]["SKIN"]["metadata"]["model"], "slim");
+        assert!(payload["textures"].get("CAPE").is_none());
+    }
+
+    #[test]
+    fn test_encode_textures_property_keeps_only_the_skin_model() {
+        let mut textures = HashMap::new();
+        textures.insert(
+            "CAPE".to_string(),
+            RetrievedTexture {
+                url: "http://localhost/files/def".to_string(),
+                hash: "def".to_string(),
+                metadata: Some(TextureMetadata {
+                    cape_as_elytra: Some(true),
+                    ..Default::default()
+                }),
+                source: "storage".to_string(),
+            },
+        );
+
+        let value = encode_textures_property(Uuid::new_v4(), None, &textures).unwrap();
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(value)
+            .unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(&decoded).unwrap();
+
+        assert!(payload["textures"]["CAPE"].get("metadata").is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::config::Config;
+use anyhow::{anyhow, Result};
+use axum::http::uri::Authority;
+use axum::http::{header, HeaderMap, StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::net::TcpListener;
+
+/// Load the certificate chain and key from TLS_CERT_PATH and TLS_KEY_PATH
+/// Returns None if TLS is not configured, and fails if either file cannot be loaded
+pub async fn load_config(config: &Config) -> Result<Option<RustlsConfig>> {
+    let (cert_path, key_path) = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        _ => return Ok(None),
+    };
+
+    // Several rustls crypto providers are compiled in through dependencies, so rustls
+    // cannot pick one by itself; fails harmlessly if one was installed already
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    RustlsConfig::from_pem_file(cert_path, key_path)
+        .await
+        .map(Some)
+        .map_err(|e| {
+            anyhow!(
+                "Failed to load TLS certificate {} and key {}: {}",
+                cert_path,
+                key_path,
+                e
+            )
+        })
+}
+
+/// Serve plain HTTP on `listener`, redirecting every request to the same URL over HTTPS
+/// on `https_port` (HTTP_REDIRECT_PORT)
+pub async fn serve_https_redirect(listener: TcpListener, https_port: u16) -> std::io::Result<()> {
+    let app = Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        redirect_to_https(&headers, &uri, https_port)
+    });
+    axum::serve(listener, app).await
+}
+
+fn redirect_to_https(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok());
+
+    match host {
+        Some(host) => Redirect::permanent(&https_url(host.host(), https_port, uri)).into_response(),
+        None => (StatusCode::BAD_REQUEST, "Missing or invalid Host header").into_response(),
+    }
+}
+
+/// HTTPS URL of a request, on the given port
+fn https_url(host: &str, https_port: u16, uri: &Uri) -> String {
+    let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    if https_port == 443 {
+        format!("https://{}{}", host, path)
+    } else {
+        format!("https://{}:{}{}", host, https_port, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_url_keeps_path_and_replaces_port() {
+        let uri: Uri = "/get/abc?debug=true".parse().unwrap();
+        assert_eq!(
+            https_url("skins.example.com", 443, &uri),
+            "https://skins.example.com/get/abc?debug=true"
+        );
+        assert_eq!(
+            https_url("[::1]", 8443, &uri),
+            "https://[::1]:8443/get/abc?debug=true"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use crate::hashing::Hasher;
+use crate::models::TextureType;
+use crate::storage::StorageBackend;
+use anyhow::Result;
+use axum::extract::multipart::{Field, MultipartError};
+use std::borrow::Cow;
+use std::path::Path;
+use tempfile::TempPath;
+use tokio::io::AsyncWriteExt;
+
+/// Number of leading bytes kept for format checks (PNG signature and IHDR)
+const HEAD_LEN: usize = 64;
+
+/// Texture file received in an upload, with its SHA256 hash
+/// Either held in memory or, with STREAM_UPLOADS_TO_DISK, spilled to a temporary file
+/// that is deleted when the upload is dropped
+pub struct UploadedFile {
+    hash: String,
+    size: usize,
+    content: Content,
+    /// Filename the client sent with the multipart field
+    file_name: Option<String>,
+}
+
+enum Content {
+    Memory(Vec<u8>),
+    Disk {
+        path: TempPath,
+        // First bytes of the file, so format checks do not need to read it back
+        head: Vec<u8>,
+    },
+}
+
+/// Why a file field could not be spooled
+#[derive(Debug, thiserror::Error)]
+pub enum SpoolError {
+    #[error("Failed to read file: {0}")]
+    Read(#[from] MultipartError),
+    #[error("File exceeds maximum allowed size of {0} bytes")]
+    TooLarge(usize),
+    #[error("Failed to buffer file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl UploadedFile {
+    /// Wrap a file read fully into memory
+    pub fn in_memory(bytes: Vec<u8>, hash: String) -> Self {
+        UploadedFile {
+            hash,
+            size: bytes.len(),
+            content: Content::Memory(bytes),
+            file_name: None,
+        }
+    }
+
+    /// Read a multipart field chunk by chunk, hashing as it goes
+    /// Files up to `memory_limit` bytes stay in memory; larger ones are moved to a temporary
+    /// file in `temp_dir` as soon as they cross it. Reading stops with `TooLarge` once the
+    /// file exceeds `max_size`.
+    pub async fn spool(
+        mut field: Field<'_>,
+        memory_limit: usize,
+        max_size: usize,
+        temp_dir: &Path,
+    ) -> Result<Self, SpoolError> {
+        let mut hasher = Hasher::new();
+        let mut buffer = Vec::new();
+        let mut spilled: Option<(tokio::fs::File, TempPath)> = None;
+        let mut head = Vec::new();
+        let mut len = 0;
+
+        while let Some(chunk) = field.chunk().await? {
+            len += chunk.len();
+            if len > max_size {
+                return Err(SpoolError::TooLarge(max_size));
+            }
+            hasher.update(&chunk);
+
+            if let Some((file, _)) = &mut spilled {
+                file.write_all(&chunk).await?;
+                continue;
+            }
+
+            buffer.extend_from_slice(&chunk);
+            if buffer.len() > memory_limit {
+                let (file, path) = tempfile::Builder::new()
+                    .prefix("upload-")
+                    .tempfile_in(temp_dir)?
+                    .into_parts();
+                let mut file = tokio::fs::File::from_std(file);
+                file.write_all(&buffer).await?;
+                head = buffer[..HEAD_LEN.min(buffer.len())].to_vec();
+                buffer = Vec::new();
+                spilled = Some((file, path));
+            }
+        }
+
+        let hash = hasher.finalize();
+        let content = match spilled {
+            Some((mut file, path)) => {
+                file.flush().await?;
+                Content::Disk { path, head }
+            }
+            None => Content::Memory(buffer),
+        };
+
+        Ok(UploadedFile {
+            hash,
+            size: len,
+            content,
+            file_name: None,
+        })
+    }
+
+    /// Set the filename the client sent
+    pub fn with_file_name(mut self, file_name: Option<String>) -> Self {
+        self.file_name = file_name;
+        self
+    }
+
+    /// SHA256 hash of the file, hex encoded
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Filename the client sent, if any
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// Leading bytes of the file, at least the PNG signature and IHDR chunk if present
+    pub fn head(&self) -> &[u8] {
+        match &self.content {
+            Content::Memory(bytes) => bytes,
+            Content::Disk { head, .. } => head,
+        }
+    }
+
+    /// Whole file contents, read back from disk if the file was spilled
+    pub async fn bytes(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        match &self.content {
+            Content::Memory(bytes) => Ok(Cow::Borrowed(bytes)),
+            Content::Disk { path, .. } => tokio::fs::read(path).await.map(Cow::Owned),
+        }
+    }
+
+    /// Store the file as a texture of the given type in a storage backend and return its URL
+    /// Spilled files are handed over by path, so backends can store them without
+    /// reading them into memory
+    pub async fn store(
+        self,
+        storage: &dyn StorageBackend,
+        texture_type: TextureType,
+    ) -> Result<String> {
+        let key = storage.texture_key(&self.hash, texture_type);
+        self.store_as(storage, &key, texture_type.file_extension()).await
+    }
+
+    /// Store the file under its hash with an extension of its own, e.g. a resource pack
+    pub async fn store_with_extension(
+        self,
+        storage: &dyn StorageBackend,
+        extension: &str,
+    ) -> Result<String> {
+        let key = self.hash.clone();
+        self.store_as(storage, &key, extension).await
+    }
+
+    async fn store_as(
+        self,
+        storage: &dyn StorageBackend,
+        key: &str,
+        extension: &str,
+    ) -> Result<String> {
+        let url = match self.content {
+            Content::Memory(bytes) => storage.store_file(bytes, key, extension).await?,
+            Content::Disk { path, .. } => {
+                storage
+                    .store_file_from_path(&path, key, extension)
+                    .await?
+            }
+        };
+        Ok(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{FromRequest, Multipart, Request};
+
+    // Multipart request with a single "file" field
+    async fn multipart_with_file(contents: &[u8]) -> Multipart {
+        let mut body = b"--XX\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\n".to_vec();
+        body.extend_from_slice(contents);
+        body.extend_from_slice(b"\r\n--XX--\r\n");
+        let request = Request::builder()
+            .header("content-type", "multipart/form-data; boundary=XX")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        Multipart::from_request(request, &()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_spool_spills_large_files_and_hashes_them() {
+        let temp_dir = std::env::temp_dir();
+        let contents = vec![7u8; 1000];
+        let expected_hash = crate::hashing::hash_bytes(&contents);
+
+        let mut multipart = multipart_with_file(&contents).await;
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let small = UploadedFile::spool(field, 4096, 4096, &temp_dir).await.unwrap();
+        assert!(matches!(small.content, Content::Memory(_)));
+        assert_eq!(small.hash(), expected_hash);
+
+        let mut multipart = multipart_with_file(&contents).await;
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let spilled = UploadedFile::spool(field, 100, 4096, &temp_dir).await.unwrap();
+        let Content::Disk { path, .. } = &spilled.content else {
+            panic!("expected the upload to be spilled to disk");
+        };
+        let path = path.to_path_buf();
+        assert_eq!(spilled.hash(), expected_hash);
+        assert_eq!(spilled.head(), &contents[..HEAD_LEN]);
+        assert_eq!(spilled.bytes().await.unwrap().as_ref(), contents.as_slice());
+        drop(spilled);
+        assert!(!path.exists());
+
+        let mut multipart = multipart_with_file(&contents).await;
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let too_large = UploadedFile::spool(field, 100, 999, &temp_dir).await;
+        assert!(matches!(too_large, Err(SpoolError::TooLarge(999))));
+    }
+}